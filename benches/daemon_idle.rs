@@ -0,0 +1,100 @@
+//! What an idle daemon costs when it re-reads the store every poll
+//! interval, and when it watches the store instead. Run with
+//! `cargo bench --bench daemon_idle`.
+
+// CPU time comes from getrusage, which only Unix has
+#[cfg(unix)]
+mod idle {
+    use reminder_cli::daemon::run_daemon_until;
+    use reminder_cli::notifier::NotifierRegistry;
+    use reminder_cli::paths::{self, DATA_DIR_ENV};
+    use reminder_cli::reminder::Reminder;
+    use reminder_cli::storage::{ReminderStore, Storage};
+    use std::collections::HashSet;
+    use std::fs;
+    use std::path::Path;
+    use std::time::{Duration, Instant};
+
+    const REMINDERS: usize = 5_000;
+    const IDLE: Duration = Duration::from_secs(10);
+    const POLL_INTERVAL_SECS: u64 = 1;
+
+    /// User plus system CPU time of the whole process
+    fn cpu_time() -> Duration {
+        let mut usage: libc::rusage = unsafe { std::mem::zeroed() };
+        unsafe { libc::getrusage(libc::RUSAGE_SELF, &mut usage) };
+        let micros = |t: libc::timeval| t.tv_sec as u64 * 1_000_000 + t.tv_usec as u64;
+        Duration::from_micros(micros(usage.ru_utime) + micros(usage.ru_stime))
+    }
+
+    /// Bytes the process has read through system calls, where the platform
+    /// reports it
+    fn bytes_read() -> Option<u64> {
+        let io = fs::read_to_string("/proc/self/io").ok()?;
+        io.lines()
+            .find_map(|line| line.strip_prefix("rchar: "))
+            .and_then(|n| n.trim().parse().ok())
+    }
+
+    fn idle(dir: &Path, watch: bool) -> (Duration, Option<u64>) {
+        fs::create_dir_all(dir).unwrap();
+        // Keep the config, heartbeat and log of each run in its own directory
+        std::env::set_var(DATA_DIR_ENV, dir);
+        std::env::set_var("XDG_CONFIG_HOME", dir);
+        std::env::set_var("XDG_RUNTIME_DIR", dir);
+        fs::write(
+            paths::config_file().unwrap(),
+            format!(
+                "[daemon]\nwatch = {}\npoll_interval_secs = {}\n",
+                watch, POLL_INTERVAL_SECS
+            ),
+        )
+        .unwrap();
+
+        let storage = Storage::with_path(dir.join("reminders.json"));
+        let tomorrow = chrono::Local::now() + chrono::Duration::days(1);
+        let reminders: Vec<Reminder> = (0..REMINDERS)
+            .map(|i| {
+                Reminder::new_one_time(format!("Reminder {}", i), None, tomorrow, HashSet::new())
+            })
+            .collect();
+        storage.save(&reminders).unwrap();
+
+        let (cpu, read) = (cpu_time(), bytes_read());
+        run_daemon_until(
+            &storage,
+            NotifierRegistry::new(),
+            Some(Instant::now() + IDLE),
+        )
+        .unwrap();
+        let read = read.zip(bytes_read()).map(|(before, after)| after - before);
+        (cpu_time() - cpu, read)
+    }
+
+    pub fn main() {
+        println!(
+            "Idle daemon over {} reminders for {}s (poll interval {}s)\n",
+            REMINDERS,
+            IDLE.as_secs(),
+            POLL_INTERVAL_SECS
+        );
+        println!("{:<8} {:>10} {:>14}", "mode", "CPU (ms)", "read (KiB)");
+        // The log file stays in the first data directory, so keep both
+        let root = tempfile::tempdir().unwrap();
+        for (name, watch) in [("poll", false), ("watch", true)] {
+            let (cpu, read) = idle(&root.path().join(name), watch);
+            let read = read.map_or("n/a".to_string(), |bytes| (bytes / 1024).to_string());
+            println!("{:<8} {:>10} {:>14}", name, cpu.as_millis(), read);
+        }
+    }
+}
+
+#[cfg(unix)]
+fn main() {
+    idle::main();
+}
+
+#[cfg(not(unix))]
+fn main() {
+    println!("daemon_idle only runs on Unix");
+}
@@ -0,0 +1,152 @@
+//! Lookups and changes over 100k reminders: the indexed collection against
+//! the linear scans it replaces, and a JSON store with and without its
+//! cache. Run with `cargo bench --bench index`.
+
+use chrono::{Duration, Local};
+use criterion::{criterion_group, criterion_main, BatchSize, Criterion};
+use reminder_cli::index::ReminderIndex;
+use reminder_cli::reminder::Reminder;
+use reminder_cli::storage::{JsonStore, ReminderStore};
+use std::collections::HashSet;
+use std::hint::black_box;
+
+const REMINDERS: usize = 100_000;
+const TAGS: usize = 50;
+
+fn reminders() -> Vec<Reminder> {
+    let now = Local::now();
+    (0..REMINDERS)
+        .map(|i| {
+            // Spread triggers from a day ago to a year ahead
+            let at = now + Duration::minutes(i as i64 * 5 - 1440);
+            let tags: HashSet<String> = [format!("tag{}", i % TAGS)].into();
+            let mut reminder = Reminder::new_one_time(format!("Reminder {}", i), None, at, tags);
+            reminder.paused = i % 10 == 0;
+            reminder
+        })
+        .collect()
+}
+
+fn lookups(c: &mut Criterion) {
+    let reminders = reminders();
+    let index = ReminderIndex::new(reminders.clone());
+    let target = &reminders[REMINDERS / 2];
+    let prefix = target.id.to_string()[..8].to_string();
+
+    let mut group = c.benchmark_group("100k");
+    group.sample_size(20);
+
+    group.bench_function("build index", |b| {
+        b.iter_batched(
+            || reminders.clone(),
+            ReminderIndex::new,
+            BatchSize::LargeInput,
+        )
+    });
+
+    group.bench_function("get/scan", |b| {
+        b.iter(|| reminders.iter().find(|r| r.id == black_box(target.id)))
+    });
+    group.bench_function("get/index", |b| b.iter(|| index.get(black_box(target.id))));
+
+    group.bench_function("prefix/scan", |b| {
+        b.iter(|| {
+            reminders
+                .iter()
+                .filter(|r| r.id.to_string().starts_with(black_box(&prefix)))
+                .count()
+        })
+    });
+    group.bench_function("prefix/index", |b| {
+        b.iter(|| index.find_by_prefix(black_box(&prefix)).len())
+    });
+
+    group.bench_function("tag/scan", |b| {
+        b.iter(|| {
+            reminders
+                .iter()
+                .filter(|r| r.tags.contains(black_box("tag7")))
+                .count()
+        })
+    });
+    group.bench_function("tag/index", |b| {
+        b.iter(|| index.with_tag(black_box("tag7")).len())
+    });
+
+    group.bench_function("tag counts/scan", |b| {
+        b.iter(|| {
+            let mut tags: Vec<&String> = reminders.iter().flat_map(|r| &r.tags).collect();
+            tags.sort();
+            tags.dedup();
+            tags.iter()
+                .map(|tag| reminders.iter().filter(|r| r.tags.contains(*tag)).count())
+                .sum::<usize>()
+        })
+    });
+    group.bench_function("tag counts/index", |b| b.iter(|| index.tags().len()));
+
+    group.bench_function("due/scan", |b| {
+        b.iter(|| reminders.iter().filter(|r| r.is_due()).count())
+    });
+    group.bench_function("due/index", |b| b.iter(|| index.due(Local::now()).len()));
+
+    // What a transaction does to the reminders it is given
+    group.bench_function("update/scan", |b| {
+        let mut reminders = reminders.clone();
+        b.iter(|| {
+            if let Some(r) = reminders.iter_mut().find(|r| r.id == black_box(target.id)) {
+                r.paused = !r.paused;
+            }
+        })
+    });
+    group.bench_function("update/index", |b| {
+        let mut index = index.clone();
+        b.iter(|| index.update(black_box(target.id), |r| r.paused = !r.paused))
+    });
+
+    group.bench_function("delete/scan", |b| {
+        b.iter_batched_ref(
+            || reminders.clone(),
+            |reminders| reminders.retain(|r| r.id != black_box(target.id)),
+            BatchSize::LargeInput,
+        )
+    });
+    group.bench_function("delete/index", |b| {
+        b.iter_batched_ref(
+            || index.clone(),
+            |index| index.remove(black_box(target.id)),
+            BatchSize::LargeInput,
+        )
+    });
+
+    group.finish();
+}
+
+fn json_store(c: &mut Criterion) {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("reminders.json");
+    let reminders = reminders();
+    let prefix = reminders[REMINDERS / 2].id.to_string()[..8].to_string();
+    JsonStore::new(&path).save(&reminders).unwrap();
+
+    let mut group = c.benchmark_group("100k json store");
+    group.sample_size(10);
+
+    // A fresh store reads and parses the file for every lookup
+    group.bench_function("find_by_short_id/uncached", |b| {
+        b.iter(|| {
+            JsonStore::new(&path)
+                .find_by_short_id(black_box(&prefix))
+                .unwrap()
+        })
+    });
+    let store = JsonStore::new(&path);
+    group.bench_function("find_by_short_id/cached", |b| {
+        b.iter(|| store.find_by_short_id(black_box(&prefix)).unwrap())
+    });
+
+    group.finish();
+}
+
+criterion_group!(benches, lookups, json_store);
+criterion_main!(benches);
@@ -0,0 +1,572 @@
+use crate::crypto::Vault;
+use crate::events::{self, Cause, EventLog, EVENTS_FILE};
+use crate::log_warn;
+use crate::sqlite::SqliteStore;
+use crate::storage::{write_atomic, Backend, JsonStore, ReminderStore, FAILED_FILE, META_FILE};
+use anyhow::{bail, Context, Result};
+use chrono::{DateTime, Datelike, Duration, Local, NaiveDateTime, TimeZone};
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::fs;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+/// Snapshots go here, inside the data directory, unless `backup.dir` is set
+pub const BACKUP_DIR: &str = "backups";
+
+const PREFIX: &str = "reminders-";
+const EXTENSION: &str = ".tar.gz";
+const TIME_FORMAT: &str = "%Y%m%d-%H%M%S";
+
+/// Archive folders for files from the data and config directories
+const DATA: &str = "data/";
+const CONFIG: &str = "config/";
+
+/// Files of the data directory worth restoring; locks, temp files, store
+/// backups, logs and daemon state are left out
+const DATA_FILES: [&str; 5] = [
+    Backend::Json.file_name(),
+    META_FILE,
+    Backend::Sqlite.file_name(),
+    EVENTS_FILE,
+    FAILED_FILE,
+];
+
+/// `[backup]` settings for snapshots and their retention
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct BackupConfig {
+    /// Let the daemon take a snapshot once the newest is a day old
+    pub daily: bool,
+
+    /// Directory for snapshots, relative to the data directory unless
+    /// absolute; `backups` when unset
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub dir: Option<PathBuf>,
+
+    /// Newest snapshots that are always kept
+    pub keep_last: usize,
+
+    /// Days for which the newest snapshot of the day is kept
+    pub keep_daily: usize,
+
+    /// Weeks for which the newest snapshot of the week is kept
+    pub keep_weekly: usize,
+
+    /// Months for which the newest snapshot of the month is kept
+    pub keep_monthly: usize,
+}
+
+impl Default for BackupConfig {
+    fn default() -> Self {
+        Self {
+            daily: true,
+            dir: None,
+            keep_last: 3,
+            keep_daily: 7,
+            keep_weekly: 4,
+            keep_monthly: 6,
+        }
+    }
+}
+
+impl BackupConfig {
+    pub fn validate(&self) -> Result<()> {
+        if self.keep_last == 0 {
+            bail!("backup.keep_last must be at least 1");
+        }
+        Ok(())
+    }
+}
+
+/// One archive in the backup directory
+#[derive(Debug, Clone)]
+pub struct Snapshot {
+    pub path: PathBuf,
+    pub created: DateTime<Local>,
+    pub size: u64,
+}
+
+impl Snapshot {
+    /// The snapshot at `path`, if its name is one of ours
+    fn at(path: PathBuf) -> Option<Self> {
+        let name = path.file_name()?.to_str()?;
+        let stem = name.strip_prefix(PREFIX)?.strip_suffix(EXTENSION)?;
+        let time = NaiveDateTime::parse_from_str(stem.get(..15)?, TIME_FORMAT).ok()?;
+        let created = Local.from_local_datetime(&time).earliest()?;
+        let size = fs::metadata(&path).ok()?.len();
+        Some(Self {
+            path,
+            created,
+            size,
+        })
+    }
+
+    pub fn name(&self) -> String {
+        self.path
+            .file_name()
+            .map(|n| n.to_string_lossy().into_owned())
+            .unwrap_or_default()
+    }
+
+    /// Names of the files in the archive
+    pub fn files(&self) -> Result<Vec<String>> {
+        Ok(self.read()?.into_iter().map(|(name, _)| name).collect())
+    }
+
+    fn read(&self) -> Result<Vec<(String, Vec<u8>)>> {
+        let file = fs::File::open(&self.path)
+            .with_context(|| format!("Failed to open {}", self.path.display()))?;
+        read_archive(file).with_context(|| format!("Invalid archive {}", self.path.display()))
+    }
+}
+
+/// What a restore replaced
+#[derive(Debug)]
+pub struct Restore {
+    /// The state from before the restore
+    pub safety: Snapshot,
+    pub files: Vec<PathBuf>,
+    /// Stores of the other backend, renamed so the restored one is used
+    pub moved: Vec<PathBuf>,
+}
+
+/// Timestamped archives of everything needed to bring the reminders back:
+/// the store, the event log, failed deliveries and `config.toml`
+pub struct Backups {
+    dir: PathBuf,
+    store: PathBuf,
+    config_file: PathBuf,
+}
+
+impl Backups {
+    /// Snapshots of the data directory holding `store`
+    pub fn new(
+        store: impl Into<PathBuf>,
+        config_file: impl Into<PathBuf>,
+        config: &BackupConfig,
+    ) -> Self {
+        let store = store.into();
+        let data_dir = store.parent().unwrap_or(Path::new(".")).to_path_buf();
+        let dir = match &config.dir {
+            Some(dir) => data_dir.join(dir),
+            None => data_dir.join(BACKUP_DIR),
+        };
+        Self {
+            dir,
+            store,
+            config_file: config_file.into(),
+        }
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Archive the current state
+    pub fn create(&self) -> Result<Snapshot> {
+        fs::create_dir_all(&self.dir)
+            .with_context(|| format!("Failed to create directory {}", self.dir.display()))?;
+
+        let mut entries = Vec::new();
+        for (name, content) in self.data_files()? {
+            entries.push((format!("{}{}", DATA, name), content));
+        }
+        if let Some(content) = read_optional(&self.config_file)? {
+            entries.push((format!("{}{}", CONFIG, crate::paths::CONFIG_FILE), content));
+        }
+
+        let archive = write_archive(&entries)?;
+
+        let path = self.new_path(Local::now());
+        write_atomic(&path, &archive)?;
+        Snapshot::at(path).context("Failed to read back the new snapshot")
+    }
+
+    /// Every snapshot, oldest first
+    pub fn list(&self) -> Result<Vec<Snapshot>> {
+        if !self.dir.exists() {
+            return Ok(Vec::new());
+        }
+        let mut snapshots: Vec<Snapshot> = fs::read_dir(&self.dir)
+            .with_context(|| format!("Failed to read {}", self.dir.display()))?
+            .filter_map(|entry| Snapshot::at(entry.ok()?.path()))
+            .collect();
+        // `-2` and on mark later snapshots taken within the same second
+        snapshots.sort_by_key(|s| (s.created, s.name().len(), s.name()));
+        Ok(snapshots)
+    }
+
+    /// The snapshot named `name`, with or without its extension, or
+    /// `latest` for the newest
+    pub fn find(&self, name: &str) -> Result<Snapshot> {
+        let snapshots = self.list()?;
+        let found = if name == "latest" {
+            snapshots.last()
+        } else {
+            snapshots.iter().find(|s| {
+                let own = s.name();
+                own == name || own.strip_suffix(EXTENSION) == Some(name)
+            })
+        };
+        match found {
+            Some(snapshot) => Ok(snapshot.clone()),
+            None => bail!(
+                "No snapshot '{}' in {}; run `reminder backup list`",
+                name,
+                self.dir.display()
+            ),
+        }
+    }
+
+    /// Put the files of `snapshot` back in place, after saving the current
+    /// state as a new snapshot. Files the snapshot lacks are left alone.
+    /// An existing event log is kept and gets the restore appended as
+    /// edits; the snapshot's log only replaces one that cannot be replayed.
+    pub fn restore(&self, snapshot: &Snapshot) -> Result<Restore> {
+        let data_dir = self.store.parent().unwrap_or(Path::new("."));
+        let vault = Arc::new(Vault::default());
+        let log = EventLog::for_store(&self.store, vault.clone());
+        let before = if log.exists() {
+            match log.read().and_then(|events| events::replay(&events)) {
+                Ok(reminders) => Some(reminders),
+                Err(e) => {
+                    log_warn!("Replacing unreadable event log: {:#}", e);
+                    None
+                }
+            }
+        } else {
+            None
+        };
+
+        let entries = snapshot.read()?;
+        let mut targets = Vec::new();
+        for (name, content) in entries {
+            let path = self.target(&name)?;
+            if before.is_none() || path != log.path() {
+                targets.push((path, content));
+            }
+        }
+
+        let safety = self.create()?;
+        let restored_stores: Vec<&Path> = targets
+            .iter()
+            .map(|(path, _)| path.as_path())
+            .filter(|path| is_store(path))
+            .collect();
+
+        let mut moved = Vec::new();
+        if !restored_stores.is_empty() {
+            for name in [Backend::Json.file_name(), Backend::Sqlite.file_name()] {
+                let path = data_dir.join(name);
+                if path.exists() && !restored_stores.contains(&path.as_path()) {
+                    let aside = path.with_file_name(format!("{}.pre-restore", name));
+                    fs::rename(&path, &aside)
+                        .with_context(|| format!("Failed to move {} aside", path.display()))?;
+                    moved.push(aside);
+                }
+            }
+        }
+
+        let mut files = Vec::new();
+        for (path, content) in targets {
+            match Backend::for_path(&path) {
+                Backend::Sqlite if is_store(&path) => {
+                    // The old journal belongs to the database being replaced
+                    for suffix in ["-wal", "-shm"] {
+                        let mut journal = path.clone().into_os_string();
+                        journal.push(suffix);
+                        let _ = fs::remove_file(PathBuf::from(journal));
+                    }
+                    write_atomic(&path, &content)?;
+                }
+                Backend::Json if is_store(&path) => {
+                    let lock = JsonStore::new(&path).lock()?;
+                    write_atomic(&path, &content)?;
+                    drop(lock);
+                }
+                _ => write_atomic(&path, &content)?,
+            }
+            files.push(path);
+        }
+
+        if let (Some(before), Some(store)) = (before, files.iter().find(|path| is_store(path))) {
+            let after = match Backend::for_path(store) {
+                Backend::Json => JsonStore::with_vault(store, vault.clone()).load()?,
+                Backend::Sqlite => SqliteStore::new(store).load()?,
+            };
+            // Encrypted like the store now in place
+            EventLog::for_store(store, vault).append(&events::diff(
+                &before,
+                &after,
+                Cause::Edit,
+            ))?;
+        }
+
+        Ok(Restore {
+            safety,
+            files,
+            moved,
+        })
+    }
+
+    /// Snapshots the retention rules in `config` no longer keep, oldest
+    /// first
+    pub fn expired(&self, config: &BackupConfig) -> Result<Vec<Snapshot>> {
+        let snapshots = self.list()?;
+        let times: Vec<DateTime<Local>> = snapshots.iter().map(|s| s.created).collect();
+        let keep = retained(&times, config);
+        Ok(snapshots
+            .into_iter()
+            .zip(keep)
+            .filter(|(_, keep)| !keep)
+            .map(|(snapshot, _)| snapshot)
+            .collect())
+    }
+
+    /// Delete the snapshots the retention rules no longer keep
+    pub fn prune(&self, config: &BackupConfig) -> Result<Vec<Snapshot>> {
+        let expired = self.expired(config)?;
+        for snapshot in &expired {
+            fs::remove_file(&snapshot.path)
+                .with_context(|| format!("Failed to delete {}", snapshot.path.display()))?;
+        }
+        Ok(expired)
+    }
+
+    /// Whether the newest snapshot is at least a day older than `now`
+    pub fn is_due(&self, now: DateTime<Local>) -> Result<bool> {
+        Ok(match self.list()?.last() {
+            Some(newest) => now - newest.created >= Duration::days(1),
+            None => true,
+        })
+    }
+
+    /// The store and the files next to it, read consistently
+    fn data_files(&self) -> Result<Vec<(&'static str, Vec<u8>)>> {
+        let mut files = Vec::new();
+        let mut lock = None;
+        match Backend::for_path(&self.store) {
+            Backend::Json => {
+                // Writers append to the event log under the same lock
+                lock = Some(JsonStore::new(&self.store).lock()?);
+                if let Some(content) = read_optional(&self.store)? {
+                    files.push((Backend::Json.file_name(), content));
+                }
+                if let Some(content) = read_optional(&self.store.with_file_name(META_FILE))? {
+                    files.push((META_FILE, content));
+                }
+            }
+            Backend::Sqlite if self.store.exists() => {
+                let copy = self.dir.join(format!(".copy{}", std::process::id()));
+                let _ = fs::remove_file(&copy);
+                SqliteStore::new(&self.store).copy_to(&copy)?;
+                let content = fs::read(&copy).context("Failed to read database copy");
+                let _ = fs::remove_file(&copy);
+                files.push((Backend::Sqlite.file_name(), content?));
+            }
+            Backend::Sqlite => {}
+        }
+        for name in [EVENTS_FILE, FAILED_FILE] {
+            if let Some(content) = read_optional(&self.store.with_file_name(name))? {
+                files.push((name, content));
+            }
+        }
+        drop(lock);
+        Ok(files)
+    }
+
+    /// Where an archive entry is restored to
+    fn target(&self, name: &str) -> Result<PathBuf> {
+        if let Some(file) = name.strip_prefix(DATA) {
+            if DATA_FILES.contains(&file) {
+                return Ok(self.store.with_file_name(file));
+            }
+        }
+        if name.strip_prefix(CONFIG) == Some(crate::paths::CONFIG_FILE) {
+            return Ok(self.config_file.clone());
+        }
+        bail!("Unexpected file '{}' in snapshot", name)
+    }
+
+    /// A name no snapshot has yet
+    fn new_path(&self, now: DateTime<Local>) -> PathBuf {
+        let stamp = now.format(TIME_FORMAT).to_string();
+        let mut path = self.dir.join(format!("{}{}{}", PREFIX, stamp, EXTENSION));
+        let mut n = 1;
+        while path.exists() {
+            n += 1;
+            path = self
+                .dir
+                .join(format!("{}{}-{}{}", PREFIX, stamp, n, EXTENSION));
+        }
+        path
+    }
+}
+
+/// Take the daemon's daily snapshot once the newest is a day old, then
+/// prune. Returns the new snapshot and the ones pruned.
+pub fn run_daily(
+    store: &Path,
+    config_file: &Path,
+    config: &BackupConfig,
+) -> Result<Option<(Snapshot, Vec<Snapshot>)>> {
+    if !config.daily || !store.exists() {
+        return Ok(None);
+    }
+    let backups = Backups::new(store, config_file, config);
+    if !backups.is_due(Local::now())? {
+        return Ok(None);
+    }
+    let snapshot = backups.create()?;
+    let pruned = backups.prune(config)?;
+    Ok(Some((snapshot, pruned)))
+}
+
+fn is_store(path: &Path) -> bool {
+    path.file_name().is_some_and(|name| {
+        name == Backend::Json.file_name() || name == Backend::Sqlite.file_name()
+    })
+}
+
+fn read_optional(path: &Path) -> Result<Option<Vec<u8>>> {
+    match fs::read(path) {
+        Ok(content) => Ok(Some(content)),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e).with_context(|| format!("Failed to read {}", path.display())),
+    }
+}
+
+/// Which of the snapshots taken at `times`, oldest first, the rules keep:
+/// the newest `keep_last`, and the newest of each of the most recent
+/// `keep_daily` days, `keep_weekly` weeks and `keep_monthly` months
+fn retained(times: &[DateTime<Local>], config: &BackupConfig) -> Vec<bool> {
+    let mut keep = vec![false; times.len()];
+    let newest_first = (0..times.len()).rev();
+    for i in newest_first.clone().take(config.keep_last) {
+        keep[i] = true;
+    }
+
+    type Period = fn(&DateTime<Local>) -> (i32, u32);
+    let periods: [(usize, Period); 3] = [
+        (config.keep_daily, |t| (t.year(), t.ordinal())),
+        (config.keep_weekly, |t| {
+            let week = t.iso_week();
+            (week.year(), week.week())
+        }),
+        (config.keep_monthly, |t| (t.year(), t.month())),
+    ];
+    for (count, period) in periods {
+        let mut seen = HashSet::new();
+        for i in newest_first.clone() {
+            if seen.len() == count {
+                break;
+            }
+            if seen.insert(period(&times[i])) {
+                keep[i] = true;
+            }
+        }
+    }
+    keep
+}
+
+/// Gzipped tar archive of `entries`, each a file name and its content
+fn write_archive(entries: &[(String, Vec<u8>)]) -> Result<Vec<u8>> {
+    let mtime = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+
+    let mut builder = tar::Builder::new(GzEncoder::new(Vec::new(), Compression::default()));
+    for (name, content) in entries {
+        let mut header = tar::Header::new_ustar();
+        header.set_size(content.len() as u64);
+        header.set_mode(0o644);
+        header.set_mtime(mtime);
+        header.set_entry_type(tar::EntryType::Regular);
+        builder
+            .append_data(&mut header, name, content.as_slice())
+            .with_context(|| format!("Failed to archive {}", name))?;
+    }
+    let encoder = builder.into_inner().context("Failed to write snapshot")?;
+    encoder.finish().context("Failed to compress snapshot")
+}
+
+/// The files in a gzipped tar archive, in order
+fn read_archive(archive: impl Read) -> Result<Vec<(String, Vec<u8>)>> {
+    let mut archive = tar::Archive::new(GzDecoder::new(archive));
+    let mut entries = Vec::new();
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        let name = entry
+            .path()?
+            .to_str()
+            .context("File name is not UTF-8")?
+            .to_string();
+        if !entry.header().entry_type().is_file() {
+            bail!("{} is not a regular file", name);
+        }
+        let mut content = Vec::new();
+        entry
+            .read_to_end(&mut content)
+            .with_context(|| format!("{} is cut off", name))?;
+        entries.push((name, content));
+    }
+    Ok(entries)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(y: i32, m: u32, d: u32, h: u32) -> DateTime<Local> {
+        Local.with_ymd_and_hms(y, m, d, h, 0, 0).unwrap()
+    }
+
+    #[test]
+    fn test_archive_round_trip() {
+        let entries = vec![
+            ("data/reminders.json".to_string(), b"[]".to_vec()),
+            ("config/config.toml".to_string(), vec![b'x'; 1000]),
+            ("data/empty".to_string(), Vec::new()),
+        ];
+        let archive = write_archive(&entries).unwrap();
+        assert_eq!(read_archive(archive.as_slice()).unwrap(), entries);
+
+        let mut corrupted = archive.clone();
+        corrupted[0] = b'x';
+        assert!(read_archive(corrupted.as_slice()).is_err());
+        assert!(read_archive(&archive[..archive.len() / 2]).is_err());
+    }
+
+    #[test]
+    fn test_retention() {
+        let config = BackupConfig {
+            keep_last: 2,
+            keep_daily: 3,
+            keep_weekly: 2,
+            keep_monthly: 3,
+            ..BackupConfig::default()
+        };
+        let times = [
+            at(2026, 8, 20, 9),  // newest of August: monthly
+            at(2026, 9, 29, 9),  // superseded by 30 September
+            at(2026, 9, 30, 9),  // newest of September: monthly
+            at(2026, 10, 5, 9),  // superseded by 7 October
+            at(2026, 10, 6, 9),  // superseded by 7 October
+            at(2026, 10, 7, 9),  // daily, and newest of its week
+            at(2026, 10, 12, 8), // superseded by 9:00 the same day
+            at(2026, 10, 12, 9), // daily and among the last two
+            at(2026, 10, 13, 9), // newest of everything
+        ];
+        let keep = retained(&times, &config);
+        assert_eq!(
+            keep,
+            [true, false, true, false, false, true, false, true, true]
+        );
+    }
+}
@@ -0,0 +1,511 @@
+use crate::backup::BackupConfig;
+use crate::crypto::EncryptionConfig;
+use crate::daemon::DaemonConfig;
+use crate::email::EmailConfig;
+use crate::hooks::DEFAULT_HOOK_TIMEOUT_SECS;
+use crate::logger::LogConfig;
+use crate::mqtt::MqttConfig;
+use crate::notification::NotificationConfig;
+use crate::notifier::DeliveryConfig;
+use crate::paths;
+use crate::reminder::{NotificationStyle, Reminder};
+use crate::retry::RetryConfig;
+use crate::template::MessageTemplate;
+use crate::terminal::TerminalConfig;
+use crate::time_parser::TimeConfig;
+use crate::webhook::WebhookConfig;
+use anyhow::{anyhow, bail, Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+/// User configuration read from `config.toml`
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    #[serde(default)]
+    pub daemon: DaemonConfig,
+
+    #[serde(default)]
+    pub notification: NotificationConfig,
+
+    #[serde(default)]
+    pub log: LogConfig,
+
+    #[serde(default)]
+    pub time: TimeConfig,
+
+    /// Where to find the secret for an encrypted store
+    #[serde(default)]
+    pub encryption: EncryptionConfig,
+
+    /// Settings applied to every reminder carrying the tag
+    #[serde(default)]
+    pub tags: BTreeMap<String, TagConfig>,
+
+    #[serde(default)]
+    pub hooks: HooksConfig,
+
+    /// Settings used for per-reminder `--webhook` URLs
+    #[serde(default)]
+    pub webhook: WebhookConfig,
+
+    /// Per-reminder hooks allowed to run on this machine
+    #[serde(default)]
+    pub trusted: TrustedConfig,
+
+    /// SMTP settings for the `email` channel
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub email: Option<EmailConfig>,
+
+    /// Broker settings for the `mqtt` channel
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mqtt: Option<MqttConfig>,
+
+    /// How the `terminal` channel writes to logged-in terminals
+    #[serde(default)]
+    pub terminal: TerminalConfig,
+
+    /// Default summary and body templates for every channel
+    #[serde(default)]
+    pub template: MessageTemplate,
+
+    /// Fallback order for failed deliveries
+    #[serde(default)]
+    pub delivery: DeliveryConfig,
+
+    /// Retries for deliveries that failed along the whole fallback chain
+    #[serde(default)]
+    pub retry: RetryConfig,
+
+    /// Snapshots of the data directory and how long they are kept
+    #[serde(default)]
+    pub backup: BackupConfig,
+}
+
+/// Shell commands run by the daemon whenever any reminder fires
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct HooksConfig {
+    #[serde(default)]
+    pub on_trigger: Vec<String>,
+
+    /// Seconds before a hook is killed
+    #[serde(default = "default_hook_timeout")]
+    pub timeout_secs: u64,
+}
+
+impl Default for HooksConfig {
+    fn default() -> Self {
+        Self {
+            on_trigger: Vec::new(),
+            timeout_secs: DEFAULT_HOOK_TIMEOUT_SECS,
+        }
+    }
+}
+
+fn default_hook_timeout() -> u64 {
+    DEFAULT_HOOK_TIMEOUT_SECS
+}
+
+/// Per-reminder `--exec` commands and `--webhook` URLs set on this machine.
+/// Reminders also arrive through sync, merge and import, so their hooks are
+/// only used once listed here; `add` and `edit` list the ones they set.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TrustedConfig {
+    #[serde(default)]
+    pub exec: Vec<String>,
+
+    #[serde(default)]
+    pub webhooks: Vec<String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TagConfig {
+    #[serde(flatten)]
+    pub style: NotificationStyle,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub webhook: Option<WebhookConfig>,
+
+    /// MQTT topic for reminders carrying the tag
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mqtt_topic: Option<String>,
+}
+
+impl Config {
+    pub fn load() -> Result<Self> {
+        Self::load_from(&paths::config_file()?)
+    }
+
+    pub fn load_from(path: &Path) -> Result<Self> {
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+
+        let content = fs::read_to_string(path).context("Failed to read config file")?;
+        Self::parse(&content).with_context(|| format!("Invalid config file {}", path.display()))
+    }
+
+    /// Parse and validate the contents of a config file
+    pub fn parse(content: &str) -> Result<Self> {
+        let config: Self = toml::from_str(content)?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// Check values that parse but make no sense
+    pub fn validate(&self) -> Result<()> {
+        self.daemon.validate()?;
+        self.log.validate()?;
+        self.backup.validate()?;
+        self.delivery.validate()?;
+        for (tag, settings) in &self.tags {
+            if let Some(webhook) = &settings.webhook {
+                if webhook.url.trim().is_empty() {
+                    bail!("tags.{}.webhook.url must be set", tag);
+                }
+            }
+        }
+        if let Some(mqtt) = &self.mqtt {
+            if mqtt.qos > 2 {
+                bail!("mqtt.qos must be 0, 1 or 2, not {}", mqtt.qos);
+            }
+        }
+        Ok(())
+    }
+
+    /// Every effective setting, defaults included, as dotted keys with
+    /// TOML values
+    pub fn entries(&self) -> Result<Vec<(String, String)>> {
+        let value = toml::Value::try_from(self).context("Failed to serialize config")?;
+        let mut entries = Vec::new();
+        flatten("", &value, &mut entries);
+        Ok(entries)
+    }
+
+    /// The setting at `key`, or every setting under it for a section
+    pub fn get(&self, key: &str) -> Result<Vec<(String, String)>> {
+        let prefix = format!("{}.", key);
+        let entries: Vec<_> = self
+            .entries()?
+            .into_iter()
+            .filter(|(k, _)| k == key || k.starts_with(&prefix))
+            .collect();
+        if entries.is_empty() {
+            bail!(unknown_key(key));
+        }
+        Ok(entries)
+    }
+
+    /// The reminder's own `--exec` command, if it may run here
+    pub fn trusted_exec<'a>(&self, reminder: &'a Reminder) -> Option<&'a String> {
+        reminder
+            .exec
+            .as_ref()
+            .filter(|command| self.trusted.exec.contains(command))
+    }
+
+    /// The reminder's own `--webhook` URL, if it may be posted to from here
+    pub fn trusted_webhook<'a>(&self, reminder: &'a Reminder) -> Option<&'a String> {
+        reminder
+            .webhook
+            .as_ref()
+            .filter(|url| self.trusted.webhooks.contains(url))
+    }
+
+    /// Notification style for a reminder: its own overrides first, then tag
+    /// overrides in tag name order
+    pub fn style_for(&self, reminder: &Reminder) -> NotificationStyle {
+        let mut tags: Vec<&String> = reminder.tags.iter().collect();
+        tags.sort();
+
+        tags.into_iter()
+            .filter_map(|tag| self.tags.get(tag))
+            .fold(reminder.style.clone(), |style, tag| style.or(&tag.style))
+    }
+}
+
+fn flatten(prefix: &str, value: &toml::Value, entries: &mut Vec<(String, String)>) {
+    match value {
+        toml::Value::Table(table) => {
+            for (key, value) in table {
+                let key = if prefix.is_empty() {
+                    key.clone()
+                } else {
+                    format!("{}.{}", prefix, key)
+                };
+                flatten(&key, value, entries);
+            }
+        }
+        value => entries.push((prefix.to_string(), value.to_string())),
+    }
+}
+
+fn unknown_key(key: &str) -> String {
+    format!(
+        "Unknown config key '{}'; run `reminder config list` to see the available keys",
+        key
+    )
+}
+
+/// Return `content` with `key` set to `value`, keeping the rest of the file,
+/// comments included, as it was. `value` is read as a TOML value, or as a
+/// string if it is not one. The result must be a valid config that has `key`.
+pub fn set_value(content: &str, key: &str, value: &str) -> Result<String> {
+    let mut doc: toml_edit::DocumentMut = content.parse().context("Failed to parse config file")?;
+    let value: toml_edit::Value = value
+        .parse()
+        .unwrap_or_else(|_| toml_edit::Value::from(value));
+
+    let segments: Vec<&str> = key.split('.').collect();
+    if segments.iter().any(|s| s.is_empty()) {
+        bail!(unknown_key(key));
+    }
+    let (last, parents) = segments.split_last().unwrap();
+
+    let mut table: &mut dyn toml_edit::TableLike = doc.as_table_mut();
+    for segment in parents {
+        table = table
+            .entry(segment)
+            .or_insert_with(toml_edit::table)
+            .as_table_like_mut()
+            .ok_or_else(|| anyhow!("'{}' in '{}' is not a section", segment, key))?;
+    }
+    table.insert(last, toml_edit::value(value));
+
+    let content = doc.to_string();
+    check_key(&content, key)?;
+    Ok(content)
+}
+
+/// Return `content` with `value` added to the list at `key`, as
+/// [`set_value`] does for single values, or `None` if it is already there
+pub fn add_value(content: &str, key: &str, value: &str) -> Result<Option<String>> {
+    let mut doc: toml_edit::DocumentMut = content.parse().context("Failed to parse config file")?;
+    let (section, name) = key
+        .split_once('.')
+        .ok_or_else(|| anyhow!(unknown_key(key)))?;
+    let list = doc
+        .entry(section)
+        .or_insert_with(toml_edit::table)
+        .as_table_like_mut()
+        .ok_or_else(|| anyhow!("'{}' in '{}' is not a section", section, key))?
+        .entry(name)
+        .or_insert_with(|| toml_edit::value(toml_edit::Array::new()))
+        .as_array_mut()
+        .ok_or_else(|| anyhow!("'{}' is not a list", key))?;
+    if list.iter().any(|v| v.as_str() == Some(value)) {
+        return Ok(None);
+    }
+    list.push(value);
+
+    let content = doc.to_string();
+    check_key(&content, key)?;
+    Ok(Some(content))
+}
+
+/// Check that `content`, with `key` just set, is a valid config that has
+/// `key`
+fn check_key(content: &str, key: &str) -> Result<()> {
+    let name = key.rsplit('.').next().unwrap_or(key);
+    let config = match Config::parse(content) {
+        Ok(config) => config,
+        Err(e) if e.to_string().contains(&format!("unknown field `{}`", name)) => {
+            bail!(unknown_key(key))
+        }
+        Err(e) => return Err(e.context(format!("Invalid value for {}", key))),
+    };
+    if !config.entries()?.iter().any(|(k, _)| k == key) {
+        bail!(unknown_key(key));
+    }
+    Ok(())
+}
+
+/// Keeps the config a long-running process uses up to date with the file
+pub struct ConfigWatcher {
+    path: PathBuf,
+    modified: Option<SystemTime>,
+    current: Config,
+}
+
+impl ConfigWatcher {
+    /// Starts from the defaults; the first `refresh` reads the file
+    pub fn new(path: PathBuf) -> Self {
+        Self {
+            path,
+            modified: None,
+            current: Config::default(),
+        }
+    }
+
+    pub fn current(&self) -> &Config {
+        &self.current
+    }
+
+    /// Reload the file if it changed since the last call. Returns whether
+    /// the config was replaced; an invalid file leaves the previous config
+    /// in place and is not read again until it changes.
+    pub fn refresh(&mut self) -> Result<bool> {
+        let modified = fs::metadata(&self.path)
+            .and_then(|meta| meta.modified())
+            .ok();
+        if modified == self.modified {
+            return Ok(false);
+        }
+        self.modified = modified;
+        self.current = Config::load_from(&self.path)?;
+        Ok(true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::reminder::{NotificationTimeout, Urgency};
+    use std::collections::HashSet;
+
+    #[test]
+    fn test_reminder_style_overrides_tags() {
+        let config: Config = toml::from_str(
+            r#"
+            [tags.work]
+            icon = "briefcase"
+            urgency = "low"
+
+            [tags.urgent]
+            urgency = "critical"
+            timeout = "persistent"
+            "#,
+        )
+        .unwrap();
+
+        let tags: HashSet<String> = ["work", "urgent"].iter().map(|t| t.to_string()).collect();
+        let mut reminder =
+            Reminder::new_one_time("Deploy".to_string(), None, chrono::Local::now(), tags);
+        reminder.style.icon = Some("rocket".to_string());
+
+        let style = config.style_for(&reminder);
+        assert_eq!(style.icon.as_deref(), Some("rocket"));
+        // "urgent" sorts before "work"
+        assert_eq!(style.urgency, Some(Urgency::Critical));
+        assert_eq!(style.timeout, Some(NotificationTimeout::Persistent));
+    }
+
+    #[test]
+    fn test_timeout_parsing() {
+        assert_eq!(
+            "15s".parse::<NotificationTimeout>().unwrap(),
+            NotificationTimeout::Milliseconds(15000)
+        );
+        assert_eq!(
+            "2500".parse::<NotificationTimeout>().unwrap(),
+            NotificationTimeout::Milliseconds(2500)
+        );
+        assert_eq!(
+            "never".parse::<NotificationTimeout>().unwrap(),
+            NotificationTimeout::Persistent
+        );
+        assert!("soon".parse::<NotificationTimeout>().is_err());
+    }
+
+    #[test]
+    fn test_set_value_keeps_comments_and_validates() {
+        let content = "# check often\n[daemon]\npoll_interval_secs = 5 # seconds\n";
+
+        let updated = set_value(content, "time.default_time", "18:30").unwrap();
+        assert!(updated.contains("poll_interval_secs = 5 # seconds"));
+        let config = Config::parse(&updated).unwrap();
+        assert_eq!(config.time.default_time.to_string(), "18:30:00");
+
+        let updated = set_value(&updated, "log.max_size_kb", "64").unwrap();
+        assert_eq!(
+            Config::parse(&updated).unwrap().log.max_size_bytes(),
+            64 * 1024
+        );
+
+        let err = set_value(content, "daemon.poll_interval_secs", "0").unwrap_err();
+        assert!(format!("{:#}", err).contains("at least 1"));
+        assert!(set_value(content, "time.default_time", "late").is_err());
+        assert!(set_value(content, "daemon.poll", "5").is_err());
+        let err = set_value(content, "tags.work.colour", "red").unwrap_err();
+        assert!(err.to_string().contains("reminder config list"));
+
+        // A tag webhook without a URL would only fail once a reminder fires
+        let err = Config::parse("[tags.team.webhook]\ntimeout_secs = 5\n").unwrap_err();
+        assert!(err.to_string().contains("tags.team.webhook.url"));
+        assert!(Config::parse("[webhook]\ntimeout_secs = 5\n").is_ok());
+    }
+
+    #[test]
+    fn test_rejects_unknown_keys_in_sections() {
+        for content in [
+            "[retry]\nretriez = 5\n",
+            "[hooks]\non_triger = [\"x\"]\n",
+            "[delivery]\nfallbacks = [\"log\"]\n",
+            "[webhook]\ntimeout = 5\n",
+            "[terminal]\nenable = true\n",
+            "[trusted]\ncommands = []\n",
+            "[tags.work]\ncolour = \"red\"\n",
+            "[tags.work.webhook]\nurl = \"https://example.com\"\nmethd = \"PUT\"\n",
+        ] {
+            let err = Config::parse(content).unwrap_err();
+            assert!(err.to_string().contains("unknown field"), "{}", content);
+        }
+        // Keys of the flattened style are still known to tags
+        let config = Config::parse("[tags.work]\nicon = \"briefcase\"\n").unwrap();
+        assert_eq!(config.tags["work"].style.icon.as_deref(), Some("briefcase"));
+    }
+
+    #[test]
+    fn test_rejects_unknown_fallback_channels() {
+        let err = Config::parse("[delivery]\nfallback = [\"desktp\", \"log\"]\n").unwrap_err();
+        assert!(err.to_string().contains("unknown channel desktp"));
+        assert!(set_value("", "delivery.fallback", "[\"Log\"]").is_err());
+        let config = Config::parse("[delivery]\nfallback = [\"email\", \"log\"]\n").unwrap();
+        assert_eq!(config.delivery.fallback, ["email", "log"]);
+    }
+
+    #[test]
+    fn test_add_value_appends_once() {
+        let content = "[trusted]\nexec = [\"true\"] # mine\n";
+
+        let updated = add_value(content, "trusted.exec", "date").unwrap().unwrap();
+        assert!(updated.contains("# mine"));
+        let config = Config::parse(&updated).unwrap();
+        assert_eq!(config.trusted.exec, ["true", "date"]);
+        let again = add_value(&updated, "trusted.exec", "date").unwrap();
+        assert!(again.is_none());
+
+        let updated = add_value("", "trusted.webhooks", "https://example.com")
+            .unwrap()
+            .unwrap();
+        let config = Config::parse(&updated).unwrap();
+        assert_eq!(config.trusted.webhooks, ["https://example.com"]);
+        assert!(add_value("", "trusted.hooks", "date").is_err());
+    }
+
+    #[test]
+    fn test_watcher_keeps_last_good_config() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("config.toml");
+        let mut watcher = ConfigWatcher::new(path.clone());
+        assert!(!watcher.refresh().unwrap());
+        assert_eq!(watcher.current().daemon.poll_interval_secs, 10);
+
+        fs::write(&path, "[daemon]\npoll_interval_secs = 2\n").unwrap();
+        assert!(watcher.refresh().unwrap());
+        assert!(!watcher.refresh().unwrap());
+        assert_eq!(watcher.current().daemon.poll_interval_secs, 2);
+
+        // Make sure the modification time moves on coarse filesystems
+        std::thread::sleep(std::time::Duration::from_millis(20));
+        fs::write(&path, "[daemon]\npoll_interval_secs = \"often\"\n").unwrap();
+        assert!(watcher.refresh().is_err());
+        assert_eq!(watcher.current().daemon.poll_interval_secs, 2);
+    }
+}
@@ -0,0 +1,376 @@
+use crate::config::Config;
+use anyhow::{anyhow, bail, Context, Result};
+use argon2::{Algorithm, Argon2, Params, Version};
+use chacha20poly1305::aead::rand_core::RngCore;
+use chacha20poly1305::aead::{Aead, KeyInit, OsRng, Payload};
+use chacha20poly1305::{XChaCha20Poly1305, XNonce};
+use serde::{Deserialize, Serialize};
+use std::io::IsTerminal;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::Mutex;
+use zeroize::Zeroizing;
+
+/// Key file used instead of `encryption.key_file`
+pub const KEY_FILE_ENV: &str = "REMINDER_KEY_FILE";
+
+/// Passphrase used instead of the configured sources or a prompt
+pub const PASSPHRASE_ENV: &str = "REMINDER_PASSPHRASE";
+
+const MAGIC: &[u8; 8] = b"RMDRENC1";
+const SALT_LEN: usize = 16;
+const NONCE_LEN: usize = 24;
+/// Magic, the three Argon2 parameters, salt and nonce
+const HEADER_LEN: usize = MAGIC.len() + 12 + SALT_LEN + NONCE_LEN;
+
+// Argon2id cost for newly encrypted files; each file records its own
+const MEMORY_KIB: u32 = 19 * 1024;
+const ITERATIONS: u32 = 2;
+const PARALLELISM: u32 = 1;
+
+// Highest Argon2id cost accepted from a file header, so a tampered or
+// synced file cannot make every run allocate gigabytes or spin for minutes
+const MAX_MEMORY_KIB: u32 = 256 * 1024;
+const MAX_ITERATIONS: u32 = 16;
+const MAX_PARALLELISM: u32 = 16;
+
+/// `[encryption]` settings: where the daemon and other runs without a
+/// terminal get the secret for an encrypted store
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct EncryptionConfig {
+    /// File holding the secret, e.g. one made by `storage encrypt --key-file`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub key_file: Option<PathBuf>,
+
+    /// Shell command printing the passphrase, such as a password manager
+    /// or keyring agent lookup
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub passphrase_command: Option<String>,
+}
+
+/// Whether `content` is a file written by [`Vault::encrypt`]
+pub fn is_encrypted(content: &[u8]) -> bool {
+    content.starts_with(MAGIC)
+}
+
+/// Where a [`Vault`] gets its secret
+#[derive(Clone)]
+pub enum KeySource {
+    /// `REMINDER_KEY_FILE`, `REMINDER_PASSPHRASE`, the `[encryption]`
+    /// config, then a prompt when running in a terminal
+    Auto,
+    KeyFile(PathBuf),
+    Passphrase(Zeroizing<String>),
+}
+
+/// Encrypts store files with XChaCha20-Poly1305 under a key derived from
+/// the secret with Argon2id. The secret is fetched on first use and the
+/// derived key kept for the life of the process.
+pub struct Vault {
+    source: KeySource,
+    state: Mutex<State>,
+}
+
+#[derive(Default)]
+struct State {
+    secret: Option<Zeroizing<Vec<u8>>>,
+    key: Option<DerivedKey>,
+}
+
+struct DerivedKey {
+    /// Argon2 parameters and salt the key was derived with
+    header: [u8; HEADER_LEN - NONCE_LEN],
+    key: Zeroizing<[u8; 32]>,
+}
+
+impl std::fmt::Debug for Vault {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Vault").finish_non_exhaustive()
+    }
+}
+
+impl Default for Vault {
+    fn default() -> Self {
+        Self::new(KeySource::Auto)
+    }
+}
+
+impl Vault {
+    pub fn new(source: KeySource) -> Self {
+        Self {
+            source,
+            state: Mutex::new(State::default()),
+        }
+    }
+
+    pub fn encrypt(&self, plaintext: &[u8]) -> Result<Vec<u8>> {
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        if state.key.is_none() {
+            let mut header = [0u8; HEADER_LEN - NONCE_LEN];
+            header[..MAGIC.len()].copy_from_slice(MAGIC);
+            for (i, value) in [MEMORY_KIB, ITERATIONS, PARALLELISM].iter().enumerate() {
+                let at = MAGIC.len() + i * 4;
+                header[at..at + 4].copy_from_slice(&value.to_le_bytes());
+            }
+            OsRng.fill_bytes(&mut header[MAGIC.len() + 12..]);
+            let key = self.derive(&mut state, &header)?;
+            state.key = Some(key);
+        }
+        let derived = state.key.as_ref().unwrap();
+
+        let mut nonce = [0u8; NONCE_LEN];
+        OsRng.fill_bytes(&mut nonce);
+        let mut output = derived.header.to_vec();
+        output.extend_from_slice(&nonce);
+
+        let cipher = XChaCha20Poly1305::new(derived.key.as_ref().into());
+        let ciphertext = cipher
+            .encrypt(
+                XNonce::from_slice(&nonce),
+                Payload {
+                    msg: plaintext,
+                    aad: &output,
+                },
+            )
+            .map_err(|_| anyhow!("Failed to encrypt reminders"))?;
+        output.extend_from_slice(&ciphertext);
+        Ok(output)
+    }
+
+    pub fn decrypt(&self, data: &[u8]) -> Result<Vec<u8>> {
+        if !is_encrypted(data) || data.len() < HEADER_LEN {
+            bail!("Not an encrypted reminders file");
+        }
+        let (header, ciphertext) = data.split_at(HEADER_LEN);
+        let (params, nonce) = header.split_at(HEADER_LEN - NONCE_LEN);
+
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        if state.key.as_ref().map(|k| &k.header[..]) != Some(params) {
+            let key = self.derive(&mut state, params.try_into().unwrap())?;
+            state.key = Some(key);
+        }
+        let derived = state.key.as_ref().unwrap();
+
+        let cipher = XChaCha20Poly1305::new(derived.key.as_ref().into());
+        cipher
+            .decrypt(
+                XNonce::from_slice(nonce),
+                Payload {
+                    msg: ciphertext,
+                    aad: header,
+                },
+            )
+            .map_err(|_| {
+                anyhow!(
+                    "Failed to decrypt reminders: wrong passphrase or key file, \
+                     or the file is damaged"
+                )
+            })
+    }
+
+    fn derive(
+        &self,
+        state: &mut State,
+        header: &[u8; HEADER_LEN - NONCE_LEN],
+    ) -> Result<DerivedKey> {
+        let field = |i: usize| {
+            let at = MAGIC.len() + i * 4;
+            u32::from_le_bytes(header[at..at + 4].try_into().unwrap())
+        };
+        let (memory, iterations, parallelism) = (field(0), field(1), field(2));
+        if memory > MAX_MEMORY_KIB || iterations > MAX_ITERATIONS || parallelism > MAX_PARALLELISM {
+            bail!(
+                "Unsupported key derivation settings: {} KiB, {} iterations, {} lanes \
+                 (at most {} KiB, {} iterations and {} lanes)",
+                memory,
+                iterations,
+                parallelism,
+                MAX_MEMORY_KIB,
+                MAX_ITERATIONS,
+                MAX_PARALLELISM
+            );
+        }
+        let params = Params::new(memory, iterations, parallelism, Some(32))
+            .map_err(|e| anyhow!("Unsupported key derivation settings: {}", e))?;
+
+        if state.secret.is_none() {
+            state.secret = Some(self.source.secret()?);
+        }
+        let secret = state.secret.as_ref().unwrap();
+
+        let mut key = Zeroizing::new([0u8; 32]);
+        Argon2::new(Algorithm::Argon2id, Version::V0x13, params)
+            .hash_password_into(secret, &header[MAGIC.len() + 12..], key.as_mut())
+            .map_err(|e| anyhow!("Failed to derive key: {}", e))?;
+        Ok(DerivedKey {
+            header: *header,
+            key,
+        })
+    }
+}
+
+impl KeySource {
+    fn secret(&self) -> Result<Zeroizing<Vec<u8>>> {
+        match self {
+            KeySource::KeyFile(path) => read_key_file(path),
+            KeySource::Passphrase(passphrase) => Ok(Zeroizing::new(passphrase.as_bytes().to_vec())),
+            KeySource::Auto => {
+                if let Some(path) = std::env::var_os(KEY_FILE_ENV) {
+                    return read_key_file(Path::new(&path));
+                }
+                if let Ok(passphrase) = std::env::var(PASSPHRASE_ENV) {
+                    return Ok(Zeroizing::new(passphrase.into_bytes()));
+                }
+
+                let config = Config::load()?.encryption;
+                if let Some(path) = &config.key_file {
+                    return read_key_file(path);
+                }
+                if let Some(command) = &config.passphrase_command {
+                    return run_passphrase_command(command);
+                }
+                if std::io::stdin().is_terminal() {
+                    let passphrase = Zeroizing::new(
+                        rpassword::prompt_password("Passphrase for reminders: ")
+                            .context("Failed to read passphrase")?,
+                    );
+                    return Ok(Zeroizing::new(passphrase.as_bytes().to_vec()));
+                }
+
+                bail!(
+                    "The reminders store is encrypted and no key is available; set \
+                     encryption.key_file or encryption.passphrase_command with \
+                     `reminder config set`, or {} or {}",
+                    KEY_FILE_ENV,
+                    PASSPHRASE_ENV
+                )
+            }
+        }
+    }
+}
+
+/// The secret in a key file, without surrounding whitespace
+fn read_key_file(path: &Path) -> Result<Zeroizing<Vec<u8>>> {
+    let content = Zeroizing::new(
+        std::fs::read(path)
+            .with_context(|| format!("Failed to read key file {}", path.display()))?,
+    );
+    let secret = content.trim_ascii();
+    if secret.is_empty() {
+        bail!("Key file {} is empty", path.display());
+    }
+    Ok(Zeroizing::new(secret.to_vec()))
+}
+
+fn run_passphrase_command(command: &str) -> Result<Zeroizing<Vec<u8>>> {
+    let output = Command::new("sh")
+        .arg("-c")
+        .arg(command)
+        .output()
+        .with_context(|| format!("Failed to run passphrase command '{}'", command))?;
+    if !output.status.success() {
+        bail!(
+            "Passphrase command '{}' failed: {}",
+            command,
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    let stdout = Zeroizing::new(output.stdout);
+    let secret = stdout.trim_ascii_end();
+    if secret.is_empty() {
+        bail!("Passphrase command '{}' printed nothing", command);
+    }
+    Ok(Zeroizing::new(secret.to_vec()))
+}
+
+/// Write a new random key to `path`, readable only by the owner. Refuses
+/// to replace an existing file.
+pub fn generate_key_file(path: &Path) -> Result<()> {
+    let mut key = Zeroizing::new([0u8; 32]);
+    OsRng.fill_bytes(key.as_mut());
+    let hex: Zeroizing<String> = Zeroizing::new(key.iter().map(|b| format!("{:02x}", b)).collect());
+
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    let mut file = options
+        .open(path)
+        .with_context(|| format!("Failed to create key file {}", path.display()))?;
+    std::io::Write::write_all(&mut file, format!("{}\n", hex.as_str()).as_bytes())
+        .context("Failed to write key file")?;
+    file.sync_all().context("Failed to sync key file")?;
+    Ok(())
+}
+
+/// Ask twice for a new passphrase on the terminal
+pub fn prompt_new_passphrase() -> Result<Zeroizing<String>> {
+    if !std::io::stdin().is_terminal() {
+        bail!(
+            "No terminal to ask for a passphrase; use --key-file or {}",
+            PASSPHRASE_ENV
+        );
+    }
+    let passphrase = Zeroizing::new(
+        rpassword::prompt_password("New passphrase: ").context("Failed to read passphrase")?,
+    );
+    if passphrase.is_empty() {
+        bail!("The passphrase must not be empty");
+    }
+    let again = Zeroizing::new(
+        rpassword::prompt_password("Repeat passphrase: ").context("Failed to read passphrase")?,
+    );
+    if passphrase != again {
+        bail!("Passphrases do not match");
+    }
+    Ok(passphrase)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_trip_and_wrong_secret() {
+        let vault = Vault::new(KeySource::Passphrase(Zeroizing::new(
+            "correct horse".into(),
+        )));
+        let sealed = vault.encrypt(b"[]").unwrap();
+        assert!(is_encrypted(&sealed));
+        assert_eq!(vault.decrypt(&sealed).unwrap(), b"[]");
+
+        // A fresh vault derives the key again from the stored salt
+        let again = Vault::new(KeySource::Passphrase(Zeroizing::new(
+            "correct horse".into(),
+        )));
+        assert_eq!(again.decrypt(&sealed).unwrap(), b"[]");
+
+        let wrong = Vault::new(KeySource::Passphrase(Zeroizing::new("battery".into())));
+        assert!(wrong.decrypt(&sealed).is_err());
+
+        let mut tampered = sealed.clone();
+        *tampered.last_mut().unwrap() ^= 1;
+        assert!(vault.decrypt(&tampered).is_err());
+    }
+
+    #[test]
+    fn test_rejects_excessive_key_derivation_cost() {
+        let vault = Vault::new(KeySource::Passphrase(Zeroizing::new(
+            "correct horse".into(),
+        )));
+        let mut sealed = vault.encrypt(b"[]").unwrap();
+        sealed[MAGIC.len()..MAGIC.len() + 4].copy_from_slice(&u32::MAX.to_le_bytes());
+
+        let fresh = Vault::new(KeySource::Passphrase(Zeroizing::new(
+            "correct horse".into(),
+        )));
+        let err = fresh.decrypt(&sealed).unwrap_err();
+        assert!(err
+            .to_string()
+            .contains("Unsupported key derivation settings"));
+    }
+}
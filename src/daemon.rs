@@ -1,19 +1,78 @@
-use crate::notification::send_notification;
-use crate::storage::Storage;
-use crate::{log_debug, log_error, log_info};
-use anyhow::{Context, Result};
+use crate::backup;
+use crate::config::{Config, ConfigWatcher};
+use crate::index::ReminderIndex;
+use crate::notification::{open_link, NotificationAction, SNOOZE_MINUTES};
+use crate::notifier::NotifierRegistry;
+use crate::paths;
+use crate::retry::{deliver_with_retry, retry_due, DeadLetterQueue};
+use crate::storage::{is_conflict_copy, ReminderStore, Storage, FAILED_FILE};
+use crate::watch::FileWatcher;
+use crate::{log_debug, log_error, log_info, log_warn};
+use anyhow::{bail, Context, Result};
 use chrono::Local;
+use serde::{Deserialize, Serialize};
 use std::fs;
+use std::path::{Path, PathBuf};
 use std::process::{Command, Stdio};
-use std::thread;
-use std::time::Duration;
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+use uuid::Uuid;
+
+/// How often the daemon checks whether a daily backup is due
+const BACKUP_CHECK_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// `[daemon]` timing settings, reloaded by a running daemon
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DaemonConfig {
+    /// Watch the store for changes and sleep until the next reminder is
+    /// due, instead of re-reading it every poll interval. Read at startup.
+    pub watch: bool,
+
+    /// Seconds between checks for due reminders when not watching, or
+    /// when watching is unavailable
+    pub poll_interval_secs: u64,
+
+    /// Seconds between heartbeat writes
+    pub heartbeat_interval_secs: u64,
+
+    /// Seconds without a heartbeat before the daemon counts as unhealthy
+    pub heartbeat_timeout_secs: u64,
+}
+
+impl Default for DaemonConfig {
+    fn default() -> Self {
+        Self {
+            watch: true,
+            poll_interval_secs: 10,
+            heartbeat_interval_secs: 30,
+            heartbeat_timeout_secs: 120,
+        }
+    }
+}
 
-const POLL_INTERVAL_SECS: u64 = 10;
-const HEARTBEAT_INTERVAL_SECS: u64 = 30;
-const HEARTBEAT_TIMEOUT_SECS: u64 = 120;
+impl DaemonConfig {
+    pub fn validate(&self) -> Result<()> {
+        if self.poll_interval_secs == 0 {
+            bail!("daemon.poll_interval_secs must be at least 1");
+        }
+        if self.heartbeat_interval_secs == 0 {
+            bail!("daemon.heartbeat_interval_secs must be at least 1");
+        }
+        if self.heartbeat_timeout_secs <= self.heartbeat_interval_secs {
+            bail!(
+                "daemon.heartbeat_timeout_secs ({}) must be greater than daemon.heartbeat_interval_secs ({})",
+                self.heartbeat_timeout_secs,
+                self.heartbeat_interval_secs
+            );
+        }
+        Ok(())
+    }
+}
 
 pub fn start_daemon() -> Result<()> {
-    let pid_file = Storage::pid_file_path()?;
+    let pid_file = paths::pid_file()?;
 
     if is_daemon_running()? {
         println!("Daemon is already running");
@@ -38,7 +97,7 @@ pub fn start_daemon() -> Result<()> {
 }
 
 pub fn stop_daemon() -> Result<()> {
-    let pid_file = Storage::pid_file_path()?;
+    let pid_file = paths::pid_file()?;
 
     if !pid_file.exists() {
         println!("Daemon is not running");
@@ -68,10 +127,10 @@ pub fn stop_daemon() -> Result<()> {
 
 pub fn daemon_status() -> Result<()> {
     let running = is_daemon_running()?;
-    let healthy = is_daemon_healthy()?;
+    let healthy = is_daemon_healthy(&Config::load()?)?;
 
     if running {
-        let pid_file = Storage::pid_file_path()?;
+        let pid_file = paths::pid_file()?;
         let pid = fs::read_to_string(&pid_file)?;
         println!("Daemon is running (PID: {})", pid.trim());
 
@@ -82,7 +141,7 @@ pub fn daemon_status() -> Result<()> {
         }
 
         // Show last heartbeat time
-        if let Ok(heartbeat_path) = Storage::heartbeat_file_path() {
+        if let Ok(heartbeat_path) = paths::heartbeat_file() {
             if heartbeat_path.exists() {
                 if let Ok(content) = fs::read_to_string(&heartbeat_path) {
                     if let Ok(timestamp) = content.trim().parse::<i64>() {
@@ -105,7 +164,7 @@ pub fn daemon_status() -> Result<()> {
 }
 
 pub fn is_daemon_running() -> Result<bool> {
-    let pid_file = Storage::pid_file_path()?;
+    let pid_file = paths::pid_file()?;
 
     if !pid_file.exists() {
         return Ok(false);
@@ -157,14 +216,14 @@ pub fn is_daemon_running() -> Result<bool> {
 
 
 fn write_heartbeat() {
-    if let Ok(heartbeat_path) = Storage::heartbeat_file_path() {
+    if let Ok(heartbeat_path) = paths::heartbeat_file() {
         let timestamp = Local::now().timestamp().to_string();
         let _ = fs::write(heartbeat_path, timestamp);
     }
 }
 
-fn check_heartbeat() -> Result<bool> {
-    let heartbeat_path = Storage::heartbeat_file_path()?;
+fn check_heartbeat(config: &Config) -> Result<bool> {
+    let heartbeat_path = paths::heartbeat_file()?;
 
     if !heartbeat_path.exists() {
         return Ok(false);
@@ -174,67 +233,286 @@ fn check_heartbeat() -> Result<bool> {
     let timestamp: i64 = content.trim().parse().unwrap_or(0);
     let now = Local::now().timestamp();
 
-    Ok((now - timestamp) < HEARTBEAT_TIMEOUT_SECS as i64)
+    Ok((now - timestamp) < config.daemon.heartbeat_timeout_secs as i64)
 }
 
-pub fn is_daemon_healthy() -> Result<bool> {
+pub fn is_daemon_healthy(config: &Config) -> Result<bool> {
     if !is_daemon_running()? {
         return Ok(false);
     }
-    check_heartbeat()
+    check_heartbeat(config)
 }
 
 pub fn run_daemon_loop() -> Result<()> {
     let storage = Storage::new()?;
+    let registry = NotifierRegistry::with_defaults(storage.shared());
+    run_daemon_loop_with(&storage, registry)
+}
+
+/// Run the daemon loop over `storage`, delivering through the given notifiers
+pub fn run_daemon_loop_with(storage: &dyn ReminderStore, registry: NotifierRegistry) -> Result<()> {
+    run_daemon_until(storage, registry, None)
+}
+
+/// The daemon loop, returning once `until` has passed if given. While
+/// watching, the store is only read again after it changes on disk; in
+/// between, the loop sleeps until the next reminder or retry is due or the
+/// next heartbeat.
+pub fn run_daemon_until(
+    storage: &dyn ReminderStore,
+    registry: NotifierRegistry,
+    until: Option<Instant>,
+) -> Result<()> {
+    let registry = Arc::new(registry);
     log_info!("Daemon started");
     write_heartbeat();
 
-    let mut heartbeat_counter = 0u64;
+    let config_file = paths::config_file()?;
+    let mut config = ConfigWatcher::new(config_file.clone());
+    refresh_config(&mut config);
+    let watcher = match storage.path() {
+        Some(path) if config.current().daemon.watch => watch_store(path, &config_file),
+        _ => None,
+    };
 
-    loop {
-        match storage.load() {
-            Ok(mut reminders) => {
-                let mut updated = false;
+    let queue = DeadLetterQueue::for_storage(storage);
 
-                for reminder in reminders.iter_mut() {
-                    if reminder.is_due() {
-                        log_info!("Triggering reminder: {}", reminder.title);
+    // Reminders as last read; `None` once the store may have changed
+    let mut cached: Option<Arc<ReminderIndex>> = None;
+    let mut next_heartbeat = Instant::now();
+    let mut next_backup_check = Instant::now();
 
-                        if let Err(e) = send_notification(reminder) {
-                            log_error!("Failed to send notification: {}", e);
-                        }
-                        reminder.calculate_next_trigger();
-                        updated = true;
-                    }
-                }
+    loop {
+        refresh_config(&mut config);
+        let timing = config.current().daemon.clone();
 
-                if updated {
-                    if let Err(e) = storage.save(&reminders) {
-                        log_error!("Failed to save reminders: {}", e);
-                    }
-                }
+        if cached.is_none() {
+            match storage.index() {
+                Ok(index) => cached = Some(index),
+                Err(e) => log_error!("Failed to load reminders: {}", e),
             }
-            Err(e) => {
-                log_error!("Failed to load reminders: {}", e);
+        }
+        if cached
+            .as_ref()
+            .is_none_or(|index| !index.due(Local::now()).is_empty())
+        {
+            if let Err(e) = fire_due_reminders(storage, &registry, config.current()) {
+                log_error!("Failed to process reminders: {}", e);
             }
+            cached = None;
+        }
+        if let Err(e) = retry_due(&registry, config.current(), &queue) {
+            log_error!("Failed to retry deliveries: {:#}", e);
         }
 
-        // Write heartbeat periodically
-        heartbeat_counter += POLL_INTERVAL_SECS;
-        if heartbeat_counter >= HEARTBEAT_INTERVAL_SECS {
+        let now = Instant::now();
+        if now >= next_heartbeat {
             write_heartbeat();
             log_debug!("Heartbeat written");
-            heartbeat_counter = 0;
+            next_heartbeat = now + Duration::from_secs(timing.heartbeat_interval_secs);
+        }
+        if now >= next_backup_check {
+            if let Some(path) = storage.path() {
+                daily_backup(path, &config_file, config.current());
+            }
+            next_backup_check = now + BACKUP_CHECK_INTERVAL;
+        }
+        if until.is_some_and(|until| now >= until) {
+            return Ok(());
+        }
+
+        let mut wait = next_heartbeat - now;
+        match (&watcher, &cached) {
+            (Some(_), Some(index)) => {
+                if let Some(next) = index.next_trigger() {
+                    let due_in = (next - Local::now()).to_std().unwrap_or_default();
+                    wait = wait.min(due_in);
+                }
+            }
+            _ => wait = wait.min(Duration::from_secs(timing.poll_interval_secs)),
+        }
+        if let Ok(Some(next)) = queue.next_attempt() {
+            wait = wait.min((next - Local::now()).to_std().unwrap_or_default());
+        }
+        if let Some(until) = until {
+            wait = wait.min(until.saturating_duration_since(now));
+        }
+        // Never spin, even if a trigger is a hair away
+        let wait = wait.max(Duration::from_millis(10));
+
+        match &watcher {
+            Some(watcher) => {
+                if watcher.wait(wait) {
+                    log_debug!("Store changed on disk");
+                    cached = None;
+                }
+            }
+            None => {
+                thread::sleep(wait);
+                cached = None;
+            }
         }
+    }
+}
+
+/// Snapshot the data directory if the newest snapshot is a day old
+fn daily_backup(store: &Path, config_file: &Path, config: &Config) {
+    match backup::run_daily(store, config_file, &config.backup) {
+        Ok(Some((snapshot, pruned))) => {
+            log_info!("Created daily backup {}", snapshot.name());
+            if !pruned.is_empty() {
+                log_info!("Pruned {} old backup(s)", pruned.len());
+            }
+        }
+        Ok(None) => {}
+        Err(e) => log_error!("Daily backup failed: {:#}", e),
+    }
+}
+
+/// Pick up edits to config.toml without a restart
+fn refresh_config(config: &mut ConfigWatcher) {
+    match config.refresh() {
+        Ok(true) => {
+            log_info!("Config loaded");
+            crate::logger::get_logger().set_max_size(config.current().log.max_size_bytes());
+        }
+        Ok(false) => {}
+        Err(e) => log_warn!("Keeping previous config: {:#}", e),
+    }
+}
+
+/// Watch the store, its SQLite journal, conflict copies left by sync tools,
+/// the failed deliveries and the config file. Falls back to polling if
+/// watching is unavailable.
+fn watch_store(store: &Path, config_file: &Path) -> Option<FileWatcher> {
+    let canonical = |path: &Path| -> Option<PathBuf> {
+        let dir = match path.parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir,
+            _ => Path::new("."),
+        };
+        Some(dir.canonicalize().ok()?.join(path.file_name()?))
+    };
+    let (Some(store), Some(config_file)) = (canonical(store), canonical(config_file)) else {
+        log_warn!("Cannot watch the store; polling instead");
+        return None;
+    };
+
+    let mut journal = store.clone().into_os_string();
+    journal.push("-wal");
+    let journal = PathBuf::from(journal);
+    let failed = store.with_file_name(FAILED_FILE);
+    let dirs: Vec<PathBuf> = [store.parent(), config_file.parent()]
+        .into_iter()
+        .flatten()
+        .map(Path::to_path_buf)
+        .collect();
+
+    let relevant = {
+        let store = store.clone();
+        move |path: &Path| {
+            path == store
+                || path == journal
+                || path == failed
+                || path == config_file
+                || is_conflict_copy(&store, path)
+        }
+    };
+    match FileWatcher::new(dirs.iter().map(PathBuf::as_path), relevant) {
+        Ok(watcher) => {
+            log_info!("Watching {} for changes", store.display());
+            Some(watcher)
+        }
+        Err(e) => {
+            log_warn!("Cannot watch the store, polling instead: {:#}", e);
+            None
+        }
+    }
+}
+
+/// Fire every due reminder and advance its schedule. Deliveries run on
+/// background threads with `config`; the returned handles finish once they
+/// are done.
+pub fn fire_due_reminders(
+    storage: &dyn ReminderStore,
+    registry: &Arc<NotifierRegistry>,
+    config: &Config,
+) -> Result<Vec<JoinHandle<()>>> {
+    // Advance every due reminder in one locked step so concurrent edits
+    // are neither lost nor overwritten; deliver once that is saved
+    let mut fired = Vec::new();
+    storage
+        .update_due(&mut |reminder| {
+            reminder.occurrences += 1;
+            fired.push(reminder.clone());
+            reminder.calculate_next_trigger();
+        })
+        .context("Failed to update due reminders")?;
+
+    let mut deliveries = Vec::new();
+    for reminder in fired {
+        log_info!("Triggering reminder: {}", reminder.title);
+
+        let (registry, config) = (Arc::clone(registry), config.clone());
+        let queue = DeadLetterQueue::for_storage(storage);
+        deliveries.push(thread::spawn(move || {
+            deliver_with_retry(&registry, &reminder, &config, &queue);
+        }));
+    }
+
+    Ok(deliveries)
+}
+
+/// Apply the button a user picked on a fired reminder's notification
+pub fn handle_notification_action(
+    storage: &dyn ReminderStore,
+    id: Uuid,
+    action: NotificationAction,
+) -> Result<()> {
+    log_info!("Notification action '{}' for reminder {}", action.key(), id);
+
+    match action {
+        NotificationAction::Snooze => {
+            let snooze = chrono::Duration::minutes(SNOOZE_MINUTES);
+            if !storage.update(id, Box::new(|r| r.snooze(snooze)))? {
+                log_warn!("Snooze ignored: reminder {} no longer exists", id);
+            }
+        }
+        NotificationAction::Done => {
+            if !storage.update(id, Box::new(|r| r.mark_done()))? {
+                log_warn!("Done ignored: reminder {} no longer exists", id);
+            }
+        }
+        NotificationAction::Open => {
+            let reminder = storage.get(id)?;
+            match reminder.as_ref().and_then(|r| r.link()) {
+                Some(link) => open_link(link)?,
+                None => log_warn!("Open ignored: reminder {} has no link", id),
+            }
+        }
+    }
+
+    Ok(())
+}
 
-        thread::sleep(Duration::from_secs(POLL_INTERVAL_SECS));
+/// `--data-dir <dir>` when a data directory was chosen, so the auto-started
+/// daemon uses the same one
+#[cfg(any(target_os = "macos", target_os = "linux"))]
+fn data_dir_args() -> Result<Vec<String>> {
+    if std::env::var_os(paths::DATA_DIR_ENV).is_none() {
+        return Ok(Vec::new());
     }
+    let dir = paths::data_dir()?;
+    Ok(vec!["--data-dir".to_string(), dir.display().to_string()])
 }
 
 /// Generate launchd plist for macOS auto-start
 #[cfg(target_os = "macos")]
 pub fn generate_launchd_plist() -> Result<String> {
     let exe = std::env::current_exe()?;
+    let extra_args: String = data_dir_args()?
+        .iter()
+        .map(|arg| format!("\n        <string>{}</string>", arg))
+        .collect();
     let plist = format!(
         r#"<?xml version="1.0" encoding="UTF-8"?>
 <!DOCTYPE plist PUBLIC "-//Apple//DTD PLIST 1.0//EN" "http://www.apple.com/DTDs/PropertyList-1.0.dtd">
@@ -244,7 +522,7 @@ pub fn generate_launchd_plist() -> Result<String> {
     <string>com.reminder-cli.daemon</string>
     <key>ProgramArguments</key>
     <array>
-        <string>{}</string>
+        <string>{}</string>{}
         <string>daemon</string>
         <string>run</string>
     </array>
@@ -254,7 +532,8 @@ pub fn generate_launchd_plist() -> Result<String> {
     <true/>
 </dict>
 </plist>"#,
-        exe.display()
+        exe.display(),
+        extra_args
     );
     Ok(plist)
 }
@@ -263,6 +542,10 @@ pub fn generate_launchd_plist() -> Result<String> {
 #[cfg(target_os = "linux")]
 pub fn generate_systemd_service() -> Result<String> {
     let exe = std::env::current_exe()?;
+    let extra_args: String = data_dir_args()?
+        .iter()
+        .map(|arg| format!(" \"{}\"", arg))
+        .collect();
     let service = format!(
         r#"[Unit]
 Description=Reminder CLI Daemon
@@ -270,13 +553,14 @@ After=network.target
 
 [Service]
 Type=simple
-ExecStart={} daemon run
+ExecStart={}{} daemon run
 Restart=always
 RestartSec=10
 
 [Install]
 WantedBy=default.target"#,
-        exe.display()
+        exe.display(),
+        extra_args
     );
     Ok(service)
 }
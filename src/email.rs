@@ -0,0 +1,134 @@
+use crate::config::Config;
+use crate::reminder::Reminder;
+use crate::template;
+use anyhow::{Context, Result};
+use lettre::message::Mailbox;
+use lettre::transport::smtp::authentication::Credentials;
+use lettre::{Message, SmtpTransport, Transport};
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+pub const DEFAULT_SMTP_PORT: u16 = 587;
+pub const DEFAULT_PASSWORD_ENV: &str = "REMINDER_SMTP_PASSWORD";
+pub const DEFAULT_SMTP_TIMEOUT_SECS: u64 = 30;
+
+/// SMTP server used by the `email` channel
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct EmailConfig {
+    pub host: String,
+
+    #[serde(default = "default_port")]
+    pub port: u16,
+
+    /// Upgrade the connection with STARTTLS (required when enabled)
+    #[serde(default = "default_starttls")]
+    pub starttls: bool,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub username: Option<String>,
+
+    /// Environment variable holding the password, so it never sits in the
+    /// config file
+    #[serde(default = "default_password_env")]
+    pub password_env: String,
+
+    pub from: String,
+
+    pub to: Vec<String>,
+
+    #[serde(default = "default_timeout")]
+    pub timeout_secs: u64,
+}
+
+fn default_port() -> u16 {
+    DEFAULT_SMTP_PORT
+}
+
+fn default_starttls() -> bool {
+    true
+}
+
+fn default_password_env() -> String {
+    DEFAULT_PASSWORD_ENV.to_string()
+}
+
+fn default_timeout() -> u64 {
+    DEFAULT_SMTP_TIMEOUT_SECS
+}
+
+pub fn send_email(email: &EmailConfig, reminder: &Reminder, config: &Config) -> Result<()> {
+    let message = build_message(
+        email,
+        reminder,
+        &template::Message::render(reminder, config),
+    )?;
+
+    let mut builder = if email.starttls {
+        SmtpTransport::starttls_relay(&email.host)
+            .with_context(|| format!("Invalid SMTP host: {}", email.host))?
+    } else {
+        SmtpTransport::builder_dangerous(&email.host)
+    };
+    builder = builder
+        .port(email.port)
+        .timeout(Some(Duration::from_secs(email.timeout_secs)));
+
+    if let Some(username) = &email.username {
+        let password = std::env::var(&email.password_env).with_context(|| {
+            format!(
+                "SMTP password not found in environment variable {}",
+                email.password_env
+            )
+        })?;
+        builder = builder.credentials(Credentials::new(username.clone(), password));
+    }
+
+    builder
+        .build()
+        .send(&message)
+        .with_context(|| format!("SMTP delivery via {}:{} failed", email.host, email.port))?;
+
+    Ok(())
+}
+
+fn build_message(
+    config: &EmailConfig,
+    reminder: &Reminder,
+    text: &template::Message,
+) -> Result<Message> {
+    let from: Mailbox = config
+        .from
+        .parse()
+        .with_context(|| format!("Invalid sender address: {}", config.from))?;
+
+    let mut builder = Message::builder()
+        .from(from)
+        .subject(format!("Reminder: {}", text.summary));
+    for to in &config.to {
+        let mailbox: Mailbox = to
+            .parse()
+            .with_context(|| format!("Invalid recipient address: {}", to))?;
+        builder = builder.to(mailbox);
+    }
+
+    let mut body = text.summary.clone();
+    if !text.body.is_empty() {
+        body.push_str("\n\n");
+        body.push_str(&text.body);
+    }
+    if let Some(next) = reminder.next_trigger {
+        body.push_str(&format!(
+            "\n\nScheduled: {}",
+            next.format("%Y-%m-%d %H:%M:%S")
+        ));
+    }
+    if !reminder.tags.is_empty() {
+        let mut tags: Vec<&str> = reminder.tags.iter().map(String::as_str).collect();
+        tags.sort();
+        body.push_str(&format!("\nTags: {}", tags.join(", ")));
+    }
+    body.push_str(&format!("\nID: {}\n", reminder.id));
+
+    builder.body(body).context("Failed to build email")
+}
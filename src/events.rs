@@ -0,0 +1,431 @@
+use crate::crypto::Vault;
+use crate::reminder::Reminder;
+use crate::storage::{write_atomic, JsonStore};
+use anyhow::{Context, Result};
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use chrono::{DateTime, Local};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, OnceLock};
+use uuid::Uuid;
+
+pub const EVENTS_FILE: &str = "history.jsonl";
+
+/// Start of a line encrypted with the store's key, followed by the
+/// ciphertext in base64
+const SEALED: &str = "sealed:";
+
+/// Fields stores update on every change; replaying an event updates them
+/// again instead of taking them from the log
+const BOOKKEEPING: [&str; 2] = ["revision", "updated_at"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum EventKind {
+    /// Already in the store when the log was started
+    Existing,
+    Created,
+    Edited,
+    Paused,
+    Resumed,
+    Fired,
+    Deleted,
+    Imported,
+    /// Brought in or changed by merging another copy of the store
+    Merged,
+}
+
+impl std::fmt::Display for EventKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            EventKind::Existing => "existing",
+            EventKind::Created => "created",
+            EventKind::Edited => "edited",
+            EventKind::Paused => "paused",
+            EventKind::Resumed => "resumed",
+            EventKind::Fired => "fired",
+            EventKind::Deleted => "deleted",
+            EventKind::Imported => "imported",
+            EventKind::Merged => "merged",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// What made a change, where the change alone does not tell
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Cause {
+    Edit,
+    Import,
+    Fire,
+    Merge,
+}
+
+/// One field before and after a change; `null` where it was unset
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Change {
+    pub old: Value,
+    pub new: Value,
+}
+
+/// One line of the event log
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Event {
+    pub at: DateTime<Local>,
+    pub user: String,
+    pub host: String,
+    pub kind: EventKind,
+    pub id: Uuid,
+    pub title: String,
+    /// Changed fields of an existing reminder
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub changes: BTreeMap<String, Change>,
+    /// The whole reminder, for events that bring one in
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reminder: Option<Reminder>,
+}
+
+impl Event {
+    fn new(kind: EventKind, reminder: &Reminder) -> Self {
+        let (user, host) = actor();
+        Self {
+            at: Local::now(),
+            user: user.clone(),
+            host: host.clone(),
+            kind,
+            id: reminder.id,
+            title: reminder.title.clone(),
+            changes: BTreeMap::new(),
+            reminder: None,
+        }
+    }
+
+    fn with_reminder(kind: EventKind, reminder: &Reminder) -> Self {
+        Self {
+            reminder: Some(reminder.clone()),
+            ..Self::new(kind, reminder)
+        }
+    }
+}
+
+/// The user and host changes are made by, looked up once
+fn actor() -> &'static (String, String) {
+    static ACTOR: OnceLock<(String, String)> = OnceLock::new();
+    ACTOR.get_or_init(|| {
+        let user = crate::terminal::current_user().unwrap_or_else(|| "unknown".to_string());
+        let host = hostname::get()
+            .ok()
+            .and_then(|h| h.into_string().ok())
+            .unwrap_or_else(|| "unknown".to_string());
+        (user, host)
+    })
+}
+
+/// Events turning `before` into `after`
+pub fn diff(before: &[Reminder], after: &[Reminder], cause: Cause) -> Vec<Event> {
+    let old: HashMap<Uuid, &Reminder> = before.iter().map(|r| (r.id, r)).collect();
+    let live: BTreeSet<Uuid> = after.iter().map(|r| r.id).collect();
+
+    let mut events = Vec::new();
+    for reminder in after {
+        let Some(previous) = old.get(&reminder.id) else {
+            let kind = match cause {
+                Cause::Import => EventKind::Imported,
+                Cause::Merge => EventKind::Merged,
+                _ => EventKind::Created,
+            };
+            events.push(Event::with_reminder(kind, reminder));
+            continue;
+        };
+
+        let changes = changed_fields(previous, reminder);
+        if changes.is_empty() {
+            continue;
+        }
+        let mut event = match cause {
+            // Overwritten by an import: keep the whole new version
+            Cause::Import => Event::with_reminder(EventKind::Imported, reminder),
+            Cause::Fire => Event::new(EventKind::Fired, reminder),
+            // Keeps the version merged in, revision and all
+            Cause::Merge => Event::with_reminder(EventKind::Merged, reminder),
+            Cause::Edit => Event::new(classify(&changes, reminder), reminder),
+        };
+        event.changes = changes;
+        events.push(event);
+    }
+
+    for reminder in before.iter().filter(|r| !live.contains(&r.id)) {
+        events.push(Event::new(EventKind::Deleted, reminder));
+    }
+    events
+}
+
+/// Pausing and resuming only touch `paused` and the next trigger
+fn classify(changes: &BTreeMap<String, Change>, reminder: &Reminder) -> EventKind {
+    let only_pause = changes.contains_key("paused")
+        && changes.keys().all(|f| f == "paused" || f == "next_trigger");
+    match (only_pause, reminder.paused) {
+        (true, true) => EventKind::Paused,
+        (true, false) => EventKind::Resumed,
+        _ => EventKind::Edited,
+    }
+}
+
+fn to_fields(reminder: &Reminder) -> Map<String, Value> {
+    match serde_json::to_value(reminder) {
+        Ok(Value::Object(fields)) => fields,
+        _ => Map::new(),
+    }
+}
+
+fn changed_fields(before: &Reminder, after: &Reminder) -> BTreeMap<String, Change> {
+    let (old, new) = (to_fields(before), to_fields(after));
+    old.keys()
+        .chain(new.keys())
+        .filter(|key| !BOOKKEEPING.contains(&key.as_str()))
+        .filter(|key| old.get(*key) != new.get(*key))
+        .map(|key| {
+            let value = |fields: &Map<String, Value>| fields.get(key).cloned().unwrap_or_default();
+            (
+                key.clone(),
+                Change {
+                    old: value(&old),
+                    new: value(&new),
+                },
+            )
+        })
+        .collect()
+}
+
+/// Events recording `reminders` as the starting point of a new log
+pub fn existing(reminders: &[Reminder]) -> Vec<Event> {
+    reminders
+        .iter()
+        .map(|r| Event::with_reminder(EventKind::Existing, r))
+        .collect()
+}
+
+/// Replay `events` from an empty store. Each change bumps the revision
+/// and sets the change time, as the JSON store does when saving it.
+pub fn replay(events: &[Event]) -> Result<Vec<Reminder>> {
+    let mut reminders: Vec<Reminder> = Vec::new();
+    for event in events {
+        let position = reminders.iter().position(|r| r.id == event.id);
+        let i = match (event.kind, &event.reminder, position) {
+            (EventKind::Deleted, _, Some(i)) => {
+                reminders.remove(i);
+                continue;
+            }
+            (EventKind::Deleted, _, None) => continue,
+            (_, Some(reminder), Some(i)) => {
+                reminders[i] = reminder.clone();
+                i
+            }
+            (_, Some(reminder), None) => {
+                reminders.push(reminder.clone());
+                reminders.len() - 1
+            }
+            (_, None, Some(i)) => {
+                let mut fields = to_fields(&reminders[i]);
+                for (key, change) in &event.changes {
+                    match &change.new {
+                        Value::Null => fields.remove(key),
+                        value => fields.insert(key.clone(), value.clone()),
+                    };
+                }
+                reminders[i] =
+                    serde_json::from_value(Value::Object(fields)).with_context(|| {
+                        format!(
+                            "Failed to apply {} event of {} at {}",
+                            event.kind, event.id, event.at
+                        )
+                    })?;
+                i
+            }
+            (_, None, None) => {
+                anyhow::bail!(
+                    "The log has a {} event for {} at {}, but no event creating it",
+                    event.kind,
+                    event.id,
+                    event.at
+                );
+            }
+        };
+        if event.kind != EventKind::Existing {
+            reminders[i].revision += 1;
+            reminders[i].updated_at = Some(event.at);
+        }
+    }
+    Ok(reminders)
+}
+
+/// Append-only log of every change to the reminders, one JSON event per
+/// line. A log kept next to an encrypted store encrypts each line with
+/// the store's key.
+#[derive(Debug, Clone)]
+pub struct EventLog {
+    path: PathBuf,
+    /// The store whose encryption the log follows
+    store: Option<PathBuf>,
+    vault: Arc<Vault>,
+}
+
+impl EventLog {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self {
+            path: path.into(),
+            store: None,
+            vault: Arc::default(),
+        }
+    }
+
+    /// The log next to `store`, encrypted with `vault` while the store is
+    pub fn for_store(store: &Path, vault: Arc<Vault>) -> Self {
+        Self {
+            path: store.with_file_name(EVENTS_FILE),
+            store: Some(store.to_path_buf()),
+            vault,
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn exists(&self) -> bool {
+        self.path.exists()
+    }
+
+    fn is_sealed(&self) -> bool {
+        self.store
+            .as_ref()
+            .is_some_and(|store| JsonStore::new(store).is_encrypted())
+    }
+
+    /// Add `events` to the end in a single write, so concurrent writers
+    /// never interleave lines
+    pub fn append(&self, events: &[Event]) -> Result<()> {
+        if events.is_empty() {
+            return Ok(());
+        }
+        let vault = self.is_sealed().then_some(self.vault.as_ref());
+        let content = encode(events, vault)?;
+
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .context("Failed to open event log")?;
+        file.write_all(&content)
+            .context("Failed to write event log")?;
+        file.sync_data().context("Failed to sync event log")
+    }
+
+    /// Every event, oldest first
+    pub fn read(&self) -> Result<Vec<Event>> {
+        if !self.path.exists() {
+            return Ok(Vec::new());
+        }
+        let content = fs::read_to_string(&self.path).context("Failed to read event log")?;
+        content
+            .lines()
+            .enumerate()
+            .filter(|(_, line)| !line.trim().is_empty())
+            .map(|(n, line)| {
+                self.parse(line).with_context(|| {
+                    format!("Invalid event on line {} of {}", n + 1, self.path.display())
+                })
+            })
+            .collect()
+    }
+
+    /// Rewrite every line encrypted with `vault`, or as plaintext for
+    /// `None`. Returns the number of events.
+    pub fn convert(&self, vault: Option<&Vault>) -> Result<usize> {
+        if !self.path.exists() {
+            return Ok(0);
+        }
+        let events = self.read()?;
+        write_atomic(&self.path, &encode(&events, vault)?)?;
+        Ok(events.len())
+    }
+
+    fn parse(&self, line: &str) -> Result<Event> {
+        match line.strip_prefix(SEALED) {
+            Some(sealed) => {
+                let data = BASE64
+                    .decode(sealed.trim())
+                    .context("Invalid encrypted event")?;
+                Ok(serde_json::from_slice(&self.vault.decrypt(&data)?)?)
+            }
+            None => Ok(serde_json::from_str(line)?),
+        }
+    }
+}
+
+/// `events` as lines of the log, each encrypted with `vault` if given
+fn encode(events: &[Event], vault: Option<&Vault>) -> Result<Vec<u8>> {
+    let mut content = Vec::new();
+    for event in events {
+        let json = serde_json::to_vec(event).context("Failed to serialize event")?;
+        match vault {
+            Some(vault) => {
+                content.extend_from_slice(SEALED.as_bytes());
+                content.extend_from_slice(BASE64.encode(vault.encrypt(&json)?).as_bytes());
+            }
+            None => content.extend_from_slice(&json),
+        }
+        content.push(b'\n');
+    }
+    Ok(content)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+
+    fn reminder(title: &str) -> Reminder {
+        Reminder::new_one_time(
+            title.to_string(),
+            None,
+            Local::now() + chrono::Duration::hours(1),
+            HashSet::new(),
+        )
+    }
+
+    #[test]
+    fn test_diff_and_replay() {
+        let (a, b) = (reminder("a"), reminder("b"));
+        let v1 = vec![a.clone(), b.clone()];
+        let mut events = diff(&[], &v1, Cause::Edit);
+
+        let mut v2 = v1.clone();
+        v2[0].pause();
+        v2[1].title = "b renamed".to_string();
+        v2[1].description = Some("details".to_string());
+        let edits = diff(&v1, &v2, Cause::Edit);
+        let kinds: Vec<EventKind> = edits.iter().map(|e| e.kind).collect();
+        assert_eq!(kinds, [EventKind::Paused, EventKind::Edited]);
+        assert_eq!(edits[1].changes["title"].old, "b");
+        assert_eq!(edits[1].changes["description"].old, Value::Null);
+        events.extend(edits);
+
+        let mut v3 = v2.clone();
+        v3.remove(0);
+        v3[0].description = None;
+        events.extend(diff(&v2, &v3, Cause::Edit));
+        assert_eq!(events.last().unwrap().kind, EventKind::Deleted);
+
+        let rebuilt = replay(&events).unwrap();
+        assert_eq!(rebuilt.len(), 1);
+        assert_eq!(rebuilt[0].id, b.id);
+        assert_eq!(rebuilt[0].title, "b renamed");
+        assert_eq!(rebuilt[0].description, None);
+    }
+}
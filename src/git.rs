@@ -0,0 +1,513 @@
+use crate::crypto::Vault;
+use crate::ids::Names;
+use crate::index::ReminderIndex;
+use crate::log_warn;
+use crate::merge::{Document, MergeReport};
+use crate::reminder::Reminder;
+use crate::storage::{Finish, JsonStore, Mutation, ReminderStore, Scope};
+use anyhow::{bail, Context, Result};
+use serde_json::{Map, Value};
+use std::collections::{BTreeSet, HashMap};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output};
+use std::sync::Arc;
+use uuid::Uuid;
+
+const REMOTE: &str = "origin";
+
+/// Only the reminders themselves are versioned; logs, locks, backups and
+/// machine-specific files stay out of the repository
+const GITIGNORE: &str = "*\n!reminders.json\n!.gitignore\n!.gitattributes\n";
+const GITATTRIBUTES: &str = "reminders.json merge=reminders\n";
+
+/// A data directory kept in git
+#[derive(Debug, Clone)]
+pub struct GitRepo {
+    dir: PathBuf,
+}
+
+impl GitRepo {
+    /// The repository at `dir`, if sync has been set up there
+    pub fn open(dir: &Path) -> Option<Self> {
+        dir.join(".git").exists().then(|| Self {
+            dir: dir.to_path_buf(),
+        })
+    }
+
+    /// Turn `dir` into a repository, commit what is there and, if given,
+    /// add `remote` as `origin`
+    pub fn init(dir: &Path, remote: Option<&str>) -> Result<Self> {
+        let repo = Self {
+            dir: dir.to_path_buf(),
+        };
+        if !dir.join(".git").exists() {
+            repo.git(&["init", "--quiet", "--initial-branch=main"])?;
+        }
+        // Commits need an identity; fall back to a local one
+        if !repo.run(&["config", "user.name"])?.status.success() {
+            repo.git(&["config", "user.name", "reminder-cli"])?;
+            repo.git(&["config", "user.email", "reminder-cli@localhost"])?;
+        }
+        fs::write(dir.join(".gitignore"), GITIGNORE).context("Failed to write .gitignore")?;
+        fs::write(dir.join(".gitattributes"), GITATTRIBUTES)
+            .context("Failed to write .gitattributes")?;
+        repo.commit_all("Start syncing reminders")?;
+
+        if let Some(remote) = remote {
+            repo.set_remote(remote)?;
+        }
+        Ok(repo)
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    pub fn set_remote(&self, url: &str) -> Result<()> {
+        if self.remote_url()?.is_some() {
+            self.git(&["remote", "set-url", REMOTE, url])?;
+        } else {
+            self.git(&["remote", "add", REMOTE, url])?;
+        }
+        Ok(())
+    }
+
+    pub fn remote_url(&self) -> Result<Option<String>> {
+        let output = self.run(&["remote", "get-url", REMOTE])?;
+        Ok(output
+            .status
+            .success()
+            .then(|| String::from_utf8_lossy(&output.stdout).trim().to_string()))
+    }
+
+    /// Commit every change in the directory. Returns whether there was
+    /// anything to commit.
+    pub fn commit_all(&self, message: &str) -> Result<bool> {
+        self.git(&["add", "--all"])?;
+        if self.run(&["diff", "--cached", "--quiet"])?.status.success() {
+            return Ok(false);
+        }
+        self.git(&["commit", "--quiet", "--message", message])?;
+        Ok(true)
+    }
+
+    /// Commit local changes, merge the remote's reminders into them and
+    /// push the result. Concurrent edits to `reminders.json` are merged
+    /// field by field by `merge_driver`, run by git as `exe`.
+    pub fn sync(&self, exe: &Path) -> Result<SyncReport> {
+        if self.remote_url()?.is_none() {
+            bail!("No remote to sync with; run `reminder sync init --remote <url>`");
+        }
+        let store = JsonStore::new(self.dir.join("reminders.json"));
+        if store.is_encrypted() {
+            bail!("Encrypted stores cannot be merged; run `reminder storage decrypt` first");
+        }
+
+        // Keep other commands and the daemon from writing mid-merge
+        let lock = store.lock()?;
+        self.commit_all("Sync local changes")?;
+
+        self.git(&[
+            "config",
+            "merge.reminders.driver",
+            &format!(
+                "\"{}\" sync merge-driver %O %A %B",
+                exe.display().to_string().replace('"', "\\\"")
+            ),
+        ])?;
+        self.git(&["fetch", "--quiet", REMOTE])?;
+
+        let branch = self.git(&["symbolic-ref", "--short", "HEAD"])?;
+        let upstream = format!("{}/{}", REMOTE, branch);
+        let mut report = SyncReport::default();
+
+        if self
+            .run(&["rev-parse", "--verify", "--quiet", &upstream])?
+            .status
+            .success()
+        {
+            report.pulled = self.count(&format!("HEAD..{}", upstream))?;
+            if report.pulled > 0 {
+                let merge = self.run(&[
+                    "merge",
+                    "--quiet",
+                    "--no-edit",
+                    "--allow-unrelated-histories",
+                    &upstream,
+                ])?;
+                if !merge.status.success() {
+                    let _ = self.run(&["merge", "--abort"]);
+                    bail!(
+                        "Failed to merge {}: {}",
+                        upstream,
+                        String::from_utf8_lossy(&merge.stderr).trim()
+                    );
+                }
+            }
+            report.pushed = self.count(&format!("{}..HEAD", upstream))?;
+        } else {
+            report.pushed = self.count("HEAD")?;
+        }
+
+        if report.pushed > 0 {
+            self.git(&["push", "--quiet", "--set-upstream", REMOTE, &branch])?;
+        }
+        lock.unlock().context("Failed to release lock")?;
+
+        Ok(report)
+    }
+
+    fn count(&self, range: &str) -> Result<usize> {
+        let count = self.git(&["rev-list", "--count", range])?;
+        count.parse().context("Unexpected output from git rev-list")
+    }
+
+    fn run(&self, args: &[&str]) -> Result<Output> {
+        Command::new("git")
+            .arg("-C")
+            .arg(&self.dir)
+            .args(args)
+            .output()
+            .context("Failed to run git; is it installed?")
+    }
+
+    /// Run git and return its trimmed output, failing on a non-zero exit
+    fn git(&self, args: &[&str]) -> Result<String> {
+        let output = self.run(args)?;
+        if !output.status.success() {
+            bail!(
+                "git {} failed: {}",
+                args.join(" "),
+                String::from_utf8_lossy(&output.stderr).trim()
+            );
+        }
+        Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+    }
+}
+
+/// Result of [`GitRepo::sync`]
+#[derive(Debug, Default)]
+pub struct SyncReport {
+    /// Commits merged from the remote
+    pub pulled: usize,
+    /// Commits pushed to the remote
+    pub pushed: usize,
+}
+
+/// A store in a git repository that commits every change it makes
+pub struct GitStore {
+    inner: Arc<dyn ReminderStore>,
+    repo: GitRepo,
+}
+
+impl GitStore {
+    pub fn new(inner: Arc<dyn ReminderStore>, repo: GitRepo) -> Self {
+        Self { inner, repo }
+    }
+
+    /// The change is already saved, so a failed commit is only logged;
+    /// the next commit or sync picks it up
+    fn commit(&self, message: &str) {
+        if let Err(e) = self.repo.commit_all(message) {
+            log_warn!("Failed to commit reminders: {:#}", e);
+        }
+    }
+}
+
+impl ReminderStore for GitStore {
+    fn load(&self) -> Result<Vec<Reminder>> {
+        self.inner.load()
+    }
+
+    fn save(&self, reminders: &[Reminder]) -> Result<()> {
+        let before = self.inner.load().unwrap_or_default();
+        self.inner.save(reminders)?;
+        self.commit(&describe(&before, reminders));
+        Ok(())
+    }
+
+    fn transaction(&self, mutation: Mutation) -> Result<()> {
+        self.apply(Scope::All, mutation, None)
+    }
+
+    fn apply(&self, scope: Scope, mutation: Mutation, finish: Option<Finish>) -> Result<()> {
+        let mut message = None;
+        let describe_changes = Box::new(|index: &mut ReminderIndex| {
+            let finished = match finish {
+                Some(finish) => finish(index)?,
+                None => false,
+            };
+            if index.has_changes() {
+                let (before, after) = index.changes();
+                message = Some(describe(&before, &after));
+            }
+            Ok(finished)
+        });
+        self.inner.apply(scope, mutation, Some(describe_changes))?;
+
+        if let Some(message) = message {
+            self.commit(&message);
+        }
+        Ok(())
+    }
+
+    fn path(&self) -> Option<&Path> {
+        self.inner.path()
+    }
+
+    fn vault(&self) -> Option<Arc<Vault>> {
+        self.inner.vault()
+    }
+
+    fn index(&self) -> Result<Arc<ReminderIndex>> {
+        self.inner.index()
+    }
+
+    fn merge(&self, other: Document, finish: Option<Finish>) -> Result<MergeReport> {
+        let report = self.inner.merge(other, finish)?;
+        if !report.is_empty() {
+            self.commit(&format!("Merge reminders: {}", report));
+        }
+        Ok(report)
+    }
+}
+
+/// Commit message summarising the difference between two versions
+pub fn describe(before: &[Reminder], after: &[Reminder]) -> String {
+    let old: HashMap<Uuid, &Reminder> = before.iter().map(|r| (r.id, r)).collect();
+    let new: HashMap<Uuid, &Reminder> = after.iter().map(|r| (r.id, r)).collect();
+
+    let added: Vec<&Reminder> = after.iter().filter(|r| !old.contains_key(&r.id)).collect();
+    let deleted: Vec<&Reminder> = before.iter().filter(|r| !new.contains_key(&r.id)).collect();
+    let updated: Vec<&Reminder> = after
+        .iter()
+        .filter(|r| old.get(&r.id).is_some_and(|o| to_fields(o) != to_fields(r)))
+        .collect();
+
+    let one = |verb: &str, r: &Reminder| {
+        format!(
+            "{} reminder \"{}\" ({})",
+            verb,
+            r.title,
+            &r.id.to_string()[..8]
+        )
+    };
+    match (added.as_slice(), updated.as_slice(), deleted.as_slice()) {
+        ([], [], []) => "Save reminders".to_string(),
+        ([r], [], []) => one("Add", r),
+        ([], [r], []) => one("Update", r),
+        ([], [], [r]) => one("Delete", r),
+        _ => {
+            let parts: Vec<String> = [("add", &added), ("update", &updated), ("delete", &deleted)]
+                .iter()
+                .filter(|(_, list)| !list.is_empty())
+                .map(|(verb, list)| format!("{} {}", verb, list.len()))
+                .collect();
+            let mut message = parts.join(", ");
+            message[..1].make_ascii_uppercase();
+            format!("{} reminders", message)
+        }
+    }
+}
+
+fn to_fields(reminder: &Reminder) -> Map<String, Value> {
+    match serde_json::to_value(reminder) {
+        Ok(Value::Object(fields)) => fields,
+        _ => Map::new(),
+    }
+}
+
+/// Three-way merge of reminder lists, field by field. A field changed on
+/// one side only takes that change; a field changed on both takes the side
+/// with the higher revision, or the later change, keeping ours on a tie.
+/// An edit beats a concurrent delete. Returns the merged list and the
+/// number of fields changed differently on both sides.
+pub fn merge_reminders(
+    base: &[Reminder],
+    ours: &[Reminder],
+    theirs: &[Reminder],
+) -> Result<(Vec<Reminder>, usize)> {
+    let base: HashMap<Uuid, Map<String, Value>> =
+        base.iter().map(|r| (r.id, to_fields(r))).collect();
+    let their_fields: HashMap<Uuid, Map<String, Value>> =
+        theirs.iter().map(|r| (r.id, to_fields(r))).collect();
+    let our_ids: BTreeSet<Uuid> = ours.iter().map(|r| r.id).collect();
+
+    let mut merged = Vec::new();
+    let mut conflicts = 0;
+
+    let their_ranks: HashMap<Uuid, _> = theirs
+        .iter()
+        .map(|r| (r.id, (r.revision, r.updated_at)))
+        .collect();
+
+    for reminder in ours {
+        let mine = to_fields(reminder);
+        let prefer_theirs = their_ranks
+            .get(&reminder.id)
+            .is_some_and(|rank| *rank > (reminder.revision, reminder.updated_at));
+        let fields = match (base.get(&reminder.id), their_fields.get(&reminder.id)) {
+            (Some(old), Some(other)) => {
+                let (fields, clashes) = merge_fields(old, &mine, other, prefer_theirs);
+                conflicts += clashes;
+                fields
+            }
+            // Deleted there; keep it only if edited here
+            (Some(old), None) if *old == mine => continue,
+            (Some(_), None) => mine,
+            (None, Some(other)) => {
+                let (fields, clashes) = merge_fields(&Map::new(), &mine, other, prefer_theirs);
+                conflicts += clashes;
+                fields
+            }
+            (None, None) => mine,
+        };
+        merged.push(fields);
+    }
+
+    for reminder in theirs.iter().filter(|r| !our_ids.contains(&r.id)) {
+        let other = &their_fields[&reminder.id];
+        match base.get(&reminder.id) {
+            // Deleted here; keep it only if edited there
+            Some(old) if old == other => continue,
+            _ => merged.push(other.clone()),
+        }
+    }
+
+    let mut merged = merged
+        .into_iter()
+        .map(|fields| serde_json::from_value(Value::Object(fields)))
+        .collect::<serde_json::Result<Vec<Reminder>>>()
+        .context("Failed to rebuild merged reminders")?;
+    // Both sides may have given out the same number or alias; the oldest
+    // reminder keeps it, whichever side merges
+    Names::of(theirs.iter().chain(ours)).settle(&mut merged)?;
+    Ok((merged, conflicts))
+}
+
+fn merge_fields(
+    base: &Map<String, Value>,
+    ours: &Map<String, Value>,
+    theirs: &Map<String, Value>,
+    prefer_theirs: bool,
+) -> (Map<String, Value>, usize) {
+    let keys: BTreeSet<&String> = base
+        .keys()
+        .chain(ours.keys())
+        .chain(theirs.keys())
+        .collect();
+    let mut merged = Map::new();
+    let mut conflicts = 0;
+
+    for key in keys {
+        let (old, mine, other) = (base.get(key), ours.get(key), theirs.get(key));
+        let value = if mine == other || other == old {
+            mine
+        } else if mine == old {
+            other
+        } else {
+            conflicts += 1;
+            if prefer_theirs {
+                other
+            } else {
+                mine
+            }
+        };
+        if let Some(value) = value {
+            merged.insert(key.clone(), value.clone());
+        }
+    }
+    (merged, conflicts)
+}
+
+/// Git merge driver for `reminders.json`: merge `ours` and `theirs` with
+/// `base` as their common ancestor and write the result over `ours`.
+/// Tombstones are kept next to the file, out of the repository; `base`
+/// tells a delete from an add here.
+pub fn merge_driver(base: &Path, ours: &Path, theirs: &Path) -> Result<usize> {
+    let read = |path: &Path| JsonStore::new(path).document();
+    let (old, mine, other) = (read(base)?, read(ours)?, read(theirs)?);
+    let (merged, conflicts) = merge_reminders(&old.reminders, &mine.reminders, &other.reminders)?;
+
+    let document = Document {
+        reminders: merged,
+        ..Document::default()
+    };
+    fs::write(ours, document.to_json()?).context("Failed to write merged reminders")?;
+    Ok(conflicts)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+
+    fn reminder(title: &str) -> Reminder {
+        Reminder::new_one_time(
+            title.to_string(),
+            None,
+            chrono::Local::now() + chrono::Duration::hours(1),
+            HashSet::new(),
+        )
+    }
+
+    #[test]
+    fn test_merges_fields_and_deletes() {
+        let (a, b, c) = (reminder("a"), reminder("b"), reminder("c"));
+        let base = vec![a.clone(), b.clone(), c.clone()];
+
+        let mut ours = base.clone();
+        ours[0].title = "a renamed".to_string();
+        ours[1].paused = true;
+        ours.retain(|r| r.id != c.id);
+        ours.push(reminder("mine"));
+
+        let mut theirs = base.clone();
+        theirs[0].description = Some("details".to_string());
+        theirs[1].paused = false;
+        theirs[1].title = "b both".to_string();
+        theirs[2].tags.insert("kept".to_string());
+        theirs.push(reminder("theirs"));
+
+        let (merged, conflicts) = merge_reminders(&base, &ours, &theirs).unwrap();
+        let titles: Vec<&str> = merged.iter().map(|r| r.title.as_str()).collect();
+        assert_eq!(titles, ["a renamed", "b both", "mine", "c", "theirs"]);
+        assert_eq!(merged[0].description.as_deref(), Some("details"));
+        // Only `paused` changed on one side; `c` was edited there, so its
+        // delete here is overridden
+        assert!(merged[1].paused);
+        assert_eq!(conflicts, 0);
+
+        theirs[0].title = "a elsewhere".to_string();
+        let (merged, conflicts) = merge_reminders(&base, &ours, &theirs).unwrap();
+        assert_eq!(merged[0].title, "a renamed");
+        assert_eq!(conflicts, 1);
+
+        // Unless their side is the newer revision
+        theirs[0].touch(0);
+        let (merged, _) = merge_reminders(&base, &ours, &theirs).unwrap();
+        assert_eq!(merged[0].title, "a elsewhere");
+    }
+
+    #[test]
+    fn test_describe() {
+        let (a, b) = (reminder("Stand-up"), reminder("Lunch"));
+        let short = &a.id.to_string()[..8];
+        assert_eq!(
+            describe(&[], std::slice::from_ref(&a)),
+            format!("Add reminder \"Stand-up\" ({})", short)
+        );
+        let mut paused = a.clone();
+        paused.paused = true;
+        assert!(describe(std::slice::from_ref(&a), &[paused]).starts_with("Update reminder"));
+        assert_eq!(
+            describe(&[a, b.clone()], &[reminder("x")]),
+            "Add 1, delete 2 reminders"
+        );
+        assert_eq!(
+            describe(std::slice::from_ref(&b), std::slice::from_ref(&b)),
+            "Save reminders"
+        );
+    }
+}
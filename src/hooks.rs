@@ -0,0 +1,292 @@
+use crate::config::Config;
+use crate::notifier::FailedTargets;
+use crate::reminder::Reminder;
+use crate::template::Message;
+use crate::{log_error, log_info, log_warn};
+use anyhow::{anyhow, bail, Context, Result};
+use std::io::Read;
+use std::process::{Child, Command, ExitStatus, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
+
+pub const DEFAULT_HOOK_TIMEOUT_SECS: u64 = 30;
+
+/// Longest hook output kept for the log
+const MAX_OUTPUT_CHARS: usize = 2000;
+
+#[derive(Debug)]
+pub struct HookOutcome {
+    /// `None` when the hook was killed after timing out
+    pub status: Option<ExitStatus>,
+    pub stdout: String,
+    pub stderr: String,
+}
+
+impl HookOutcome {
+    pub fn success(&self) -> bool {
+        self.status.map(|s| s.success()).unwrap_or(false)
+    }
+}
+
+/// Commands to run for a reminder: its own `--exec` hook, if trusted here,
+/// then the global hooks
+pub fn hooks_for(reminder: &Reminder, config: &Config) -> Vec<String> {
+    config
+        .trusted_exec(reminder)
+        .into_iter()
+        .chain(config.hooks.on_trigger.iter())
+        .cloned()
+        .collect()
+}
+
+/// Run the reminder's hooks concurrently, logging each outcome; fails with
+/// [`FailedTargets`] naming the hooks that could not be started, exited
+/// non-zero or timed out
+pub fn run_hooks(reminder: &Reminder, config: &Config) -> Result<()> {
+    let timeout = Duration::from_secs(config.hooks.timeout_secs);
+    let message = Message::render(reminder, config);
+
+    let handles: Vec<_> = hooks_for(reminder, config)
+        .into_iter()
+        .map(|command| {
+            let (hook, reminder, message) = (command.clone(), reminder.clone(), message.clone());
+            let handle = thread::spawn(move || run_logged(&hook, &reminder, &message, timeout));
+            (command, handle)
+        })
+        .collect();
+
+    let total = handles.len();
+    let failed: Vec<(String, String)> = handles
+        .into_iter()
+        .filter_map(|(command, handle)| match handle.join() {
+            Ok(Ok(())) => None,
+            Ok(Err(e)) => Some((command, format!("{:#}", e))),
+            Err(_) => Some((command, "Hook panicked".to_string())),
+        })
+        .collect();
+
+    if !failed.is_empty() {
+        return Err(FailedTargets {
+            kind: "hook",
+            failed,
+            total,
+        }
+        .into());
+    }
+    Ok(())
+}
+
+/// Run only `command` of the reminder's hooks, as a retry of one that failed
+pub fn run_hook_target(reminder: &Reminder, config: &Config, command: &str) -> Result<()> {
+    if !hooks_for(reminder, config).iter().any(|c| c == command) {
+        bail!("Hook '{}' is no longer configured", command);
+    }
+    let timeout = Duration::from_secs(config.hooks.timeout_secs);
+    run_logged(
+        command,
+        reminder,
+        &Message::render(reminder, config),
+        timeout,
+    )
+}
+
+/// Run one hook and log its outcome
+fn run_logged(
+    command: &str,
+    reminder: &Reminder,
+    message: &Message,
+    timeout: Duration,
+) -> Result<()> {
+    let outcome = run_hook(command, reminder, message, timeout);
+    let result = match &outcome {
+        Ok(outcome) if outcome.success() => Ok(()),
+        Ok(HookOutcome {
+            status: Some(status),
+            ..
+        }) => Err(anyhow!("Hook '{}' failed ({})", command, status)),
+        Ok(_) => Err(anyhow!("Hook '{}' timed out", command)),
+        Err(e) => Err(anyhow!("{:#}", e)),
+    };
+    log_hook_outcome(command, outcome);
+    result
+}
+
+/// Run one hook through the shell with the reminder exposed in `REMINDER_*`
+/// environment variables, killing it once `timeout` has passed
+pub fn run_hook(
+    command: &str,
+    reminder: &Reminder,
+    message: &Message,
+    timeout: Duration,
+) -> Result<HookOutcome> {
+    let mut child = shell_command(command)
+        .envs(hook_env(reminder, message))
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .with_context(|| format!("Failed to start hook: {}", command))?;
+
+    let stdout = read_in_background(child.stdout.take());
+    let stderr = read_in_background(child.stderr.take());
+    let status = wait_with_timeout(&mut child, timeout)?;
+
+    Ok(HookOutcome {
+        status,
+        stdout: stdout.join().unwrap_or_default(),
+        stderr: stderr.join().unwrap_or_default(),
+    })
+}
+
+fn hook_env(reminder: &Reminder, message: &Message) -> Vec<(&'static str, String)> {
+    let mut tags: Vec<&str> = reminder.tags.iter().map(String::as_str).collect();
+    tags.sort();
+
+    vec![
+        ("REMINDER_ID", reminder.id.to_string()),
+        ("REMINDER_TITLE", reminder.title.clone()),
+        (
+            "REMINDER_DESCRIPTION",
+            reminder.description.clone().unwrap_or_default(),
+        ),
+        ("REMINDER_TAGS", tags.join(",")),
+        (
+            "REMINDER_SCHEDULED_TIME",
+            reminder
+                .next_trigger
+                .map(|t| t.to_rfc3339())
+                .unwrap_or_default(),
+        ),
+        (
+            "REMINDER_OCCURRENCE",
+            reminder.occurrences.max(1).to_string(),
+        ),
+        ("REMINDER_SUMMARY", message.summary.clone()),
+        ("REMINDER_BODY", message.body.clone()),
+    ]
+}
+
+#[cfg(unix)]
+fn shell_command(command: &str) -> Command {
+    use std::os::unix::process::CommandExt;
+
+    let mut cmd = Command::new("sh");
+    // Own process group, so a timeout also kills whatever the hook started
+    cmd.arg("-c").arg(command).process_group(0);
+    cmd
+}
+
+#[cfg(windows)]
+fn shell_command(command: &str) -> Command {
+    let mut cmd = Command::new("cmd");
+    cmd.arg("/C").arg(command);
+    cmd
+}
+
+fn read_in_background<R: Read + Send + 'static>(pipe: Option<R>) -> thread::JoinHandle<String> {
+    thread::spawn(move || {
+        let mut buf = Vec::new();
+        if let Some(mut pipe) = pipe {
+            let _ = pipe.read_to_end(&mut buf);
+        }
+        String::from_utf8_lossy(&buf).into_owned()
+    })
+}
+
+fn wait_with_timeout(child: &mut Child, timeout: Duration) -> Result<Option<ExitStatus>> {
+    let start = Instant::now();
+    loop {
+        if let Some(status) = child.try_wait()? {
+            return Ok(Some(status));
+        }
+        if start.elapsed() >= timeout {
+            #[cfg(unix)]
+            let _ = Command::new("kill")
+                .args(["-s", "KILL", "--", &format!("-{}", child.id())])
+                .status();
+            let _ = child.kill();
+            let _ = child.wait();
+            return Ok(None);
+        }
+        thread::sleep(Duration::from_millis(50));
+    }
+}
+
+fn log_hook_outcome(command: &str, outcome: Result<HookOutcome>) {
+    match outcome {
+        Ok(outcome) => {
+            let stdout = truncate_output(&outcome.stdout);
+            let stderr = truncate_output(&outcome.stderr);
+            match outcome.status {
+                Some(status) if status.success() => {
+                    log_info!("Hook '{}' succeeded: stdout={:?}", command, stdout)
+                }
+                Some(status) => log_warn!(
+                    "Hook '{}' failed ({}): stdout={:?} stderr={:?}",
+                    command,
+                    status,
+                    stdout,
+                    stderr
+                ),
+                None => log_warn!(
+                    "Hook '{}' timed out and was killed: stdout={:?} stderr={:?}",
+                    command,
+                    stdout,
+                    stderr
+                ),
+            }
+        }
+        Err(e) => log_error!("{}", e),
+    }
+}
+
+fn truncate_output(output: &str) -> String {
+    let output = output.trim();
+    if output.chars().count() <= MAX_OUTPUT_CHARS {
+        output.to_string()
+    } else {
+        format!(
+            "{}...",
+            output.chars().take(MAX_OUTPUT_CHARS).collect::<String>()
+        )
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+
+    fn reminder() -> Reminder {
+        let tags: HashSet<String> = ["work", "ops"].iter().map(|t| t.to_string()).collect();
+        Reminder::new_one_time("Deploy".to_string(), None, chrono::Local::now(), tags)
+    }
+
+    #[test]
+    fn test_hook_sees_reminder_env() {
+        let reminder = reminder();
+        let outcome = run_hook(
+            "echo \"$REMINDER_TITLE|$REMINDER_TAGS\"; echo oops >&2; exit 3",
+            &reminder,
+            &Message::render(&reminder, &Config::default()),
+            Duration::from_secs(5),
+        )
+        .unwrap();
+
+        assert_eq!(outcome.status.unwrap().code(), Some(3));
+        assert_eq!(outcome.stdout.trim(), "Deploy|ops,work");
+        assert_eq!(outcome.stderr.trim(), "oops");
+    }
+
+    #[test]
+    fn test_hook_killed_after_timeout() {
+        let start = Instant::now();
+        let reminder = reminder();
+        let message = Message::render(&reminder, &Config::default());
+        let outcome = run_hook("sleep 5", &reminder, &message, Duration::from_millis(200)).unwrap();
+
+        assert!(outcome.status.is_none());
+        assert!(!outcome.success());
+        assert!(start.elapsed() < Duration::from_secs(3));
+    }
+}
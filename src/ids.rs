@@ -0,0 +1,271 @@
+use crate::index::ReminderIndex;
+use crate::log_warn;
+use crate::reminder::Reminder;
+use anyhow::{bail, Result};
+use std::collections::{HashMap, HashSet};
+use uuid::Uuid;
+
+/// `12` or `#12` as a reminder number
+pub fn parse_number(key: &str) -> Option<u32> {
+    let digits = key.strip_prefix('#').unwrap_or(key);
+    if digits.is_empty() || !digits.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    digits.parse().ok().filter(|&n| n != 0)
+}
+
+/// Aliases are single words of letters, digits, `-` and `_` that cannot
+/// be mistaken for a number or an id prefix
+pub fn validate_alias(alias: &str) -> Result<()> {
+    if alias.is_empty() {
+        bail!("Name must not be empty");
+    }
+    if !alias
+        .chars()
+        .all(|c| c.is_alphanumeric() || c == '-' || c == '_')
+    {
+        bail!(
+            "Invalid name '{}': use letters, digits, '-' and '_' only",
+            alias
+        );
+    }
+    if alias.bytes().all(|b| b.is_ascii_digit()) {
+        bail!("Invalid name '{}': names cannot be numbers", alias);
+    }
+    if looks_like_id(alias) {
+        bail!(
+            "Invalid name '{}': names cannot look like an id prefix",
+            alias
+        );
+    }
+    Ok(())
+}
+
+/// Whether `key` could be the start of an id, dashes included
+fn looks_like_id(key: &str) -> bool {
+    key.len() <= 36
+        && key.chars().enumerate().all(|(position, c)| {
+            if matches!(position, 8 | 13 | 18 | 23) {
+                c == '-'
+            } else {
+                c.is_ascii_hexdigit()
+            }
+        })
+}
+
+/// Numbers and aliases of the reminders before a change, so the change
+/// can be checked against them
+#[derive(Debug, Default)]
+pub struct Names {
+    before: HashMap<Uuid, (u32, Option<String>)>,
+}
+
+impl Names {
+    pub fn of<'a>(reminders: impl IntoIterator<Item = &'a Reminder>) -> Self {
+        Self {
+            before: reminders
+                .into_iter()
+                .map(|r| (r.id, (r.number, r.alias.clone())))
+                .collect(),
+        }
+    }
+
+    /// Number every reminder that has no number yet, or lost its number
+    /// to a reminder that had it first, and make sure no two reminders
+    /// share an alias. An alias given in this change that is already taken
+    /// is an error; duplicates that came in together, as from a merge,
+    /// are dropped from all but the oldest reminder. Returns whether any
+    /// reminder changed.
+    pub fn settle(&self, reminders: &mut [Reminder]) -> Result<bool> {
+        self.settle_above(reminders, 0)
+    }
+
+    /// [`Names::settle`] for the reminders changed in `index` since it was
+    /// built. Only they, the reminders sharing their numbers or aliases and
+    /// any still unnumbered are looked at; the others are settled already.
+    pub fn settle_changes(index: &mut ReminderIndex) -> Result<bool> {
+        let mut ids = Vec::new();
+        let mut seen = HashSet::new();
+        let changed = index.changed_ids().iter().filter_map(|&id| index.get(id));
+        for reminder in index.with_number(0).into_iter().chain(changed) {
+            let sharing = index.with_number(reminder.number).into_iter().chain(
+                reminder
+                    .alias
+                    .as_deref()
+                    .map(|alias| index.with_alias(alias))
+                    .unwrap_or_default(),
+            );
+            for other in std::iter::once(reminder).chain(sharing) {
+                if seen.insert(other.id) {
+                    ids.push(other.id);
+                }
+            }
+        }
+
+        let mut reminders: Vec<Reminder> = ids
+            .iter()
+            .filter_map(|&id| index.get(id).cloned())
+            .collect();
+        // Unchanged reminders hold what they have now; added ones held nothing
+        let names = Self::of(reminders.iter().filter_map(|r| match index.original(r.id) {
+            None => Some(r),
+            Some(original) => original,
+        }));
+        if !names.settle_above(&mut reminders, index.max_number())? {
+            return Ok(false);
+        }
+        for reminder in reminders {
+            let current = index.get(reminder.id);
+            if current.is_some_and(|r| r.number != reminder.number || r.alias != reminder.alias) {
+                index.insert(reminder);
+            }
+        }
+        Ok(true)
+    }
+
+    /// New numbers start above `highest` as well as above the numbers
+    /// among `reminders`
+    fn settle_above(&self, reminders: &mut [Reminder], highest: u32) -> Result<bool> {
+        let aliases = self.settle_aliases(reminders)?;
+        let numbers = self.settle_numbers(reminders, highest);
+        Ok(aliases || numbers)
+    }
+
+    fn settle_aliases(&self, reminders: &mut [Reminder]) -> Result<bool> {
+        let mut holders: HashMap<&str, Vec<usize>> = HashMap::new();
+        for (i, reminder) in reminders.iter().enumerate() {
+            if let Some(alias) = &reminder.alias {
+                holders.entry(alias).or_default().push(i);
+            }
+        }
+
+        let mut dropped = Vec::new();
+        for (alias, mut holders) in holders.into_iter().filter(|(_, h)| h.len() > 1) {
+            holders.sort_by_key(|&i| {
+                self.priority(&reminders[i], |(_, a)| a.as_deref() == Some(alias))
+            });
+            if let Some(&renamed) = holders.iter().find(|&&i| !self.had_alias(&reminders[i])) {
+                let other = holders
+                    .iter()
+                    .find(|&&i| i != renamed)
+                    .copied()
+                    .unwrap_or(renamed);
+                bail!(
+                    "Name '{}' is already used by reminder {}",
+                    alias,
+                    reminders[other].handle()
+                );
+            }
+            dropped.extend(holders.into_iter().skip(1));
+        }
+
+        for &i in &dropped {
+            let reminder = &mut reminders[i];
+            log_warn!(
+                "Dropped duplicate name '{}' from reminder {}",
+                reminder.alias.as_deref().unwrap_or_default(),
+                reminder.handle()
+            );
+            reminder.alias = None;
+        }
+        Ok(!dropped.is_empty())
+    }
+
+    fn settle_numbers(&self, reminders: &mut [Reminder], highest: u32) -> bool {
+        let mut order: Vec<usize> = (0..reminders.len()).collect();
+        order.sort_by_key(|&i| {
+            let number = reminders[i].number;
+            self.priority(&reminders[i], |&(n, _)| n == number)
+        });
+
+        let mut taken = HashSet::new();
+        let mut unnumbered = Vec::new();
+        for i in order {
+            let number = reminders[i].number;
+            if number == 0 || !taken.insert(number) {
+                unnumbered.push(i);
+            }
+        }
+
+        // New numbers follow the highest in use, so a number is never
+        // handed to a second reminder while the first still exists
+        let mut next = taken.iter().max().copied().unwrap_or(0).max(highest);
+        for &i in &unnumbered {
+            next += 1;
+            reminders[i].number = next;
+        }
+        !unnumbered.is_empty()
+    }
+
+    fn had_alias(&self, reminder: &Reminder) -> bool {
+        self.before
+            .get(&reminder.id)
+            .is_some_and(|(_, alias)| *alias == reminder.alias)
+    }
+
+    /// Sort key for reminders claiming the same number or alias: whoever
+    /// held it before the change, then the oldest
+    fn priority(
+        &self,
+        reminder: &Reminder,
+        held: impl Fn(&(u32, Option<String>)) -> bool,
+    ) -> (bool, chrono::DateTime<chrono::Local>, Uuid) {
+        let kept = self.before.get(&reminder.id).is_some_and(held);
+        (!kept, reminder.created_at, reminder.id)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Local;
+
+    fn reminder(title: &str) -> Reminder {
+        Reminder::new_one_time(title.to_string(), None, Local::now(), HashSet::new())
+    }
+
+    #[test]
+    fn test_parse_and_validate() {
+        assert_eq!(parse_number("12"), Some(12));
+        assert_eq!(parse_number("#3"), Some(3));
+        assert_eq!(parse_number("0"), None);
+        assert_eq!(parse_number("1a"), None);
+        assert!(validate_alias("standup").is_ok());
+        assert!(validate_alias("42").is_err());
+        assert!(validate_alias("#42").is_err());
+        assert!(validate_alias("two words").is_err());
+        assert!(validate_alias("cafe").is_err());
+        assert!(validate_alias("DEADBEEF-12").is_err());
+        assert!(validate_alias("cafe-run").is_ok());
+    }
+
+    #[test]
+    fn test_settle() {
+        let mut reminders = vec![reminder("a"), reminder("b")];
+        assert!(Names::default().settle(&mut reminders).unwrap());
+        assert_eq!(reminders[0].number, 1);
+        assert_eq!(reminders[1].number, 2);
+
+        // A copy from elsewhere that also numbered a reminder 2: the one
+        // that had it keeps it
+        let mut other = reminder("c");
+        other.number = 2;
+        let names = Names::of(&reminders);
+        reminders.insert(0, other);
+        assert!(names.settle(&mut reminders).unwrap());
+        assert_eq!(reminders[0].number, 3);
+        assert_eq!(reminders[2].number, 2);
+
+        // Taking a name already in use fails
+        reminders[1].alias = Some("standup".to_string());
+        let names = Names::of(&reminders);
+        reminders[2].alias = Some("standup".to_string());
+        assert!(names.settle(&mut reminders).is_err());
+
+        // Duplicates that arrive together keep the oldest
+        let names = Names::of(&reminders);
+        assert!(names.settle(&mut reminders).unwrap());
+        assert_eq!(reminders[1].alias.as_deref(), Some("standup"));
+        assert_eq!(reminders[2].alias, None);
+    }
+}
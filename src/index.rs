@@ -0,0 +1,465 @@
+use crate::ids;
+use crate::reminder::Reminder;
+use chrono::{DateTime, Local};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use uuid::Uuid;
+
+/// Hex digits in a UUID
+const ID_DIGITS: usize = 32;
+
+/// Reminders in store order, indexed by id, id prefix, number, alias, tag
+/// and next trigger. Lookups cost a hash probe or a range query instead of a scan.
+///
+/// Changes made after the index is built are tracked, so a store can write
+/// back only the reminders a transaction touched.
+#[derive(Debug, Clone, Default)]
+pub struct ReminderIndex {
+    /// Removed reminders leave a hole, so the others keep their order
+    slots: Vec<Option<Reminder>>,
+    by_id: HashMap<Uuid, usize>,
+    /// Ids as numbers: the ids sharing a hex prefix form one range, so this
+    /// sorted map serves as the prefix tree
+    by_prefix: BTreeMap<u128, usize>,
+    /// Numbers and aliases are unique once settled, but a change may
+    /// briefly give one to several reminders. Unnumbered reminders are
+    /// under 0.
+    by_number: BTreeMap<u32, BTreeSet<usize>>,
+    by_alias: HashMap<String, BTreeSet<usize>>,
+    by_tag: HashMap<String, BTreeSet<usize>>,
+    /// Active reminders by when they next fire
+    by_trigger: BTreeSet<(DateTime<Local>, usize)>,
+    /// Each reminder changed since the index was built, as it was then
+    originals: HashMap<Uuid, Option<Reminder>>,
+    /// Ids in `originals`, in the order they were first changed
+    changed: Vec<Uuid>,
+    /// Highest number handed out so far, deleted reminders' included, as
+    /// the store keeps it
+    last_number: u32,
+}
+
+impl ReminderIndex {
+    pub fn new(reminders: Vec<Reminder>) -> Self {
+        let mut index = Self {
+            slots: Vec::with_capacity(reminders.len()),
+            by_id: HashMap::with_capacity(reminders.len()),
+            ..Self::default()
+        };
+        for reminder in reminders {
+            index.place(reminder);
+        }
+        index
+    }
+
+    pub fn len(&self) -> usize {
+        self.by_id.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.by_id.is_empty()
+    }
+
+    /// Reminders in store order
+    pub fn iter(&self) -> impl Iterator<Item = &Reminder> {
+        self.slots.iter().flatten()
+    }
+
+    pub fn to_vec(&self) -> Vec<Reminder> {
+        self.iter().cloned().collect()
+    }
+
+    pub fn get(&self, id: Uuid) -> Option<&Reminder> {
+        self.by_id.get(&id).map(|&slot| self.at(slot))
+    }
+
+    /// Reminders whose id starts with `prefix`, in id order
+    pub fn find_by_prefix(&self, prefix: &str) -> Vec<&Reminder> {
+        let Some((low, high)) = prefix_range(prefix) else {
+            return Vec::new();
+        };
+        self.by_prefix
+            .range(low..=high)
+            .map(|(_, &slot)| self.at(slot))
+            .collect()
+    }
+
+    /// The reminder a user refers to by `key`: a number such as `12` or
+    /// `#12`, then an alias, then an id prefix. Only prefixes can match
+    /// several reminders.
+    pub fn find(&self, key: &str) -> Vec<&Reminder> {
+        let by_number = ids::parse_number(key).and_then(|n| self.by_number.get(&n));
+        match by_number.or_else(|| self.by_alias.get(key)) {
+            Some(slots) => slots
+                .first()
+                .map(|&slot| self.at(slot))
+                .into_iter()
+                .collect(),
+            None => self.find_by_prefix(key),
+        }
+    }
+
+    /// Reminders numbered `number`, in store order
+    pub fn with_number(&self, number: u32) -> Vec<&Reminder> {
+        self.at_all(self.by_number.get(&number))
+    }
+
+    /// Reminders named `alias`, in store order
+    pub fn with_alias(&self, alias: &str) -> Vec<&Reminder> {
+        self.at_all(self.by_alias.get(alias))
+    }
+
+    /// The highest number in use or handed out before, or 0. New numbers
+    /// go above it, so a deleted reminder's number is not reused.
+    pub fn max_number(&self) -> u32 {
+        self.by_number
+            .last_key_value()
+            .map_or(0, |(&n, _)| n)
+            .max(self.last_number)
+    }
+
+    /// Count `number` as handed out, as the store last saved it
+    pub fn set_last_number(&mut self, number: u32) {
+        self.last_number = number;
+    }
+
+    /// Reminders with `tag`, in store order
+    pub fn with_tag(&self, tag: &str) -> Vec<&Reminder> {
+        self.at_all(self.by_tag.get(tag))
+    }
+
+    /// Every tag in use and how many reminders have it, sorted by tag
+    pub fn tags(&self) -> Vec<(&str, usize)> {
+        let mut tags: Vec<(&str, usize)> = self
+            .by_tag
+            .iter()
+            .map(|(tag, slots)| (tag.as_str(), slots.len()))
+            .collect();
+        tags.sort();
+        tags
+    }
+
+    /// Active reminders due at `now`, earliest first
+    pub fn due(&self, now: DateTime<Local>) -> Vec<&Reminder> {
+        self.by_trigger
+            .range(..=(now, usize::MAX))
+            .map(|&(_, slot)| self.at(slot))
+            .collect()
+    }
+
+    /// When the earliest active reminder next fires
+    pub fn next_trigger(&self) -> Option<DateTime<Local>> {
+        self.by_trigger.first().map(|&(at, _)| at)
+    }
+
+    /// Add a reminder, replacing the one with the same id in place
+    pub fn insert(&mut self, reminder: Reminder) {
+        self.track(reminder.id);
+        self.place(reminder);
+    }
+
+    pub fn remove(&mut self, id: Uuid) -> Option<Reminder> {
+        let slot = *self.by_id.get(&id)?;
+        self.track(id);
+        self.unlink(slot);
+        self.slots[slot].take()
+    }
+
+    /// Change a reminder in place. Returns whether it exists.
+    pub fn update(&mut self, id: Uuid, updater: impl FnOnce(&mut Reminder)) -> bool {
+        let Some(&slot) = self.by_id.get(&id) else {
+            return false;
+        };
+        self.track(id);
+        self.unlink(slot);
+        let mut reminder = self.slots[slot].take().expect("indexed slot is filled");
+        updater(&mut reminder);
+        self.link(slot, &reminder);
+        self.slots[slot] = Some(reminder);
+        true
+    }
+
+    /// Replace every reminder, taking the order of `reminders`
+    pub fn replace_all(&mut self, reminders: Vec<Reminder>) {
+        let ids: Vec<Uuid> = self.by_id.keys().copied().collect();
+        for id in ids.into_iter().chain(reminders.iter().map(|r| r.id)) {
+            self.track(id);
+        }
+        let mut replaced = Self::new(reminders);
+        replaced.originals = std::mem::take(&mut self.originals);
+        replaced.changed = std::mem::take(&mut self.changed);
+        replaced.last_number = self.last_number;
+        *self = replaced;
+    }
+
+    pub fn has_changes(&self) -> bool {
+        !self.changed.is_empty()
+    }
+
+    /// The reminders changed since the index was built, as they were and as
+    /// they are now, in store order with removed ones last. Added reminders
+    /// are only in the second list and removed ones only in the first.
+    pub fn changes(&self) -> (Vec<Reminder>, Vec<Reminder>) {
+        let mut ids = self.changed.clone();
+        ids.sort_by_key(|id| self.by_id.get(id).copied().unwrap_or(usize::MAX));
+        let before = ids
+            .iter()
+            .filter_map(|id| self.originals[id].clone())
+            .collect();
+        let after = ids.iter().filter_map(|&id| self.get(id).cloned()).collect();
+        (before, after)
+    }
+
+    /// Ids of the reminders changed since the index was built, removed ones
+    /// included, in the order they were first changed
+    pub fn changed_ids(&self) -> &[Uuid] {
+        &self.changed
+    }
+
+    /// What a changed reminder was before its first change: `Some(None)`
+    /// if it was added, `None` if it has not changed
+    pub fn original(&self, id: Uuid) -> Option<Option<&Reminder>> {
+        self.originals.get(&id).map(Option::as_ref)
+    }
+
+    /// Take the current reminders as the starting point for tracking
+    /// changes, as once they are saved
+    pub fn clear_changes(&mut self) {
+        self.originals.clear();
+        self.changed.clear();
+    }
+
+    /// Add a reminder loaded to check changes against, unless the index
+    /// has it or a change removed it. This is not a change itself.
+    pub fn include(&mut self, reminder: Reminder) {
+        if !self.by_id.contains_key(&reminder.id) && !self.originals.contains_key(&reminder.id) {
+            self.place(reminder);
+        }
+    }
+
+    fn track(&mut self, id: Uuid) {
+        if !self.originals.contains_key(&id) {
+            self.originals.insert(id, self.get(id).cloned());
+            self.changed.push(id);
+        }
+    }
+
+    /// Add or replace a reminder without tracking the change
+    fn place(&mut self, reminder: Reminder) {
+        let slot = match self.by_id.get(&reminder.id) {
+            Some(&slot) => {
+                self.unlink(slot);
+                slot
+            }
+            None => {
+                self.slots.push(None);
+                self.slots.len() - 1
+            }
+        };
+        self.link(slot, &reminder);
+        self.slots[slot] = Some(reminder);
+    }
+
+    fn at(&self, slot: usize) -> &Reminder {
+        self.slots[slot]
+            .as_ref()
+            .expect("index points at a removed reminder")
+    }
+
+    fn at_all(&self, slots: Option<&BTreeSet<usize>>) -> Vec<&Reminder> {
+        slots
+            .map(|slots| slots.iter().map(|&slot| self.at(slot)).collect())
+            .unwrap_or_default()
+    }
+
+    fn link(&mut self, slot: usize, reminder: &Reminder) {
+        self.by_id.insert(reminder.id, slot);
+        self.by_prefix.insert(reminder.id.as_u128(), slot);
+        self.by_number
+            .entry(reminder.number)
+            .or_default()
+            .insert(slot);
+        if let Some(alias) = &reminder.alias {
+            self.by_alias.entry(alias.clone()).or_default().insert(slot);
+        }
+        for tag in &reminder.tags {
+            self.by_tag.entry(tag.clone()).or_default().insert(slot);
+        }
+        if let Some(at) = reminder
+            .next_trigger
+            .filter(|_| !reminder.completed && !reminder.paused)
+        {
+            self.by_trigger.insert((at, slot));
+        }
+    }
+
+    fn unlink(&mut self, slot: usize) {
+        let Some(reminder) = self.slots[slot].as_ref() else {
+            return;
+        };
+        self.by_id.remove(&reminder.id);
+        self.by_prefix.remove(&reminder.id.as_u128());
+        if let Some(slots) = self.by_number.get_mut(&reminder.number) {
+            slots.remove(&slot);
+            if slots.is_empty() {
+                self.by_number.remove(&reminder.number);
+            }
+        }
+        if let Some(alias) = &reminder.alias {
+            unlink_from(&mut self.by_alias, alias, slot);
+        }
+        for tag in &reminder.tags {
+            unlink_from(&mut self.by_tag, tag, slot);
+        }
+        if let Some(at) = reminder.next_trigger {
+            self.by_trigger.remove(&(at, slot));
+        }
+    }
+}
+
+fn unlink_from(map: &mut HashMap<String, BTreeSet<usize>>, key: &str, slot: usize) {
+    if let Some(slots) = map.get_mut(key) {
+        slots.remove(&slot);
+        if slots.is_empty() {
+            map.remove(key);
+        }
+    }
+}
+
+impl From<Vec<Reminder>> for ReminderIndex {
+    fn from(reminders: Vec<Reminder>) -> Self {
+        Self::new(reminders)
+    }
+}
+
+/// The smallest and largest id starting with `prefix`, which may include
+/// the dashes of the usual form. `None` if no id can match.
+fn prefix_range(prefix: &str) -> Option<(u128, u128)> {
+    let mut digits = 0;
+    let mut value: u128 = 0;
+    for (position, c) in prefix.chars().enumerate() {
+        if matches!(position, 8 | 13 | 18 | 23) {
+            if c != '-' {
+                return None;
+            }
+            continue;
+        }
+        if digits == ID_DIGITS {
+            return None;
+        }
+        value = (value << 4) | c.to_digit(16)? as u128;
+        digits += 1;
+    }
+
+    let free_bits = 4 * (ID_DIGITS - digits) as u32;
+    if free_bits == 128 {
+        return Some((0, u128::MAX));
+    }
+    let low = value << free_bits;
+    let mask = if free_bits == 0 {
+        0
+    } else {
+        u128::MAX >> (128 - free_bits)
+    };
+    Some((low, low | mask))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Duration;
+    use std::collections::HashSet;
+
+    fn reminder(id: &str, minutes: i64, tags: &[&str]) -> Reminder {
+        let mut reminder = Reminder::new_one_time(
+            id.to_string(),
+            None,
+            Local::now() + Duration::minutes(minutes),
+            tags.iter().map(|t| t.to_string()).collect::<HashSet<_>>(),
+        );
+        reminder.id = id.parse().unwrap();
+        reminder
+    }
+
+    #[test]
+    fn test_lookups() {
+        let a = reminder("1a2b3c4d-0000-4000-8000-000000000001", -5, &["work"]);
+        let b = reminder(
+            "1a2b3c4d-0000-4000-8000-000000000002",
+            10,
+            &["work", "home"],
+        );
+        let mut c = reminder("ffffffff-ffff-4fff-bfff-ffffffffffff", 5, &[]);
+        c.number = 12;
+        c.alias = Some("standup".to_string());
+        let mut index = ReminderIndex::new(vec![a.clone(), b.clone(), c.clone()]);
+
+        assert_eq!(index.get(b.id).unwrap().id, b.id);
+        assert_eq!(index.find_by_prefix("1a2b").len(), 2);
+        assert_eq!(
+            index.find_by_prefix("1A2B3C4D-0000-4000-8000-00000000000")[0].id,
+            a.id
+        );
+        assert_eq!(index.find_by_prefix(&b.id.to_string())[0].id, b.id);
+        assert_eq!(index.find_by_prefix("ffffffff")[0].id, c.id);
+        assert_eq!(index.find_by_prefix("").len(), 3);
+        assert!(index.find_by_prefix("1a2b3c4d0").is_empty());
+        assert!(index.find_by_prefix("xyz").is_empty());
+        assert_eq!(index.find("#12")[0].id, c.id);
+        assert_eq!(index.find("standup")[0].id, c.id);
+        assert_eq!(index.find("1a2b").len(), 2);
+
+        assert_eq!(index.tags(), [("home", 1), ("work", 2)]);
+        assert_eq!(index.due(Local::now()).len(), 1);
+        assert_eq!(index.next_trigger(), a.next_trigger);
+
+        // Changes keep every index and the order in step
+        index.update(a.id, |r| {
+            r.paused = true;
+            r.tags.clear();
+        });
+        assert!(index.due(Local::now()).is_empty());
+        assert_eq!(index.tags(), [("home", 1), ("work", 1)]);
+        assert_eq!(index.remove(b.id).unwrap().id, b.id);
+        assert!(index.with_tag("home").is_empty());
+        assert_eq!(index.next_trigger(), c.next_trigger);
+        index.insert(b.clone());
+        let order: Vec<Uuid> = index.iter().map(|r| r.id).collect();
+        assert_eq!(order, [a.id, c.id, b.id]);
+        assert_eq!(index.len(), 3);
+    }
+
+    #[test]
+    fn test_changes() {
+        let a = reminder("1a2b3c4d-0000-4000-8000-000000000001", 5, &[]);
+        let b = reminder("1a2b3c4d-0000-4000-8000-000000000002", 5, &[]);
+        let c = reminder("1a2b3c4d-0000-4000-8000-000000000003", 5, &[]);
+        let mut index = ReminderIndex::new(vec![a.clone(), b.clone()]);
+        assert!(!index.has_changes());
+
+        index.update(a.id, |r| r.title = "renamed".to_string());
+        index.update(a.id, |r| r.number = 4);
+        index.remove(b.id);
+        index.insert(c.clone());
+        assert_eq!(index.original(a.id).unwrap().unwrap().title, a.title);
+        assert!(index.original(c.id).unwrap().is_none());
+        assert!(index.original(Uuid::nil()).is_none());
+        assert_eq!(index.with_number(4)[0].id, a.id);
+        assert_eq!(index.max_number(), 4);
+
+        let (before, after) = index.changes();
+        let ids = |reminders: &[Reminder]| reminders.iter().map(|r| r.id).collect::<Vec<_>>();
+        assert_eq!(ids(&before), [a.id, b.id]);
+        assert_eq!(ids(&after), [a.id, c.id]);
+        assert_eq!(after[0].title, "renamed");
+
+        index.clear_changes();
+        assert!(!index.has_changes());
+        index.remove(c.id);
+        index.include(c.clone());
+        index.include(b.clone());
+        assert!(index.get(c.id).is_none());
+        assert_eq!(index.changed_ids(), [c.id]);
+        index.replace_all(vec![c.clone(), a.clone()]);
+        assert_eq!(ids(&index.to_vec()), [c.id, a.id]);
+        assert_eq!(index.changed_ids().len(), 3);
+    }
+}
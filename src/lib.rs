@@ -1,7 +1,27 @@
+pub mod backup;
+pub mod config;
 pub mod cron_parser;
+pub mod crypto;
 pub mod daemon;
+pub mod email;
+pub mod events;
+pub mod git;
+pub mod hooks;
+pub mod ids;
+pub mod index;
 pub mod logger;
+pub mod memory;
+pub mod merge;
+pub mod mqtt;
 pub mod notification;
+pub mod notifier;
+pub mod paths;
 pub mod reminder;
+pub mod retry;
+pub mod sqlite;
 pub mod storage;
+pub mod template;
+pub mod terminal;
 pub mod time_parser;
+pub mod watch;
+pub mod webhook;
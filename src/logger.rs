@@ -1,39 +1,73 @@
-use anyhow::Result;
+use anyhow::{bail, Result};
 use chrono::Local;
+use serde::{Deserialize, Serialize};
 use std::fs::{self, File, OpenOptions};
 use std::io::{BufRead, BufReader, Write};
 use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
 
-const MAX_LOG_SIZE: u64 = 1024 * 1024; // 1MB
 const LOG_FILE_NAME: &str = "reminder.log";
 const OLD_LOG_FILE_NAME: &str = "reminder.log.old";
 
+/// `[log]` settings
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LogConfig {
+    /// Size in KiB at which the log is rotated to `reminder.log.old`
+    pub max_size_kb: u64,
+}
+
+impl Default for LogConfig {
+    fn default() -> Self {
+        Self { max_size_kb: 1024 }
+    }
+}
+
+impl LogConfig {
+    pub fn max_size_bytes(&self) -> u64 {
+        self.max_size_kb.saturating_mul(1024)
+    }
+
+    pub fn validate(&self) -> Result<()> {
+        if self.max_size_kb == 0 {
+            bail!("log.max_size_kb must be at least 1");
+        }
+        Ok(())
+    }
+}
+
 pub struct Logger {
     path: PathBuf,
     old_path: PathBuf,
+    max_size: AtomicU64,
 }
 
 impl Logger {
     pub fn new() -> Result<Self> {
-        let data_dir = dirs::data_local_dir()
-            .ok_or_else(|| anyhow::anyhow!("Failed to get local data directory"))?
-            .join("reminder-cli");
-
-        fs::create_dir_all(&data_dir)?;
+        let data_dir = crate::paths::data_dir()?;
+        let log = crate::config::Config::load()
+            .map(|config| config.log)
+            .unwrap_or_default();
 
         Ok(Self {
             path: data_dir.join(LOG_FILE_NAME),
             old_path: data_dir.join(OLD_LOG_FILE_NAME),
+            max_size: AtomicU64::new(log.max_size_bytes()),
         })
     }
 
+    /// Change the rotation size, e.g. after the config is reloaded
+    pub fn set_max_size(&self, bytes: u64) {
+        self.max_size.store(bytes, Ordering::Relaxed);
+    }
+
     fn rotate_if_needed(&self) -> Result<()> {
         if !self.path.exists() {
             return Ok(());
         }
 
         let metadata = fs::metadata(&self.path)?;
-        if metadata.len() >= MAX_LOG_SIZE {
+        if metadata.len() >= self.max_size.load(Ordering::Relaxed) {
             // Remove old log if exists
             if self.old_path.exists() {
                 fs::remove_file(&self.old_path)?;
@@ -96,7 +130,12 @@ impl Logger {
 
         let file = File::open(&self.path)?;
         let reader = BufReader::new(file);
-        let all_lines: Vec<String> = reader.lines().filter_map(|l| l.ok()).collect();
+        // Skip lines that are not valid UTF-8; stop only on a read error
+        let all_lines: Vec<String> = reader
+            .split(b'\n')
+            .map_while(Result::ok)
+            .filter_map(|line| String::from_utf8(line).ok())
+            .collect();
 
         let start = if all_lines.len() > lines {
             all_lines.len() - lines
@@ -177,3 +216,21 @@ macro_rules! log_debug {
         $crate::logger::get_logger().debug(&format!($($arg)*))
     };
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tail_skips_lines_that_are_not_utf8() {
+        let dir = tempfile::tempdir().unwrap();
+        let logger = Logger {
+            path: dir.path().join(LOG_FILE_NAME),
+            old_path: dir.path().join(OLD_LOG_FILE_NAME),
+            max_size: AtomicU64::new(1024 * 1024),
+        };
+        fs::write(&logger.path, b"first\n\xff\xfe\nlast\n").unwrap();
+
+        assert_eq!(logger.tail(10).unwrap(), ["first", "last"]);
+    }
+}
@@ -1,19 +1,31 @@
-use anyhow::{bail, Result};
+use anyhow::{bail, Context, Result};
 use chrono::Local;
-use clap::{Parser, Subcommand};
+use clap::{Args, Parser, Subcommand};
 use cron::Schedule;
+use reminder_cli::backup::{BackupConfig, Backups};
+use reminder_cli::config::{self, Config};
 use reminder_cli::cron_parser::parse_cron;
+use reminder_cli::crypto::{self, KeySource, Vault};
 use reminder_cli::daemon::{
-    daemon_status, install_autostart, run_daemon_loop, start_daemon, stop_daemon,
+    daemon_status, install_autostart, is_daemon_running, run_daemon_loop, start_daemon, stop_daemon,
 };
+use reminder_cli::git::{self, GitRepo};
+use reminder_cli::ids;
 use reminder_cli::logger::get_logger;
-use reminder_cli::reminder::{Reminder, ReminderSchedule};
-use reminder_cli::storage::Storage;
-use reminder_cli::time_parser::parse_time;
+use reminder_cli::notifier::{NotifierRegistry, KNOWN_CHANNELS};
+use reminder_cli::paths::{self, DATA_DIR_ENV};
+use reminder_cli::reminder::{
+    NotificationStyle, NotificationTimeout, Reminder, ReminderSchedule, Urgency,
+};
+use reminder_cli::retry::{retry_failed, DeadLetterQueue, FailedDelivery};
+use reminder_cli::storage::{write_atomic, Backend, JsonStore, ReminderStore, Storage};
+use reminder_cli::terminal::desktop_session_available;
+use reminder_cli::time_parser::parse_time_with;
 use reminder_cli::{log_info, log_warn};
 use std::collections::HashSet;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::str::FromStr;
+use std::sync::Arc;
 use tabled::settings::object::{Columns, Object, Rows};
 use tabled::settings::{Color, Modify, Style, Width};
 use tabled::{Table, Tabled};
@@ -22,6 +34,11 @@ use tabled::{Table, Tabled};
 #[command(name = "reminder")]
 #[command(about = "A CLI reminder tool with cron support", long_about = None)]
 struct Cli {
+    /// Directory for reminders, backups and logs (overrides
+    /// REMINDER_DATA_DIR)
+    #[arg(long, global = true)]
+    data_dir: Option<PathBuf>,
+
     #[command(subcommand)]
     command: Commands,
 }
@@ -49,6 +66,29 @@ enum Commands {
         /// Tags for categorization (comma-separated)
         #[arg(long, value_delimiter = ',')]
         tags: Option<Vec<String>>,
+
+        /// Name to refer to the reminder by instead of its number or ID
+        #[arg(long, value_parser = parse_alias)]
+        name: Option<String>,
+
+        /// Shell command to run when the reminder fires
+        #[arg(long)]
+        exec: Option<String>,
+
+        /// Webhook URL to post the reminder to when it fires
+        #[arg(long)]
+        webhook: Option<String>,
+
+        /// MQTT topic to publish the reminder to when it fires
+        #[arg(long)]
+        mqtt_topic: Option<String>,
+
+        /// Delivery channels (comma-separated: desktop, terminal, log, email, hook, webhook, mqtt; default: desktop)
+        #[arg(long = "channel", value_delimiter = ',', value_parser = parse_channel)]
+        channels: Option<Vec<String>>,
+
+        #[command(flatten)]
+        style: StyleArgs,
     },
 
     /// List all reminders
@@ -64,20 +104,20 @@ enum Commands {
 
     /// Show details of a specific reminder
     Show {
-        /// ID of the reminder (can use short ID prefix)
+        /// Number, name or ID of the reminder (can use short ID prefix)
         id: String,
     },
 
     /// Delete a reminder
     Delete {
-        /// ID of the reminder to delete (can use short ID prefix)
+        /// Number, name or ID of the reminder to delete (can use short ID prefix)
         #[arg(short, long)]
         id: String,
     },
 
     /// Edit an existing reminder
     Edit {
-        /// ID of the reminder to edit (can use short ID prefix)
+        /// Number, name or ID of the reminder to edit (can use short ID prefix)
         #[arg(short, long)]
         id: String,
 
@@ -104,17 +144,40 @@ enum Commands {
         /// Remove tags (comma-separated)
         #[arg(long, value_delimiter = ',')]
         remove_tags: Option<Vec<String>>,
+
+        /// New name to refer to the reminder by ("" to remove)
+        #[arg(long)]
+        name: Option<String>,
+
+        /// Shell command to run when the reminder fires ("" to remove)
+        #[arg(long)]
+        exec: Option<String>,
+
+        /// Webhook URL to post the reminder to when it fires ("" to remove)
+        #[arg(long)]
+        webhook: Option<String>,
+
+        /// MQTT topic to publish the reminder to when it fires ("" to remove)
+        #[arg(long)]
+        mqtt_topic: Option<String>,
+
+        /// Replace delivery channels (comma-separated: desktop, terminal, log, email, hook, webhook, mqtt)
+        #[arg(long = "channel", value_delimiter = ',', value_parser = parse_channel)]
+        channels: Option<Vec<String>>,
+
+        #[command(flatten)]
+        style: StyleArgs,
     },
 
     /// Pause a reminder
     Pause {
-        /// ID of the reminder to pause
+        /// Number, name or ID of the reminder to pause
         id: String,
     },
 
     /// Resume a paused reminder
     Resume {
-        /// ID of the reminder to resume
+        /// Number, name or ID of the reminder to resume
         id: String,
     },
 
@@ -148,11 +211,143 @@ enum Commands {
         overwrite: bool,
     },
 
+    /// Merge another copy of reminders.json, keeping the newest version of
+    /// each reminder
+    Merge {
+        /// Copy to merge, e.g. one left by a sync tool after a conflict
+        file: PathBuf,
+    },
+
+    /// Show the history of changes to reminders
+    Audit {
+        /// Only changes to this reminder (number, name or ID prefix)
+        #[arg(short, long)]
+        id: Option<String>,
+
+        /// Number of events to show, newest last (default: 50)
+        #[arg(short, long, default_value = "50")]
+        lines: usize,
+    },
+
     /// View and manage logs
     Logs {
         #[command(subcommand)]
         action: LogsAction,
     },
+
+    /// List failed deliveries, both those waiting to be retried and those
+    /// that ran out of retries
+    Failed {
+        #[command(subcommand)]
+        action: Option<FailedAction>,
+    },
+
+    /// Send a test reminder through each channel of the fallback chain
+    NotifyTest {
+        /// Channels to test instead of the fallback chain (comma-separated)
+        #[arg(long = "channel", value_delimiter = ',', value_parser = parse_channel)]
+        channels: Option<Vec<String>>,
+    },
+
+    /// Manage the reminders store
+    Storage {
+        #[command(subcommand)]
+        action: StorageAction,
+    },
+
+    /// Snapshot the data directory and restore it from snapshots
+    Backup {
+        #[command(subcommand)]
+        action: BackupAction,
+    },
+
+    /// Show or change settings in config.toml
+    Config {
+        #[command(subcommand)]
+        action: ConfigAction,
+    },
+
+    /// Pull and push reminders through git; see `sync init`
+    Sync {
+        #[command(subcommand)]
+        action: Option<SyncAction>,
+    },
+}
+
+/// Notification appearance and text options shared by `add` and `edit`
+#[derive(Args)]
+struct StyleArgs {
+    /// Notification icon (icon name or image path)
+    #[arg(long)]
+    icon: Option<String>,
+
+    /// Sound to play (e.g. "message-new-instant" or "Glass" on macOS)
+    #[arg(long)]
+    sound: Option<String>,
+
+    /// Notification urgency: low, normal or critical
+    #[arg(long)]
+    urgency: Option<Urgency>,
+
+    /// Notification category hint (e.g. "im", "email")
+    #[arg(long)]
+    category: Option<String>,
+
+    /// How long the notification stays: "persistent", "default", "30s" or milliseconds
+    #[arg(long)]
+    timeout: Option<NotificationTimeout>,
+
+    /// Notification summary template, e.g. "⏰ {title} (#{occurrence_number})" ("" to reset)
+    #[arg(long)]
+    summary_template: Option<String>,
+
+    /// Notification body template, e.g. "{description} due {scheduled_time:%H:%M}" ("" to reset)
+    #[arg(long)]
+    body_template: Option<String>,
+}
+
+fn parse_alias(s: &str) -> Result<String> {
+    ids::validate_alias(s)?;
+    Ok(s.to_string())
+}
+
+fn parse_channel(s: &str) -> Result<String> {
+    let channel = s.trim().to_lowercase();
+    if !KNOWN_CHANNELS.contains(&channel.as_str()) {
+        bail!(
+            "Unknown channel: {} (expected one of: {})",
+            s,
+            KNOWN_CHANNELS.join(", ")
+        );
+    }
+    Ok(channel)
+}
+
+impl StyleArgs {
+    fn apply(self, reminder: &mut Reminder) {
+        let style = &mut reminder.style;
+        if let Some(icon) = self.icon {
+            style.icon = Some(icon);
+        }
+        if let Some(sound) = self.sound {
+            style.sound = Some(sound);
+        }
+        if let Some(urgency) = self.urgency {
+            style.urgency = Some(urgency);
+        }
+        if let Some(category) = self.category {
+            style.category = Some(category);
+        }
+        if let Some(timeout) = self.timeout {
+            style.timeout = Some(timeout);
+        }
+        if let Some(summary) = self.summary_template {
+            reminder.template.summary = Some(summary).filter(|t| !t.is_empty());
+        }
+        if let Some(body) = self.body_template {
+            reminder.template.body = Some(body).filter(|t| !t.is_empty());
+        }
+    }
 }
 
 #[derive(Subcommand)]
@@ -184,8 +379,130 @@ enum LogsAction {
     Clear,
 }
 
+#[derive(Subcommand)]
+enum FailedAction {
+    /// List failed deliveries (default)
+    List,
+    /// Deliver a failed reminder again
+    Retry {
+        /// ID of the failed delivery
+        #[arg(required_unless_present = "all")]
+        id: Option<String>,
+        /// Retry every failed delivery
+        #[arg(long, conflicts_with = "id")]
+        all: bool,
+    },
+    /// Remove a failed delivery from the queue
+    Dismiss {
+        /// ID of the failed delivery
+        #[arg(required_unless_present = "all")]
+        id: Option<String>,
+        /// Dismiss every failed delivery
+        #[arg(long, conflicts_with = "id")]
+        all: bool,
+    },
+}
+
+#[derive(Subcommand)]
+enum StorageAction {
+    /// Show which backend and file are in use
+    Info,
+    /// Convert the reminders store to another backend
+    Migrate {
+        /// Target backend: json or sqlite
+        #[arg(long)]
+        to: Backend,
+    },
+    /// Restore a corrupted reminders file from the latest valid backup
+    Recover,
+    /// Encrypt the reminders file and its backups
+    Encrypt {
+        /// Use this key file instead of a passphrase; created if missing
+        /// and recorded as encryption.key_file
+        #[arg(long)]
+        key_file: Option<PathBuf>,
+    },
+    /// Turn an encrypted reminders file back into plaintext
+    Decrypt,
+    /// Replace the reminders with the state replayed from the event log
+    Rebuild,
+}
+
+#[derive(Subcommand)]
+enum BackupAction {
+    /// Archive the store, event log, failed deliveries and config now
+    Create,
+    /// List snapshots, newest last
+    List,
+    /// Replace the current data with a snapshot; the current state is
+    /// snapshotted first
+    Restore {
+        /// Snapshot name from `backup list`, or "latest"
+        name: String,
+    },
+    /// Delete snapshots the retention rules in [backup] no longer keep
+    Prune {
+        /// Only show which snapshots would be deleted
+        #[arg(long)]
+        dry_run: bool,
+    },
+}
+
+#[derive(Subcommand)]
+enum SyncAction {
+    /// Keep the data directory in git, committing every change
+    Init {
+        /// Git remote to sync with, e.g. a URL or the path of a bare repository
+        #[arg(long)]
+        remote: Option<String>,
+    },
+    /// Merge driver git runs for reminders.json
+    #[command(hide = true)]
+    MergeDriver {
+        base: PathBuf,
+        ours: PathBuf,
+        theirs: PathBuf,
+    },
+}
+
+#[derive(Subcommand)]
+enum ConfigAction {
+    /// Show every setting, defaults included
+    List,
+    /// Show a setting, or every setting in a section
+    Get {
+        /// Dotted key, e.g. daemon.poll_interval_secs
+        key: String,
+    },
+    /// Change a setting in the config file
+    Set {
+        /// Dotted key, e.g. daemon.poll_interval_secs
+        key: String,
+        /// New value, e.g. 30, true or "18:00"
+        value: String,
+    },
+    /// Open the config file in $VISUAL or $EDITOR
+    Edit,
+}
+
 fn main() -> Result<()> {
     let cli = Cli::parse();
+    if let Some(dir) = &cli.data_dir {
+        // Every path lookup, and the daemon started from here, reads this
+        std::env::set_var(DATA_DIR_ENV, dir);
+    }
+    // Git runs the driver inside the sync repository in the middle of a
+    // merge, so it must neither migrate the data directory nor open the
+    // store there
+    if let Commands::Sync {
+        action: Some(SyncAction::MergeDriver { base, ours, theirs }),
+    } = &cli.command
+    {
+        return run_merge_driver(base, ours, theirs);
+    }
+    for (from, to) in paths::migrate_legacy_layout()? {
+        log_info!("Moved {} to {}", from.display(), to.display());
+    }
     let storage = Storage::new()?;
 
     match cli.command {
@@ -195,7 +512,26 @@ fn main() -> Result<()> {
             time,
             cron,
             tags,
-        } => add_reminder(&storage, title, description, time, cron, tags),
+            name,
+            exec,
+            webhook,
+            mqtt_topic,
+            channels,
+            style,
+        } => add_reminder(
+            &storage,
+            title,
+            description,
+            time,
+            cron,
+            tags,
+            name,
+            exec,
+            webhook,
+            mqtt_topic,
+            channels,
+            style,
+        ),
 
         Commands::List { tag, all } => list_reminders(&storage, tag, all),
 
@@ -211,6 +547,12 @@ fn main() -> Result<()> {
             cron,
             add_tags,
             remove_tags,
+            name,
+            exec,
+            webhook,
+            mqtt_topic,
+            channels,
+            style,
         } => edit_reminder(
             &storage,
             &id,
@@ -220,6 +562,12 @@ fn main() -> Result<()> {
             cron,
             add_tags,
             remove_tags,
+            name,
+            exec,
+            webhook,
+            mqtt_topic,
+            channels,
+            style,
         ),
 
         Commands::Pause { id } => pause_reminder(&storage, &id),
@@ -242,58 +590,141 @@ fn main() -> Result<()> {
 
         Commands::Import { input, overwrite } => import_reminders(&storage, &input, overwrite),
 
+        Commands::Merge { file } => merge_reminders(&storage, &file),
+
+        Commands::Audit { id, lines } => show_audit(&storage, id, lines),
+
         Commands::Logs { action } => match action {
             LogsAction::Show { lines } => show_logs(lines),
             LogsAction::Info => logs_info(),
             LogsAction::Clear => clear_logs(),
         },
+
+        Commands::Failed { action } => match action.unwrap_or(FailedAction::List) {
+            FailedAction::List => list_failed(&storage),
+            FailedAction::Retry { id, all } => retry_failed_deliveries(storage.shared(), id, all),
+            FailedAction::Dismiss { id, all } => dismiss_failed(&storage, id, all),
+        },
+
+        Commands::NotifyTest { channels } => notify_test(storage.shared(), channels),
+
+        Commands::Storage { action } => match action {
+            StorageAction::Info => {
+                println!("Backend: {}", storage.backend());
+                println!("File: {}", storage.path().display());
+                if storage.backend() == Backend::Json {
+                    let encrypted = if storage.is_encrypted() { "yes" } else { "no" };
+                    println!("Encrypted: {}", encrypted);
+                }
+                Ok(())
+            }
+            StorageAction::Migrate { to } => migrate_storage(&storage, to),
+            StorageAction::Recover => recover_storage(&storage),
+            StorageAction::Encrypt { key_file } => encrypt_storage(&storage, key_file),
+            StorageAction::Decrypt => decrypt_storage(&storage),
+            StorageAction::Rebuild => rebuild_storage(&storage),
+        },
+
+        Commands::Sync { action } => match action {
+            None => sync_reminders(&storage),
+            Some(SyncAction::Init { remote }) => init_sync(&storage, remote),
+            Some(SyncAction::MergeDriver { base, ours, theirs }) => {
+                run_merge_driver(&base, &ours, &theirs)
+            }
+        },
+
+        Commands::Backup { action } => match action {
+            BackupAction::Create => create_backup(&storage),
+            BackupAction::List => list_backups(&storage),
+            BackupAction::Restore { name } => restore_backup(&storage, &name),
+            BackupAction::Prune { dry_run } => prune_backups(&storage, dry_run),
+        },
+
+        Commands::Config { action } => match action {
+            ConfigAction::List => config_list(),
+            ConfigAction::Get { key } => config_get(&key),
+            ConfigAction::Set { key, value } => config_set(&key, &value),
+            ConfigAction::Edit => config_edit(),
+        },
     }
 }
 
+#[allow(clippy::too_many_arguments)]
 fn add_reminder(
-    storage: &Storage,
+    storage: &dyn ReminderStore,
     title: String,
     description: Option<String>,
     time: Option<String>,
     cron: Option<String>,
     tags: Option<Vec<String>>,
+    name: Option<String>,
+    exec: Option<String>,
+    webhook: Option<String>,
+    mqtt_topic: Option<String>,
+    channels: Option<Vec<String>>,
+    style: StyleArgs,
 ) -> Result<()> {
     let tags_set: HashSet<String> = tags.unwrap_or_default().into_iter().collect();
 
-    let reminder = if let Some(cron_input) = cron {
+    let mut reminder = if let Some(cron_input) = cron {
         let cron_expr = parse_cron(&cron_input)?;
         Reminder::new_cron(title, description, cron_expr, tags_set)?
     } else if let Some(time_str) = time {
-        let datetime = parse_time(&time_str)?;
+        let datetime = parse_time_with(&time_str, Config::load()?.time.default_time)?;
         Reminder::new_one_time(title, description, datetime, tags_set)
     } else {
         bail!("Either --time or --cron must be specified");
     };
+    reminder.alias = name;
+    // Empty values mean none, as they do for `edit`
+    reminder.exec = exec.filter(|c| !c.trim().is_empty());
+    reminder.webhook = webhook.filter(|u| !u.trim().is_empty());
+    reminder.mqtt_topic = mqtt_topic.filter(|t| !t.trim().is_empty());
+    reminder.channels = channels.unwrap_or_default();
+    style.apply(&mut reminder);
+    trust_hooks(reminder.exec.as_deref(), reminder.webhook.as_deref())?;
+
+    let id = reminder.id;
+    storage.add(reminder)?;
+    // Numbered by the store
+    let reminder = storage
+        .get(id)?
+        .ok_or_else(|| anyhow::anyhow!("Reminder {} vanished after adding it", id))?;
 
     let short_id = &reminder.id.to_string()[..8];
-    log_info!("Added reminder: {} ({})", reminder.title, short_id);
+    log_info!("Added reminder: {} ({})", reminder.title, reminder.handle());
 
     println!("✓ Reminder added successfully!");
+    println!("  Number: {}", reminder.number);
+    if let Some(alias) = &reminder.alias {
+        println!("  Name: {}", alias);
+    }
     println!("  ID: {} (short: {})", reminder.id, short_id);
     println!("  Title: {}", reminder.title);
     if let Some(desc) = &reminder.description {
         println!("  Description: {}", desc);
     }
     if !reminder.tags.is_empty() {
-        println!("  Tags: {}", reminder.tags.iter().cloned().collect::<Vec<_>>().join(", "));
+        println!(
+            "  Tags: {}",
+            reminder.tags.iter().cloned().collect::<Vec<_>>().join(", ")
+        );
     }
     if let Some(next) = reminder.next_trigger {
         println!("  Next trigger: {}", next.format("%Y-%m-%d %H:%M:%S"));
     }
 
-    storage.add(reminder)?;
     Ok(())
 }
 
 #[derive(Tabled)]
 struct ReminderRow {
+    #[tabled(rename = "#")]
+    number: String,
     #[tabled(rename = "ID")]
     id: String,
+    #[tabled(rename = "Name")]
+    alias: String,
     #[tabled(rename = "Title")]
     title: String,
     #[tabled(rename = "Next Trigger")]
@@ -304,7 +735,11 @@ struct ReminderRow {
     status: String,
 }
 
-fn list_reminders(storage: &Storage, tag_filter: Option<String>, show_all: bool) -> Result<()> {
+fn list_reminders(
+    storage: &dyn ReminderStore,
+    tag_filter: Option<String>,
+    show_all: bool,
+) -> Result<()> {
     let mut reminders = if let Some(tag) = tag_filter {
         storage.filter_by_tag(&tag)?
     } else {
@@ -350,7 +785,12 @@ fn list_reminders(storage: &Storage, tag_filter: Option<String>, show_all: bool)
             };
 
             ReminderRow {
+                number: match r.number {
+                    0 => "-".to_string(),
+                    n => n.to_string(),
+                },
                 id: r.id.to_string()[..8].to_string(),
+                alias: r.alias.clone().unwrap_or_else(|| "-".to_string()),
                 title: truncate(&r.title, 25),
                 next_trigger: r
                     .next_trigger
@@ -382,7 +822,7 @@ fn list_reminders(storage: &Storage, tag_filter: Option<String>, show_all: bool)
 
     let mut table = Table::new(rows);
     table.with(Style::rounded());
-    table.with(Modify::new(Columns::single(3)).with(Width::increase(10)));
+    table.with(Modify::new(Columns::single(5)).with(Width::increase(10)));
 
     // Gray for completed
     for row_idx in completed_rows {
@@ -397,7 +837,7 @@ fn list_reminders(storage: &Storage, tag_filter: Option<String>, show_all: bool)
     // Cyan for active one-time
     for row_idx in onetime_rows {
         table.modify(
-            Rows::single(row_idx).intersect(Columns::single(3)),
+            Rows::single(row_idx).intersect(Columns::single(5)),
             Color::FG_CYAN,
         );
     }
@@ -405,7 +845,7 @@ fn list_reminders(storage: &Storage, tag_filter: Option<String>, show_all: bool)
     // Green for active periodic
     for row_idx in periodic_rows {
         table.modify(
-            Rows::single(row_idx).intersect(Columns::single(3)),
+            Rows::single(row_idx).intersect(Columns::single(5)),
             Color::FG_GREEN,
         );
     }
@@ -415,11 +855,17 @@ fn list_reminders(storage: &Storage, tag_filter: Option<String>, show_all: bool)
     Ok(())
 }
 
-fn show_reminder(storage: &Storage, id: &str) -> Result<()> {
+fn show_reminder(storage: &dyn ReminderStore, id: &str) -> Result<()> {
     let reminder = storage
         .find_by_short_id(id)?
         .ok_or_else(|| anyhow::anyhow!("Reminder not found with ID: {}", id))?;
 
+    if reminder.number != 0 {
+        println!("Number:      {}", reminder.number);
+    }
+    if let Some(alias) = &reminder.alias {
+        println!("Name:        {}", alias);
+    }
     println!("ID:          {}", reminder.id);
     println!("Title:       {}", reminder.title);
     if let Some(desc) = &reminder.description {
@@ -449,11 +895,58 @@ fn show_reminder(storage: &Storage, id: &str) -> Result<()> {
         println!("Next:        {}", next.format("%Y-%m-%d %H:%M:%S"));
     }
     println!("Status:      {}", reminder.status());
+    let config = Config::load()?;
+    let untrusted = |trusted: Option<&String>| match trusted {
+        Some(_) => "",
+        None => " (not trusted on this machine)",
+    };
+    if let Some(exec) = &reminder.exec {
+        let note = untrusted(config.trusted_exec(&reminder));
+        println!("Exec:        {}{}", exec, note);
+    }
+    if let Some(webhook) = &reminder.webhook {
+        let note = untrusted(config.trusted_webhook(&reminder));
+        println!("Webhook:     {}{}", webhook, note);
+    }
+    if let Some(topic) = &reminder.mqtt_topic {
+        println!("MQTT topic:  {}", topic);
+    }
+    if !reminder.channels.is_empty() {
+        println!("Channels:    {}", reminder.channels.join(", "));
+    }
+    print_style(&reminder.style);
+    if let Some(summary) = &reminder.template.summary {
+        println!("Summary:     {}", summary);
+    }
+    if let Some(body) = &reminder.template.body {
+        println!("Body:        {}", body);
+    }
+    if reminder.occurrences > 0 {
+        println!("Fired:       {} time(s)", reminder.occurrences);
+    }
 
     Ok(())
 }
 
-fn delete_reminder(storage: &Storage, id: &str) -> Result<()> {
+fn print_style(style: &NotificationStyle) {
+    if let Some(icon) = &style.icon {
+        println!("Icon:        {}", icon);
+    }
+    if let Some(sound) = &style.sound {
+        println!("Sound:       {}", sound);
+    }
+    if let Some(urgency) = style.urgency {
+        println!("Urgency:     {:?}", urgency);
+    }
+    if let Some(category) = &style.category {
+        println!("Category:    {}", category);
+    }
+    if let Some(timeout) = style.timeout {
+        println!("Timeout:     {}", timeout);
+    }
+}
+
+fn delete_reminder(storage: &dyn ReminderStore, id: &str) -> Result<()> {
     match storage.delete_by_short_id(id)? {
         Some(uuid) => {
             log_info!("Deleted reminder: {}", uuid);
@@ -468,8 +961,9 @@ fn delete_reminder(storage: &Storage, id: &str) -> Result<()> {
     Ok(())
 }
 
+#[allow(clippy::too_many_arguments)]
 fn edit_reminder(
-    storage: &Storage,
+    storage: &dyn ReminderStore,
     id: &str,
     title: Option<String>,
     description: Option<String>,
@@ -477,14 +971,26 @@ fn edit_reminder(
     cron: Option<String>,
     add_tags: Option<Vec<String>>,
     remove_tags: Option<Vec<String>>,
+    name: Option<String>,
+    exec: Option<String>,
+    webhook: Option<String>,
+    mqtt_topic: Option<String>,
+    channels: Option<Vec<String>>,
+    style: StyleArgs,
 ) -> Result<()> {
     let reminder = storage
         .find_by_short_id(id)?
         .ok_or_else(|| anyhow::anyhow!("Reminder not found with ID: {}", id))?;
 
     let uuid = reminder.id;
+    let name = name.map(|n| n.trim().to_string());
+    if let Some(name) = name.as_deref().filter(|n| !n.is_empty()) {
+        ids::validate_alias(name)?;
+    }
 
-    let updated = storage.update(uuid, |reminder| {
+    let default_time = Config::load()?.time.default_time;
+    trust_hooks(exec.as_deref(), webhook.as_deref())?;
+    let changes = |reminder: &mut Reminder| {
         if let Some(new_title) = title {
             reminder.title = new_title;
         }
@@ -492,7 +998,7 @@ fn edit_reminder(
             reminder.description = Some(new_desc);
         }
         if let Some(time_str) = time {
-            if let Ok(datetime) = parse_time(&time_str) {
+            if let Ok(datetime) = parse_time_with(&time_str, default_time) {
                 reminder.schedule = ReminderSchedule::OneTime(datetime);
                 reminder.next_trigger = Some(datetime);
                 reminder.completed = false;
@@ -517,13 +1023,33 @@ fn edit_reminder(
                 reminder.tags.remove(&tag);
             }
         }
-    })?;
+        if let Some(name) = name {
+            reminder.alias = Some(name).filter(|n| !n.is_empty());
+        }
+        if let Some(command) = exec {
+            reminder.exec = Some(command).filter(|c| !c.trim().is_empty());
+        }
+        if let Some(url) = webhook {
+            reminder.webhook = Some(url).filter(|u| !u.trim().is_empty());
+        }
+        if let Some(topic) = mqtt_topic {
+            reminder.mqtt_topic = Some(topic).filter(|t| !t.trim().is_empty());
+        }
+        if let Some(channels) = channels {
+            reminder.channels = channels;
+        }
+        style.apply(reminder);
+    };
+    let updated = storage.update(uuid, Box::new(changes))?;
 
     if updated {
         log_info!("Updated reminder: {}", uuid);
         println!("✓ Reminder updated successfully");
         if let Some(reminder) = storage.get(uuid)? {
-            println!("  ID: {}", reminder.id);
+            println!("  ID: {} ({})", reminder.id, reminder.handle());
+            if let Some(alias) = &reminder.alias {
+                println!("  Name: {}", alias);
+            }
             println!("  Title: {}", reminder.title);
             if let Some(desc) = &reminder.description {
                 println!("  Description: {}", desc);
@@ -543,7 +1069,7 @@ fn edit_reminder(
     Ok(())
 }
 
-fn pause_reminder(storage: &Storage, id: &str) -> Result<()> {
+fn pause_reminder(storage: &dyn ReminderStore, id: &str) -> Result<()> {
     match storage.pause_by_short_id(id)? {
         Some(uuid) => {
             log_info!("Paused reminder: {}", &uuid.to_string()[..8]);
@@ -556,7 +1082,7 @@ fn pause_reminder(storage: &Storage, id: &str) -> Result<()> {
     Ok(())
 }
 
-fn resume_reminder(storage: &Storage, id: &str) -> Result<()> {
+fn resume_reminder(storage: &dyn ReminderStore, id: &str) -> Result<()> {
     match storage.resume_by_short_id(id)? {
         Some(uuid) => {
             log_info!("Resumed reminder: {}", &uuid.to_string()[..8]);
@@ -569,8 +1095,8 @@ fn resume_reminder(storage: &Storage, id: &str) -> Result<()> {
     Ok(())
 }
 
-fn list_tags(storage: &Storage) -> Result<()> {
-    let tags = storage.get_all_tags()?;
+fn list_tags(storage: &dyn ReminderStore) -> Result<()> {
+    let tags = storage.tag_counts()?;
 
     if tags.is_empty() {
         println!("No tags found.");
@@ -578,21 +1104,36 @@ fn list_tags(storage: &Storage) -> Result<()> {
     }
 
     println!("Tags:");
-    for tag in tags {
-        let count = storage.filter_by_tag(&tag)?.len();
+    for (tag, count) in tags {
         println!("  {} ({})", tag, count);
     }
 
     Ok(())
 }
 
-fn export_reminders(storage: &Storage, output: &PathBuf) -> Result<()> {
+fn export_reminders(storage: &dyn ReminderStore, output: &Path) -> Result<()> {
     let count = storage.export_to_file(output)?;
     println!("✓ Exported {} reminder(s) to {}", count, output.display());
     Ok(())
 }
 
-fn import_reminders(storage: &Storage, input: &PathBuf, overwrite: bool) -> Result<()> {
+fn merge_reminders(storage: &Storage, file: &Path) -> Result<()> {
+    if !file.exists() {
+        bail!("File not found: {}", file.display());
+    }
+    if file.canonicalize().ok() == storage.path().canonicalize().ok() {
+        bail!("{} is the reminders store itself", file.display());
+    }
+
+    let other = JsonStore::new(file)
+        .document()
+        .with_context(|| format!("Failed to read {}", file.display()))?;
+    let report = storage.merge(other, None)?;
+    println!("✓ Merged {}: {}", file.display(), report);
+    Ok(())
+}
+
+fn import_reminders(storage: &dyn ReminderStore, input: &Path, overwrite: bool) -> Result<()> {
     if !input.exists() {
         bail!("Import file not found: {}", input.display());
     }
@@ -611,7 +1152,51 @@ fn import_reminders(storage: &Storage, input: &PathBuf, overwrite: bool) -> Resu
     Ok(())
 }
 
-fn clean_reminders(storage: &Storage) -> Result<()> {
+fn show_audit(storage: &Storage, id: Option<String>, lines: usize) -> Result<()> {
+    let mut events = storage.events().read()?;
+    if let Some(id) = id {
+        // Deleted reminders are only in the log, by ID prefix
+        match storage.find_by_short_id(&id)? {
+            Some(reminder) => events.retain(|e| e.id == reminder.id),
+            None => events.retain(|e| e.id.to_string().starts_with(&id)),
+        }
+        if events.is_empty() {
+            bail!("No events for reminder {}", id);
+        }
+    }
+
+    if events.is_empty() {
+        println!("No events recorded yet.");
+        return Ok(());
+    }
+
+    let skip = events.len().saturating_sub(lines);
+    for event in &events[skip..] {
+        println!(
+            "{}  {}@{}  {:<8}  {}  {}",
+            event.at.format("%Y-%m-%d %H:%M:%S"),
+            event.user,
+            event.host,
+            event.kind.to_string(),
+            &event.id.to_string()[..8],
+            event.title
+        );
+        for (field, change) in &event.changes {
+            println!("    {}: {} → {}", field, change.old, change.new);
+        }
+    }
+    Ok(())
+}
+
+fn rebuild_storage(storage: &Storage) -> Result<()> {
+    let count = storage.rebuild()?;
+    let log = storage.events().path().display();
+    log_info!("Rebuilt {} reminder(s) from {}", count, log);
+    println!("✓ Rebuilt {} reminder(s) from {}", count, log);
+    Ok(())
+}
+
+fn clean_reminders(storage: &dyn ReminderStore) -> Result<()> {
     let removed = storage.clean_completed()?;
 
     if removed > 0 {
@@ -651,12 +1236,10 @@ fn show_logs(lines: usize) -> Result<()> {
 fn logs_info() -> Result<()> {
     let logger = get_logger();
     let size = logger.size()?;
+    let max_size_kb = Config::load()?.log.max_size_kb;
 
     println!("Log file: {}", logger.path().display());
-    println!(
-        "Size: {:.2} KB / 1024 KB",
-        size as f64 / 1024.0
-    );
+    println!("Size: {:.2} KB / {} KB", size as f64 / 1024.0, max_size_kb);
 
     Ok(())
 }
@@ -667,3 +1250,570 @@ fn clear_logs() -> Result<()> {
     println!("✓ Logs cleared");
     Ok(())
 }
+
+#[derive(Tabled)]
+struct FailedRow {
+    #[tabled(rename = "ID")]
+    id: String,
+    #[tabled(rename = "Reminder")]
+    title: String,
+    #[tabled(rename = "Channel")]
+    channel: String,
+    #[tabled(rename = "Attempts")]
+    attempts: u32,
+    #[tabled(rename = "Failed At")]
+    failed_at: String,
+    #[tabled(rename = "Next Retry")]
+    next_attempt: String,
+    #[tabled(rename = "Error")]
+    error: String,
+}
+
+fn list_failed(storage: &dyn ReminderStore) -> Result<()> {
+    let entries = DeadLetterQueue::for_storage(storage).load()?;
+    if entries.is_empty() {
+        println!("No failed deliveries.");
+        return Ok(());
+    }
+
+    let rows: Vec<FailedRow> = entries
+        .iter()
+        .map(|e| FailedRow {
+            id: e.short_id(),
+            title: truncate(&e.reminder.title, 25),
+            channel: truncate(&e.destination(), 30),
+            attempts: e.attempts,
+            failed_at: e.failed_at.format("%Y-%m-%d %H:%M").to_string(),
+            next_attempt: e
+                .next_attempt
+                .map(|at| at.format("%Y-%m-%d %H:%M").to_string())
+                .unwrap_or_else(|| "-".to_string()),
+            error: truncate(&e.error, 40),
+        })
+        .collect();
+
+    let mut table = Table::new(rows);
+    table.with(Style::rounded());
+    println!("{}", table);
+    println!();
+    println!(
+        "Retry with `reminder failed retry <ID>` or remove with `reminder failed dismiss <ID>`"
+    );
+
+    Ok(())
+}
+
+/// The entries selected by an ID argument or `--all`
+fn select_failed(
+    queue: &DeadLetterQueue,
+    id: Option<String>,
+    all: bool,
+) -> Result<Vec<FailedDelivery>> {
+    if all {
+        return queue.load();
+    }
+    let id = id.unwrap_or_default();
+    match queue.find(&id)? {
+        Some(entry) => Ok(vec![entry]),
+        None => bail!("Failed delivery not found: {}", id),
+    }
+}
+
+fn retry_failed_deliveries(
+    storage: Arc<dyn ReminderStore>,
+    id: Option<String>,
+    all: bool,
+) -> Result<()> {
+    let queue = DeadLetterQueue::for_storage(storage.as_ref());
+    let entries = select_failed(&queue, id, all)?;
+    if entries.is_empty() {
+        println!("No failed deliveries.");
+        return Ok(());
+    }
+
+    let config = Config::load()?;
+    let registry = NotifierRegistry::with_defaults(Arc::clone(&storage));
+    let mut still_failing = 0;
+    for entry in entries {
+        let (short_id, title, channel) = (
+            entry.short_id(),
+            entry.reminder.title.clone(),
+            entry.destination(),
+        );
+        if retry_failed(&registry, &config, &queue, entry)? {
+            println!("✓ Delivered '{}' via {} ({})", title, channel, short_id);
+        } else {
+            println!("✗ '{}' via {} failed again ({})", title, channel, short_id);
+            still_failing += 1;
+        }
+    }
+
+    if still_failing > 0 {
+        bail!(
+            "{} delivery(ies) still failing; see `reminder failed`",
+            still_failing
+        );
+    }
+    Ok(())
+}
+
+fn dismiss_failed(storage: &dyn ReminderStore, id: Option<String>, all: bool) -> Result<()> {
+    let queue = DeadLetterQueue::for_storage(storage);
+    if all {
+        let count = queue.clear()?;
+        println!("✓ Dismissed {} failed delivery(ies)", count);
+        return Ok(());
+    }
+
+    let entry = select_failed(&queue, id, false)?.remove(0);
+    queue.remove(entry.id)?;
+    println!(
+        "✓ Dismissed '{}' via {} ({})",
+        entry.reminder.title,
+        entry.destination(),
+        entry.short_id()
+    );
+    Ok(())
+}
+
+fn notify_test(storage: Arc<dyn ReminderStore>, channels: Option<Vec<String>>) -> Result<()> {
+    let config = Config::load()?;
+    let registry = NotifierRegistry::with_defaults(Arc::clone(&storage));
+    let chain = channels.unwrap_or_else(|| config.delivery.fallback.clone());
+    let reminder = Reminder::new_one_time(
+        "Test notification".to_string(),
+        Some("If you can read this, the channel works".to_string()),
+        Local::now(),
+        HashSet::new(),
+    );
+
+    println!("Testing delivery channels:");
+    let mut working = Vec::new();
+    for channel in &chain {
+        match registry.deliver(channel, &reminder, &config) {
+            Ok(()) => {
+                println!("  ✓ {}", channel);
+                working.push(channel.as_str());
+            }
+            Err(e) => println!("  ✗ {}: {:#}", channel, e),
+        }
+    }
+
+    println!();
+    println!("Fallback chain: {}", chain.join(" → "));
+    match working.first() {
+        Some(channel) => println!("Reminders will be delivered via: {}", channel),
+        None => println!("No channel in the chain works on this machine"),
+    }
+    if !desktop_session_available() {
+        println!("No D-Bus session found; desktop reminders use the terminal channel");
+    }
+
+    Ok(())
+}
+
+fn migrate_storage(storage: &Storage, to: Backend) -> Result<()> {
+    if is_daemon_running()? {
+        bail!("Stop the daemon before migrating: `reminder daemon stop`");
+    }
+
+    let from = storage.backend();
+    let (target, count) = storage.migrate(to)?;
+
+    println!(
+        "✓ Migrated {} reminder(s) from {} to {}",
+        count,
+        from,
+        target.backend()
+    );
+    println!("  Now using: {}", target.path().display());
+    log_info!(
+        "Migrated {} reminders to {}",
+        count,
+        target.path().display()
+    );
+
+    Ok(())
+}
+
+fn encrypt_storage(storage: &Storage, key_file: Option<PathBuf>) -> Result<()> {
+    if is_daemon_running()? {
+        bail!("Stop the daemon before encrypting: `reminder daemon stop`");
+    }
+
+    let source = match key_file {
+        Some(path) => {
+            let path = std::path::absolute(&path)?;
+            if !path.exists() {
+                crypto::generate_key_file(&path)?;
+                println!("✓ Created key file {}", path.display());
+            }
+            KeySource::KeyFile(path)
+        }
+        None => match std::env::var(crypto::PASSPHRASE_ENV) {
+            Ok(passphrase) => KeySource::Passphrase(passphrase.into()),
+            Err(_) => KeySource::Passphrase(crypto::prompt_new_passphrase()?),
+        },
+    };
+    // Check the key can be read before anything is rewritten
+    let vault = Vault::new(source.clone());
+    vault.encrypt(b"")?;
+    let count = storage.encrypt(&vault)?;
+
+    println!(
+        "✓ Encrypted {} reminder(s) in {}",
+        count,
+        storage.path().display()
+    );
+    if let KeySource::KeyFile(path) = &source {
+        update_config("encryption.key_file", &path.to_string_lossy())?;
+        println!("  Key file recorded as encryption.key_file; keep a copy somewhere safe");
+    } else {
+        println!(
+            "  For the daemon, set encryption.passphrase_command or {}",
+            crypto::PASSPHRASE_ENV
+        );
+    }
+    log_info!("Encrypted reminders store");
+
+    Ok(())
+}
+
+fn decrypt_storage(storage: &Storage) -> Result<()> {
+    if is_daemon_running()? {
+        bail!("Stop the daemon before decrypting: `reminder daemon stop`");
+    }
+
+    let count = storage.decrypt()?;
+    println!(
+        "✓ Decrypted {} reminder(s) in {}",
+        count,
+        storage.path().display()
+    );
+    log_info!("Decrypted reminders store");
+
+    Ok(())
+}
+
+fn recover_storage(storage: &Storage) -> Result<()> {
+    let recovery = storage.recover()?;
+
+    println!(
+        "✓ Restored {} reminder(s) from {}",
+        recovery.count,
+        recovery.backup.display()
+    );
+    if let Some(corrupted) = &recovery.corrupted {
+        println!("  Corrupted file kept as: {}", corrupted.display());
+    }
+    log_warn!(
+        "Recovered reminders from backup {}",
+        recovery.backup.display()
+    );
+
+    Ok(())
+}
+
+/// Snapshots of the data directory, with the `[backup]` settings. A broken
+/// config must not stand in the way of restoring a working one.
+fn backups(storage: &Storage) -> Result<(Backups, BackupConfig)> {
+    let config = match Config::load() {
+        Ok(config) => config.backup,
+        Err(e) => {
+            log_warn!("Using default backup settings: {:#}", e);
+            BackupConfig::default()
+        }
+    };
+    let backups = Backups::new(storage.path(), paths::config_file()?, &config);
+    Ok((backups, config))
+}
+
+fn create_backup(storage: &Storage) -> Result<()> {
+    let (backups, _) = backups(storage)?;
+    let snapshot = backups.create()?;
+
+    println!("✓ Created {}", snapshot.path.display());
+    println!("  Files: {}", snapshot.files()?.join(", "));
+    log_info!("Created backup {}", snapshot.name());
+
+    Ok(())
+}
+
+#[derive(Tabled)]
+struct BackupRow {
+    #[tabled(rename = "Name")]
+    name: String,
+    #[tabled(rename = "Created")]
+    created: String,
+    #[tabled(rename = "Size")]
+    size: String,
+}
+
+fn list_backups(storage: &Storage) -> Result<()> {
+    let (backups, _) = backups(storage)?;
+    let snapshots = backups.list()?;
+    if snapshots.is_empty() {
+        println!("No backups in {}.", backups.dir().display());
+        return Ok(());
+    }
+
+    let rows: Vec<BackupRow> = snapshots
+        .iter()
+        .map(|s| BackupRow {
+            name: s.name(),
+            created: s.created.format("%Y-%m-%d %H:%M:%S").to_string(),
+            size: format!("{:.1} KB", s.size as f64 / 1024.0),
+        })
+        .collect();
+
+    let mut table = Table::new(rows);
+    table.with(Style::rounded());
+    println!("{}", table);
+    println!("Directory: {}", backups.dir().display());
+
+    Ok(())
+}
+
+fn restore_backup(storage: &Storage, name: &str) -> Result<()> {
+    if is_daemon_running()? {
+        bail!("Stop the daemon before restoring: `reminder daemon stop`");
+    }
+
+    let (backups, _) = backups(storage)?;
+    let snapshot = backups.find(name)?;
+    let restore = backups.restore(&snapshot)?;
+
+    println!("✓ Restored {}", snapshot.name());
+    for file in &restore.files {
+        println!("  {}", file.display());
+    }
+    for file in &restore.moved {
+        println!("  Moved aside: {}", file.display());
+    }
+    println!("  Previous state saved as {}", restore.safety.name());
+    log_warn!(
+        "Restored backup {}; previous state saved as {}",
+        snapshot.name(),
+        restore.safety.name()
+    );
+
+    Ok(())
+}
+
+fn prune_backups(storage: &Storage, dry_run: bool) -> Result<()> {
+    let (backups, config) = backups(storage)?;
+    let expired = if dry_run {
+        backups.expired(&config)?
+    } else {
+        backups.prune(&config)?
+    };
+
+    if expired.is_empty() {
+        println!("No backups to prune");
+        return Ok(());
+    }
+    let verb = if dry_run { "Would delete" } else { "Deleted" };
+    for snapshot in &expired {
+        println!("  {} {}", verb, snapshot.name());
+    }
+    if !dry_run {
+        println!("✓ Pruned {} backup(s)", expired.len());
+        log_info!("Pruned {} backup(s)", expired.len());
+    }
+
+    Ok(())
+}
+
+fn init_sync(storage: &Storage, remote: Option<String>) -> Result<()> {
+    if storage.backend() != Backend::Json {
+        bail!("Only JSON stores can be synced; run `reminder storage migrate --to json` first");
+    }
+    if storage.is_encrypted() {
+        bail!("Encrypted stores cannot be synced; run `reminder storage decrypt` first");
+    }
+
+    let dir = storage
+        .path()
+        .parent()
+        .context("Reminders file has no directory")?;
+    let repo = GitRepo::init(dir, remote.as_deref())?;
+    println!("✓ Tracking reminders in git at {}", repo.dir().display());
+    match repo.remote_url()? {
+        Some(url) => println!("  Remote: {}; run `reminder sync` to pull and push", url),
+        None => println!("  Add a remote with `reminder sync init --remote <url>`"),
+    }
+    log_info!("Initialised git sync in {}", repo.dir().display());
+
+    Ok(())
+}
+
+fn sync_reminders(storage: &Storage) -> Result<()> {
+    let Some(repo) = storage.repo() else {
+        bail!("Sync is not set up; run `reminder sync init --remote <url>` first");
+    };
+
+    let before = storage.load()?;
+    let report = repo.sync(&std::env::current_exe()?)?;
+    if report.pulled > 0 {
+        storage.log_changes(&before)?;
+    }
+    if report.pulled == 0 && report.pushed == 0 {
+        println!("✓ Already up to date");
+    } else {
+        println!(
+            "✓ Synced: pulled {} commit(s), pushed {}",
+            report.pulled, report.pushed
+        );
+    }
+    log_info!(
+        "Synced reminders: pulled {}, pushed {}",
+        report.pulled,
+        report.pushed
+    );
+
+    Ok(())
+}
+
+fn run_merge_driver(base: &Path, ours: &Path, theirs: &Path) -> Result<()> {
+    let conflicts = git::merge_driver(base, ours, theirs)?;
+    if conflicts > 0 {
+        log_warn!(
+            "Merged reminders; kept local values for {} field(s) changed on both sides",
+            conflicts
+        );
+    }
+    Ok(())
+}
+
+fn config_list() -> Result<()> {
+    println!("# {}", paths::config_file()?.display());
+    for (key, value) in Config::load()?.entries()? {
+        println!("{} = {}", key, value);
+    }
+    Ok(())
+}
+
+fn config_get(key: &str) -> Result<()> {
+    let entries = Config::load()?.get(key)?;
+    match entries.as_slice() {
+        [(k, value)] if k == key => println!("{}", value.trim_matches('"')),
+        _ => {
+            for (key, value) in entries {
+                println!("{} = {}", key, value);
+            }
+        }
+    }
+    Ok(())
+}
+
+fn config_set(key: &str, value: &str) -> Result<()> {
+    let updated = update_config(key, value)?;
+    let entries = Config::parse(&updated)?.get(key)?;
+    for (key, value) in entries {
+        println!("✓ {} = {}", key, value);
+    }
+    log_info!("Config {} set to {}", key, value);
+    Ok(())
+}
+
+/// Set `key` in the config file, returning its new contents
+fn update_config(key: &str, value: &str) -> Result<String> {
+    let path = paths::config_file()?;
+    let updated = config::set_value(&read_config(&path)?, key, value)?;
+    write_atomic(&path, updated.as_bytes())?;
+    Ok(updated)
+}
+
+/// Let the `--exec` command and `--webhook` URL given here run on this
+/// machine; ones that arrive with reminders from elsewhere do not
+fn trust_hooks(exec: Option<&str>, webhook: Option<&str>) -> Result<()> {
+    let entries = [("trusted.exec", exec), ("trusted.webhooks", webhook)];
+    for (key, value) in entries {
+        let Some(value) = value.filter(|v| !v.trim().is_empty()) else {
+            continue;
+        };
+        let path = paths::config_file()?;
+        if let Some(updated) = config::add_value(&read_config(&path)?, key, value)? {
+            write_atomic(&path, updated.as_bytes())?;
+        }
+    }
+    Ok(())
+}
+
+/// The config file's contents; empty if there is none yet
+fn read_config(path: &Path) -> Result<String> {
+    if !path.exists() {
+        return Ok(String::new());
+    }
+    std::fs::read_to_string(path).context("Failed to read config file")
+}
+
+#[cfg(unix)]
+const DEFAULT_EDITOR: &str = "vi";
+#[cfg(windows)]
+const DEFAULT_EDITOR: &str = "notepad";
+
+/// Run `editor` on `file` through the shell, so that editors given with
+/// arguments work
+#[cfg(unix)]
+fn editor_command(editor: &str, file: &Path) -> std::process::Command {
+    let mut command = std::process::Command::new("sh");
+    command
+        .arg("-c")
+        .arg(format!("{} \"$1\"", editor))
+        .arg("sh")
+        .arg(file);
+    command
+}
+
+#[cfg(windows)]
+fn editor_command(editor: &str, file: &Path) -> std::process::Command {
+    use std::os::windows::process::CommandExt;
+
+    // Passed as is; cmd does not read the quoting other programs expect
+    let mut command = std::process::Command::new("cmd");
+    command
+        .arg("/C")
+        .raw_arg(format!("{} \"{}\"", editor, file.display()));
+    command
+}
+
+fn config_edit() -> Result<()> {
+    let path = paths::config_file()?;
+    let draft = path.with_extension("toml.edit");
+    if path.exists() {
+        std::fs::copy(&path, &draft).context("Failed to copy config file")?;
+    } else {
+        std::fs::write(&draft, "").context("Failed to create config file")?;
+    }
+
+    let editor = std::env::var("VISUAL")
+        .or_else(|_| std::env::var("EDITOR"))
+        .unwrap_or_else(|_| DEFAULT_EDITOR.to_string());
+    let status = editor_command(&editor, &draft)
+        .status()
+        .with_context(|| format!("Failed to run editor '{}'", editor))?;
+    if !status.success() {
+        bail!(
+            "Editor '{}' exited with {}; your changes are in {}",
+            editor,
+            status,
+            draft.display()
+        );
+    }
+
+    let content = std::fs::read_to_string(&draft).context("Failed to read edited config")?;
+    if let Err(e) = Config::parse(&content) {
+        bail!(
+            "{:#}\nThe config file was not changed; your edits are in {}",
+            e,
+            draft.display()
+        );
+    }
+    write_atomic(&path, content.as_bytes())?;
+    std::fs::remove_file(&draft).ok();
+
+    println!("✓ Saved {}", path.display());
+    log_info!("Config edited");
+    Ok(())
+}
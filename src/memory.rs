@@ -0,0 +1,88 @@
+use crate::index::ReminderIndex;
+use crate::reminder::Reminder;
+use crate::storage::{Mutation, ReminderStore};
+use anyhow::Result;
+use std::sync::{Arc, Mutex, MutexGuard};
+
+/// Reminders kept in memory only, for tests and for embedding the crate
+/// without touching the filesystem
+#[derive(Debug, Default)]
+pub struct MemoryStore {
+    index: Mutex<Arc<ReminderIndex>>,
+}
+
+impl MemoryStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_reminders(reminders: Vec<Reminder>) -> Self {
+        Self {
+            index: Mutex::new(Arc::new(ReminderIndex::new(reminders))),
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Arc<ReminderIndex>> {
+        // A panicking updater cannot leave the index half-written
+        self.index.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl ReminderStore for MemoryStore {
+    fn load(&self) -> Result<Vec<Reminder>> {
+        Ok(self.lock().to_vec())
+    }
+
+    fn save(&self, reminders: &[Reminder]) -> Result<()> {
+        let mut index = self.lock();
+        let mut saved = ReminderIndex::new(reminders.to_vec());
+        saved.set_last_number(index.max_number());
+        *index = Arc::new(saved);
+        Ok(())
+    }
+
+    fn transaction(&self, mutation: Mutation) -> Result<()> {
+        let mut index = self.lock();
+        // Work on a copy so a failed mutation leaves the store untouched
+        let mut changed = (**index).clone();
+        if mutation(&mut changed)? {
+            changed.clear_changes();
+            *index = Arc::new(changed);
+        }
+        Ok(())
+    }
+
+    fn index(&self) -> Result<Arc<ReminderIndex>> {
+        Ok(Arc::clone(&self.lock()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Local;
+    use std::collections::HashSet;
+
+    #[test]
+    fn test_store_operations() {
+        let store = MemoryStore::new();
+        let reminder = Reminder::new_one_time(
+            "Water plants".to_string(),
+            None,
+            Local::now(),
+            HashSet::from(["home".to_string()]),
+        );
+        let id = reminder.id;
+        store.add(reminder).unwrap();
+
+        let short_id = &id.to_string()[..8];
+        assert_eq!(store.find_by_short_id(short_id).unwrap().unwrap().id, id);
+        assert_eq!(store.pause_by_short_id(short_id).unwrap(), Some(id));
+        assert!(store.get(id).unwrap().unwrap().paused);
+        assert_eq!(store.filter_by_tag("home").unwrap().len(), 1);
+        assert!(store.filter_by_tag("work").unwrap().is_empty());
+
+        assert_eq!(store.delete_by_short_id(short_id).unwrap(), Some(id));
+        assert!(store.load().unwrap().is_empty());
+    }
+}
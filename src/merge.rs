@@ -0,0 +1,375 @@
+use crate::index::ReminderIndex;
+use crate::reminder::Reminder;
+use anyhow::{Context, Result};
+use chrono::{DateTime, Duration, Local};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::hash_map::Entry;
+use std::collections::HashMap;
+use uuid::Uuid;
+
+/// Days a deleted reminder's tombstone is kept. A copy out of sync for
+/// longer than this can bring the reminder back.
+pub const TOMBSTONE_DAYS: i64 = 90;
+
+/// Marks a deleted reminder, so merging an older copy that still has it
+/// does not bring it back
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Tombstone {
+    pub id: Uuid,
+    pub deleted_at: DateTime<Local>,
+    /// One more than the reminder's last revision
+    pub revision: u32,
+}
+
+/// Everything in a reminders file and the file kept next to it: the
+/// reminders, tombstones of deleted ones and the highest number handed out
+#[derive(Debug, Clone, Default)]
+pub struct Document {
+    pub reminders: Vec<Reminder>,
+    pub tombstones: Vec<Tombstone>,
+    pub last_number: u32,
+}
+
+/// What is kept next to a reminders file, in `<file>.meta`. Older versions
+/// read the file itself as a plain list of reminders, so nothing else can
+/// go there.
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+struct Meta {
+    last_number: u32,
+    tombstones: Vec<Tombstone>,
+}
+
+/// What merging another copy changed
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct MergeReport {
+    pub added: usize,
+    pub updated: usize,
+    pub deleted: usize,
+}
+
+impl MergeReport {
+    pub fn is_empty(&self) -> bool {
+        self.added == 0 && self.updated == 0 && self.deleted == 0
+    }
+}
+
+impl std::fmt::Display for MergeReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} added, {} updated, {} deleted",
+            self.added, self.updated, self.deleted
+        )
+    }
+}
+
+/// One copy's version of a reminder
+enum Version {
+    Live(Box<Reminder>),
+    Deleted(Tombstone),
+}
+
+impl Version {
+    fn id(&self) -> Uuid {
+        match self {
+            Version::Live(r) => r.id,
+            Version::Deleted(t) => t.id,
+        }
+    }
+
+    /// Higher revision wins, then the later change. Exact ties fall back
+    /// to comparing the serialized versions, so every device picks the same.
+    fn beats(&self, other: &Version) -> bool {
+        let rank = |v: &Version| match v {
+            Version::Live(r) => (r.revision, r.updated_at),
+            Version::Deleted(t) => (t.revision, Some(t.deleted_at)),
+        };
+        let text = |v: &Version| match v {
+            Version::Live(r) => serde_json::to_string(r),
+            Version::Deleted(t) => serde_json::to_string(t),
+        };
+        match rank(self).cmp(&rank(other)) {
+            std::cmp::Ordering::Equal => text(self).ok() > text(other).ok(),
+            ordering => ordering.is_gt(),
+        }
+    }
+}
+
+impl Document {
+    /// Read a reminders file. Tombstones among the reminders, as earlier
+    /// versions of this one kept them, are read too.
+    pub fn parse(content: &[u8]) -> Result<Self> {
+        let mut document = Self::default();
+        if content.iter().all(u8::is_ascii_whitespace) {
+            return Ok(document);
+        }
+
+        let items: Vec<Value> =
+            serde_json::from_slice(content).context("Failed to parse reminders JSON")?;
+        for (index, item) in items.into_iter().enumerate() {
+            if item.get("deleted_at").is_some() {
+                document.tombstones.push(
+                    serde_json::from_value(item)
+                        .with_context(|| format!("Invalid tombstone at index {}", index))?,
+                );
+            } else {
+                document.reminders.push(
+                    serde_json::from_value(item)
+                        .with_context(|| format!("Invalid reminder at index {}", index))?,
+                );
+            }
+        }
+        Ok(document)
+    }
+
+    /// Add what the file next to the reminders file keeps
+    pub fn parse_meta(&mut self, content: &[u8]) -> Result<()> {
+        let meta: Meta = serde_json::from_slice(content).context("Failed to parse metadata")?;
+        self.tombstones.extend(meta.tombstones);
+        self.last_number = self.last_number.max(meta.last_number);
+        Ok(())
+    }
+
+    /// The reminders file: a plain list of reminders
+    pub fn to_json(&self) -> Result<Vec<u8>> {
+        serde_json::to_vec_pretty(&self.reminders).context("Failed to serialize reminders")
+    }
+
+    /// The file kept next to the reminders file
+    pub fn meta_to_json(&self) -> Result<Vec<u8>> {
+        let meta = Meta {
+            last_number: self.last_number,
+            tombstones: self.tombstones.clone(),
+        };
+        serde_json::to_vec_pretty(&meta).context("Failed to serialize metadata")
+    }
+
+    /// Bump the revision of every reminder changed since `before`, leave
+    /// tombstones for deleted ones and drop expired tombstones
+    pub fn record_changes(&mut self, before: &[Reminder]) {
+        let mut index = ReminderIndex::new(before.to_vec());
+        index.replace_all(std::mem::take(&mut self.reminders));
+        record_changes(&mut index, &mut self.tombstones, &HashMap::new());
+        self.reminders = index.to_vec();
+    }
+
+    /// Merge another copy into this one, keeping the newest version of
+    /// each reminder. The result does not depend on which copy is merged
+    /// into which, apart from the order of reminders.
+    pub fn merge(&mut self, other: Document) -> MergeReport {
+        self.last_number = self.last_number.max(other.last_number);
+        let before: HashMap<Uuid, Value> = self
+            .reminders
+            .iter()
+            .map(|r| (r.id, serde_json::to_value(r).unwrap_or_default()))
+            .collect();
+
+        let versions = std::mem::take(&mut self.reminders)
+            .into_iter()
+            .chain(other.reminders)
+            .map(Box::new)
+            .map(Version::Live)
+            .chain(
+                std::mem::take(&mut self.tombstones)
+                    .into_iter()
+                    .chain(other.tombstones)
+                    .map(Version::Deleted),
+            );
+
+        let mut order = Vec::new();
+        let mut newest: HashMap<Uuid, Version> = HashMap::new();
+        for version in versions {
+            match newest.entry(version.id()) {
+                Entry::Vacant(slot) => {
+                    order.push(version.id());
+                    slot.insert(version);
+                }
+                Entry::Occupied(mut slot) => {
+                    if version.beats(slot.get()) {
+                        slot.insert(version);
+                    }
+                }
+            }
+        }
+
+        let mut report = MergeReport::default();
+        for id in order {
+            match newest.remove(&id) {
+                Some(Version::Live(reminder)) => {
+                    match before.get(&id) {
+                        None => report.added += 1,
+                        Some(old)
+                            if *old != serde_json::to_value(&reminder).unwrap_or_default() =>
+                        {
+                            report.updated += 1
+                        }
+                        Some(_) => {}
+                    }
+                    self.reminders.push(*reminder);
+                }
+                Some(Version::Deleted(tombstone)) => {
+                    if before.contains_key(&id) {
+                        report.deleted += 1;
+                    }
+                    self.tombstones.push(tombstone);
+                }
+                None => {}
+            }
+        }
+        report
+    }
+}
+
+/// [`Document::record_changes`] for the changes tracked by `index`, so only
+/// the reminders they touched are looked at. `merged` has the reminders
+/// merged in from another copy before the change, as merged; they count
+/// from there.
+pub fn record_changes(
+    index: &mut ReminderIndex,
+    tombstones: &mut Vec<Tombstone>,
+    merged: &HashMap<Uuid, Option<Reminder>>,
+) {
+    let now = Local::now();
+    let buried: HashMap<Uuid, u32> = tombstones.iter().map(|t| (t.id, t.revision)).collect();
+    let mut deleted = Vec::new();
+    for id in index.changed_ids().to_vec() {
+        let previous = match merged.get(&id) {
+            Some(merged) => merged.as_ref(),
+            None => index.original(id).flatten(),
+        };
+        let since = match (previous, index.get(id)) {
+            (Some(previous), Some(reminder)) if same(previous, reminder) => continue,
+            (Some(previous), Some(_)) => previous.revision,
+            // Added, or restored over its own tombstone
+            (None, Some(_)) => buried.get(&id).copied().unwrap_or(0),
+            (Some(previous), None) => {
+                deleted.push(Tombstone {
+                    id,
+                    deleted_at: now,
+                    revision: previous.revision + 1,
+                });
+                continue;
+            }
+            (None, None) => continue,
+        };
+        index.update(id, |reminder| reminder.touch(since));
+    }
+
+    tombstones.retain(|t| {
+        index.get(t.id).is_none() && now - t.deleted_at < Duration::days(TOMBSTONE_DAYS)
+    });
+    tombstones.extend(deleted);
+}
+
+fn same(a: &Reminder, b: &Reminder) -> bool {
+    serde_json::to_value(a).ok() == serde_json::to_value(b).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+
+    fn reminder(title: &str) -> Reminder {
+        Reminder::new_one_time(
+            title.to_string(),
+            None,
+            Local::now() + Duration::hours(1),
+            HashSet::new(),
+        )
+    }
+
+    fn titles(document: &Document) -> Vec<&str> {
+        document
+            .reminders
+            .iter()
+            .map(|r| r.title.as_str())
+            .collect()
+    }
+
+    #[test]
+    fn test_record_changes() {
+        let (a, b) = (reminder("a"), reminder("b"));
+        let mut document = Document {
+            reminders: vec![a, b.clone()],
+            ..Document::default()
+        };
+        document.record_changes(&[]);
+        assert!(document.reminders.iter().all(|r| r.revision == 1));
+
+        let before = document.reminders.clone();
+        document.reminders[0].title = "a renamed".to_string();
+        document.reminders.remove(1);
+        document.record_changes(&before);
+        assert_eq!(document.reminders[0].revision, 2);
+        assert_eq!(document.tombstones.len(), 1);
+        assert_eq!(document.tombstones[0].id, b.id);
+        assert_eq!(document.tombstones[0].revision, 2);
+
+        // Round trip through the file format; the file itself stays a
+        // plain list of reminders
+        let mut parsed = Document::parse(&document.to_json().unwrap()).unwrap();
+        assert_eq!(titles(&parsed), ["a renamed"]);
+        assert!(parsed.tombstones.is_empty());
+        let plain: Vec<Reminder> = serde_json::from_slice(&document.to_json().unwrap()).unwrap();
+        assert_eq!(plain.len(), 1);
+        parsed
+            .parse_meta(&document.meta_to_json().unwrap())
+            .unwrap();
+        assert_eq!(parsed.tombstones, document.tombstones);
+    }
+
+    #[test]
+    fn test_merge_is_deterministic() {
+        let (a, b, c) = (reminder("a"), reminder("b"), reminder("c"));
+        let mut base = Document {
+            reminders: vec![a, b, c],
+            ..Document::default()
+        };
+        base.record_changes(&[]);
+
+        // Laptop edits a twice and deletes c; desktop edits a once, edits
+        // b and adds d
+        let mut laptop = base.clone();
+        let before = laptop.reminders.clone();
+        laptop.reminders[0].title = "a laptop".to_string();
+        laptop.reminders.retain(|r| r.title != "c");
+        laptop.record_changes(&before);
+        let before = laptop.reminders.clone();
+        laptop.reminders[0].paused = true;
+        laptop.record_changes(&before);
+
+        let mut desktop = base.clone();
+        let before = desktop.reminders.clone();
+        desktop.reminders[0].title = "a desktop".to_string();
+        desktop.reminders[1].title = "b desktop".to_string();
+        desktop.reminders.push(reminder("d"));
+        desktop.record_changes(&before);
+
+        let mut one = laptop.clone();
+        let report = one.merge(desktop.clone());
+        assert_eq!(
+            report,
+            MergeReport {
+                added: 1,
+                updated: 1,
+                deleted: 0
+            }
+        );
+        assert_eq!(titles(&one), ["a laptop", "b desktop", "d"]);
+
+        let mut other = desktop;
+        let report = other.merge(laptop);
+        assert_eq!(report.deleted, 1);
+        let mut sorted = titles(&other);
+        sorted.sort();
+        assert_eq!(sorted, ["a laptop", "b desktop", "d"]);
+        assert_eq!(one.tombstones, other.tombstones);
+
+        // Merging the same copy again changes nothing
+        assert!(one.merge(other).is_empty());
+    }
+}
@@ -0,0 +1,202 @@
+use crate::config::Config;
+use crate::reminder::Reminder;
+use crate::template::{fired_event, Message, Placeholders};
+use anyhow::{bail, Context, Result};
+use rumqttc::{Client, Event, MqttOptions, Outgoing, Packet, QoS};
+use serde::{Deserialize, Serialize};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+pub const DEFAULT_MQTT_PORT: u16 = 1883;
+pub const DEFAULT_MQTT_TOPIC: &str = "reminder-cli/fired";
+pub const DEFAULT_MQTT_PASSWORD_ENV: &str = "REMINDER_MQTT_PASSWORD";
+pub const DEFAULT_MQTT_TIMEOUT_SECS: u64 = 10;
+
+/// Broker settings for the `mqtt` channel
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct MqttConfig {
+    pub host: String,
+
+    #[serde(default = "default_port")]
+    pub port: u16,
+
+    /// Prefix of the client id; each connection appends the process id and a
+    /// counter, since a broker disconnects a client whose id reconnects
+    #[serde(default = "default_client_id")]
+    pub client_id: String,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub username: Option<String>,
+
+    /// Environment variable holding the password
+    #[serde(default = "default_password_env")]
+    pub password_env: String,
+
+    /// Topic for reminders without their own or a tag's; may use template
+    /// placeholders such as `{short_id}`
+    #[serde(default = "default_topic")]
+    pub topic: String,
+
+    /// 0, 1 or 2
+    #[serde(default = "default_qos")]
+    pub qos: u8,
+
+    #[serde(default)]
+    pub retain: bool,
+
+    #[serde(default = "default_timeout")]
+    pub timeout_secs: u64,
+}
+
+fn default_port() -> u16 {
+    DEFAULT_MQTT_PORT
+}
+
+fn default_client_id() -> String {
+    "reminder-cli".to_string()
+}
+
+fn default_password_env() -> String {
+    DEFAULT_MQTT_PASSWORD_ENV.to_string()
+}
+
+fn default_topic() -> String {
+    DEFAULT_MQTT_TOPIC.to_string()
+}
+
+fn default_qos() -> u8 {
+    1
+}
+
+fn default_timeout() -> u64 {
+    DEFAULT_MQTT_TIMEOUT_SECS
+}
+
+/// Topics configured for a reminder: its own `--mqtt-topic` followed by
+/// those of its tags
+pub fn topics_for(reminder: &Reminder, config: &Config) -> Vec<String> {
+    let mut topics: Vec<String> = reminder.mqtt_topic.iter().cloned().collect();
+
+    let mut tags: Vec<&String> = reminder.tags.iter().collect();
+    tags.sort();
+    for tag in tags {
+        if let Some(topic) = config.tags.get(tag).and_then(|t| t.mqtt_topic.as_ref()) {
+            if !topics.contains(topic) {
+                topics.push(topic.clone());
+            }
+        }
+    }
+
+    topics
+}
+
+/// Publish the fired-reminder event to the reminder's topics, or to the
+/// default topic when it has none
+pub fn publish(mqtt: &MqttConfig, reminder: &Reminder, config: &Config) -> Result<()> {
+    let mut topics = topics_for(reminder, config);
+    if topics.is_empty() {
+        topics.push(mqtt.topic.clone());
+    }
+    let placeholders = Placeholders::new(reminder);
+    let topics: Vec<String> = topics.iter().map(|t| placeholders.expand(t)).collect();
+
+    let message = Message::render(reminder, config);
+    let payload = fired_event(reminder, &message).to_string();
+    publish_to(mqtt, &topics, payload.as_bytes())
+}
+
+/// Connections made by this process so far
+static CONNECTIONS: AtomicU64 = AtomicU64::new(0);
+
+/// `prefix` made unique to this connection, so concurrent publishes from the
+/// daemon, `test` and retries do not take over each other's session
+fn client_id(prefix: &str) -> String {
+    let n = CONNECTIONS.fetch_add(1, Ordering::Relaxed);
+    format!("{}-{}-{}", prefix, std::process::id(), n)
+}
+
+/// Connect, publish `payload` to every topic and wait until the broker has
+/// acknowledged them (QoS 1 and 2) or they have been sent (QoS 0)
+pub fn publish_to(mqtt: &MqttConfig, topics: &[String], payload: &[u8]) -> Result<()> {
+    if topics.is_empty() {
+        return Ok(());
+    }
+    let qos = match mqtt.qos {
+        0 => QoS::AtMostOnce,
+        1 => QoS::AtLeastOnce,
+        2 => QoS::ExactlyOnce,
+        other => bail!("Invalid MQTT QoS {}: expected 0, 1 or 2", other),
+    };
+
+    let mut options = MqttOptions::new(client_id(&mqtt.client_id), &mqtt.host, mqtt.port);
+    options.set_keep_alive(Duration::from_secs(mqtt.timeout_secs.max(5)));
+    if let Some(username) = &mqtt.username {
+        let password = std::env::var(&mqtt.password_env).with_context(|| {
+            format!(
+                "MQTT password not found in environment variable {}",
+                mqtt.password_env
+            )
+        })?;
+        options.set_credentials(username, password);
+    }
+
+    let (client, mut connection) = Client::new(options, topics.len().max(1) + 1);
+    for topic in topics {
+        client
+            .publish(topic, qos, mqtt.retain, payload)
+            .context("Failed to queue MQTT publish")?;
+    }
+
+    let deadline = Instant::now() + Duration::from_secs(mqtt.timeout_secs);
+    let mut pending = topics.len();
+    let mut disconnecting = false;
+    loop {
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        let event = match connection.recv_timeout(remaining) {
+            Ok(Ok(event)) => event,
+            // The broker may close the socket as soon as it sees DISCONNECT
+            Ok(Err(_)) if disconnecting => return Ok(()),
+            Ok(Err(e)) => {
+                return Err(e).with_context(|| {
+                    format!("MQTT connection to {}:{} failed", mqtt.host, mqtt.port)
+                })
+            }
+            Err(_) => bail!(
+                "Timed out publishing to MQTT broker {}:{}",
+                mqtt.host,
+                mqtt.port
+            ),
+        };
+
+        let done = match (&event, qos) {
+            (Event::Outgoing(Outgoing::Publish(_)), QoS::AtMostOnce) => true,
+            (Event::Incoming(Packet::PubAck(_)), QoS::AtLeastOnce) => true,
+            (Event::Incoming(Packet::PubComp(_)), QoS::ExactlyOnce) => true,
+            (Event::Outgoing(Outgoing::Disconnect), _) => return Ok(()),
+            _ => false,
+        };
+        if done {
+            pending -= 1;
+            if pending == 0 && !disconnecting {
+                client
+                    .disconnect()
+                    .context("Failed to disconnect from MQTT broker")?;
+                disconnecting = true;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_client_ids_are_unique() {
+        let first = client_id("reminder-cli");
+        let second = client_id("reminder-cli");
+        assert_ne!(first, second);
+        assert!(first.starts_with(&format!("reminder-cli-{}-", std::process::id())));
+    }
+}
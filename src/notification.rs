@@ -1,41 +1,230 @@
-use crate::reminder::Reminder;
-use crate::storage::Storage;
-use anyhow::Result;
+use crate::config::Config;
+use crate::log_warn;
+use crate::paths;
+use crate::reminder::{NotificationStyle, NotificationTimeout, Reminder};
+use crate::template::Message;
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
 use std::fs::OpenOptions;
 use std::io::Write;
 
-pub fn send_notification(reminder: &Reminder) -> Result<()> {
-    let result = notify_rust::Notification::new()
-        .summary(&reminder.title)
-        .body(reminder.description.as_deref().unwrap_or(""))
-        .appname("Reminder CLI")
-        .timeout(notify_rust::Timeout::Milliseconds(10000))
-        .show();
+const APP_NAME: &str = "Reminder CLI";
 
-    match result {
-        Ok(_) => Ok(()),
-        Err(e) => {
-            eprintln!("Failed to show notification: {}, falling back to log", e);
-            log_reminder(reminder)
+/// How long the "Snooze" action postpones a reminder
+pub const SNOOZE_MINUTES: i64 = 10;
+
+/// `[notification]` settings
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct NotificationConfig {
+    /// Timeout for reminders without their own or a tag's `timeout`
+    pub timeout: NotificationTimeout,
+}
+
+impl Default for NotificationConfig {
+    fn default() -> Self {
+        Self {
+            timeout: NotificationTimeout::Milliseconds(10_000),
+        }
+    }
+}
+
+/// Buttons offered on notification servers that support actions
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NotificationAction {
+    Snooze,
+    Done,
+    Open,
+}
+
+impl NotificationAction {
+    pub fn key(&self) -> &'static str {
+        match self {
+            NotificationAction::Snooze => "snooze",
+            NotificationAction::Done => "done",
+            NotificationAction::Open => "open",
+        }
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            NotificationAction::Snooze => "Snooze 10m",
+            NotificationAction::Done => "Done",
+            NotificationAction::Open => "Open",
+        }
+    }
+
+    pub fn from_key(key: &str) -> Option<Self> {
+        match key {
+            "snooze" => Some(NotificationAction::Snooze),
+            "done" => Some(NotificationAction::Done),
+            "open" => Some(NotificationAction::Open),
+            _ => None,
+        }
+    }
+
+    /// Actions shown for a reminder; "Open" only when there is a link to open
+    pub fn for_reminder(reminder: &Reminder) -> Vec<Self> {
+        let mut actions = vec![NotificationAction::Snooze, NotificationAction::Done];
+        if reminder.link().is_some() {
+            actions.push(NotificationAction::Open);
+        }
+        actions
+    }
+}
+
+fn build_notification(reminder: &Reminder, config: &Config) -> notify_rust::Notification {
+    let style = config.style_for(reminder);
+    let message = Message::render(reminder, config);
+
+    let mut notification = notify_rust::Notification::new();
+    notification
+        .summary(&message.summary)
+        .body(&message.body)
+        .appname(APP_NAME);
+    apply_style(&mut notification, &style, config.notification.timeout);
+    notification
+}
+
+fn apply_style(
+    notification: &mut notify_rust::Notification,
+    style: &NotificationStyle,
+    default_timeout: NotificationTimeout,
+) {
+    let timeout = match style.timeout.unwrap_or(default_timeout) {
+        NotificationTimeout::Default => notify_rust::Timeout::Default,
+        NotificationTimeout::Persistent => notify_rust::Timeout::Never,
+        NotificationTimeout::Milliseconds(ms) => notify_rust::Timeout::Milliseconds(ms),
+    };
+    notification.timeout(timeout);
+
+    if let Some(icon) = &style.icon {
+        notification.icon(icon);
+    }
+    if let Some(sound) = &style.sound {
+        notification.sound_name(sound);
+    }
+
+    #[cfg(all(unix, not(target_os = "macos")))]
+    {
+        use crate::reminder::Urgency;
+
+        if let Some(urgency) = style.urgency {
+            notification.urgency(match urgency {
+                Urgency::Low => notify_rust::Urgency::Low,
+                Urgency::Normal => notify_rust::Urgency::Normal,
+                Urgency::Critical => notify_rust::Urgency::Critical,
+            });
+        }
+        if let Some(category) = &style.category {
+            notification.hint(notify_rust::Hint::Category(category.clone()));
         }
     }
 }
 
-fn log_reminder(reminder: &Reminder) -> Result<()> {
-    let log_path = Storage::log_file_path()?;
+pub fn send_notification(reminder: &Reminder, config: &Config) -> Result<()> {
+    build_notification(reminder, config)
+        .show()
+        .context("Failed to show notification")?;
+    Ok(())
+}
+
+/// Show a notification with Snooze/Done/Open buttons when the server supports
+/// actions, calling `on_action` from a background thread once one is chosen.
+/// Servers without action support get the plain notification.
+///
+/// The thread waits until the notification is closed, which notify-rust
+/// offers no way to cut short: a persistent notification keeps its thread
+/// until it is dismissed.
+#[cfg(all(unix, not(target_os = "macos")))]
+pub fn send_notification_with_actions<F>(
+    reminder: &Reminder,
+    config: &Config,
+    on_action: F,
+) -> Result<()>
+where
+    F: FnOnce(NotificationAction) + Send + 'static,
+{
+    let supports_actions = notify_rust::get_capabilities()
+        .map(|caps| caps.iter().any(|c| c == "actions"))
+        .unwrap_or(false);
+
+    if !supports_actions {
+        return send_notification(reminder, config);
+    }
+
+    let mut notification = build_notification(reminder, config);
+    for action in NotificationAction::for_reminder(reminder) {
+        notification.action(action.key(), action.label());
+    }
+
+    let handle = notification.show().context("Failed to show notification")?;
+    std::thread::spawn(move || {
+        handle.wait_for_action(|key| {
+            if let Some(action) = NotificationAction::from_key(key) {
+                on_action(action);
+            }
+        });
+    });
+    Ok(())
+}
+
+#[cfg(not(all(unix, not(target_os = "macos"))))]
+pub fn send_notification_with_actions<F>(
+    reminder: &Reminder,
+    config: &Config,
+    _on_action: F,
+) -> Result<()>
+where
+    F: FnOnce(NotificationAction) + Send + 'static,
+{
+    send_notification(reminder, config)
+}
+
+/// Open a link with the platform's default handler. The link is passed as
+/// a single argument, never through a shell, and the handler is reaped on a
+/// background thread so a long-running daemon leaves no zombies behind.
+pub fn open_link(link: &str) -> Result<()> {
+    #[cfg(target_os = "macos")]
+    let mut command = std::process::Command::new("open");
+    #[cfg(target_os = "windows")]
+    let mut command = {
+        let mut c = std::process::Command::new("rundll32");
+        c.arg("url.dll,FileProtocolHandler");
+        c
+    };
+    #[cfg(not(any(target_os = "macos", target_os = "windows")))]
+    let mut command = std::process::Command::new("xdg-open");
+
+    let mut child = command
+        .arg(link)
+        .stdin(std::process::Stdio::null())
+        .stdout(std::process::Stdio::null())
+        .stderr(std::process::Stdio::null())
+        .spawn()
+        .context("Failed to open link")?;
+    std::thread::spawn(move || {
+        if let Ok(status) = child.wait() {
+            if !status.success() {
+                log_warn!("Link handler exited with {}", status);
+            }
+        }
+    });
+    Ok(())
+}
+
+/// Append the reminder to the daemon log
+pub fn log_reminder(reminder: &Reminder, config: &Config) -> Result<()> {
+    let log_path = paths::daemon_log_file()?;
     let mut file = OpenOptions::new()
         .create(true)
         .append(true)
         .open(log_path)?;
 
     let timestamp = chrono::Local::now().format("%Y-%m-%d %H:%M:%S");
-    let description = reminder.description.as_deref().unwrap_or("");
-    
-    writeln!(
-        file,
-        "[{}] REMINDER: {} - {}",
-        timestamp, reminder.title, description
-    )?;
+    let message = Message::render(reminder, config);
+
+    writeln!(file, "[{}] REMINDER: {}", timestamp, message.one_line())?;
 
     Ok(())
 }
@@ -0,0 +1,630 @@
+use crate::config::Config;
+use crate::daemon::handle_notification_action;
+use crate::email::send_email;
+use crate::hooks::{hooks_for, run_hook_target, run_hooks};
+use crate::mqtt::{publish, topics_for};
+use crate::notification::{log_reminder, send_notification_with_actions};
+use crate::reminder::Reminder;
+use crate::storage::ReminderStore;
+use crate::template::Message;
+use crate::terminal::{desktop_session_available, logged_in_ttys, write_to_terminals};
+use crate::webhook::{deliver_all, deliver_to, webhooks_for};
+use crate::{log_error, log_warn};
+use anyhow::{anyhow, bail, Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::panic::{self, AssertUnwindSafe};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+pub const DESKTOP_CHANNEL: &str = "desktop";
+pub const LOG_CHANNEL: &str = "log";
+pub const TERMINAL_CHANNEL: &str = "terminal";
+pub const HOOK_CHANNEL: &str = "hook";
+pub const WEBHOOK_CHANNEL: &str = "webhook";
+pub const EMAIL_CHANNEL: &str = "email";
+pub const MQTT_CHANNEL: &str = "mqtt";
+
+/// Every built-in channel, as registered by [`NotifierRegistry::with_defaults`]
+pub const KNOWN_CHANNELS: &[&str] = &[
+    DESKTOP_CHANNEL,
+    EMAIL_CHANNEL,
+    HOOK_CHANNEL,
+    LOG_CHANNEL,
+    MQTT_CHANNEL,
+    TERMINAL_CHANNEL,
+    WEBHOOK_CHANNEL,
+];
+
+/// A delivery channel for fired reminders
+pub trait Notifier: Send + Sync {
+    /// Channel name used by `--channel`
+    fn name(&self) -> &str;
+
+    /// Deliver with `config`, the settings the daemon currently holds, so a
+    /// broken config file does not affect deliveries until it is fixed
+    fn notify(&self, reminder: &Reminder, config: &Config) -> Result<()>;
+
+    /// Deliver to one of the targets named by a [`FailedTargets`] error of
+    /// [`notify`](Self::notify); channels without targets deliver as usual
+    fn notify_target(&self, reminder: &Reminder, target: &str, config: &Config) -> Result<()> {
+        let _ = target;
+        self.notify(reminder, config)
+    }
+}
+
+/// Error of a channel that delivers to several targets, such as hook
+/// commands or webhook URLs, naming the ones that failed so a retry can
+/// leave the others alone
+#[derive(Debug)]
+pub struct FailedTargets {
+    /// What a target is, for the message
+    pub kind: &'static str,
+    /// Each failed target with its error
+    pub failed: Vec<(String, String)>,
+    pub total: usize,
+}
+
+impl std::fmt::Display for FailedTargets {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} of {} {}(s) failed",
+            self.failed.len(),
+            self.total,
+            self.kind
+        )
+    }
+}
+
+impl std::error::Error for FailedTargets {}
+
+/// Result of delivering a reminder through one channel
+#[derive(Debug)]
+pub struct Delivery {
+    pub channel: String,
+    /// The failed channel this delivery stood in for
+    pub fallback_for: Option<String>,
+    pub result: Result<()>,
+}
+
+/// Where a reminder goes when one of its channels fails
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct DeliveryConfig {
+    /// When a channel in this list fails, the ones after it are tried in
+    /// order until one succeeds. Channels not listed have no fallback.
+    #[serde(default = "default_fallback")]
+    pub fallback: Vec<String>,
+}
+
+impl Default for DeliveryConfig {
+    fn default() -> Self {
+        Self {
+            fallback: default_fallback(),
+        }
+    }
+}
+
+impl DeliveryConfig {
+    pub fn validate(&self) -> Result<()> {
+        for channel in &self.fallback {
+            if !KNOWN_CHANNELS.contains(&channel.as_str()) {
+                bail!(
+                    "delivery.fallback has unknown channel {} (expected one of: {})",
+                    channel,
+                    KNOWN_CHANNELS.join(", ")
+                );
+            }
+        }
+        Ok(())
+    }
+}
+
+fn default_fallback() -> Vec<String> {
+    [DESKTOP_CHANNEL, TERMINAL_CHANNEL, LOG_CHANNEL]
+        .iter()
+        .map(|c| c.to_string())
+        .collect()
+}
+
+/// Notifiers by channel name
+#[derive(Clone, Default)]
+pub struct NotifierRegistry {
+    notifiers: BTreeMap<String, Arc<dyn Notifier>>,
+}
+
+impl NotifierRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Registry with all built-in channels; desktop actions update `storage`
+    pub fn with_defaults(storage: Arc<dyn ReminderStore>) -> Self {
+        let mut registry = Self::new();
+        registry.register(DesktopNotifier::new(storage));
+        registry.register(LogNotifier);
+        registry.register(TerminalNotifier::new());
+        registry.register(HookNotifier);
+        registry.register(WebhookNotifier);
+        registry.register(EmailNotifier);
+        registry.register(MqttNotifier);
+        registry
+    }
+
+    /// Add a notifier, replacing any existing one with the same name
+    pub fn register(&mut self, notifier: impl Notifier + 'static) {
+        self.notifiers
+            .insert(notifier.name().to_string(), Arc::new(notifier));
+    }
+
+    pub fn get(&self, name: &str) -> Option<Arc<dyn Notifier>> {
+        self.notifiers.get(name).cloned()
+    }
+
+    pub fn names(&self) -> Vec<&str> {
+        self.notifiers.keys().map(String::as_str).collect()
+    }
+
+    /// Channels a reminder is delivered through: the ones it lists (desktop
+    /// when none), plus hook, webhook and mqtt whenever any are configured
+    /// for it.
+    /// Desktop becomes terminal when there is no D-Bus session to show it on.
+    pub fn channels_for(&self, reminder: &Reminder, config: &Config) -> Vec<String> {
+        let listed = if reminder.channels.is_empty() {
+            vec![DESKTOP_CHANNEL.to_string()]
+        } else {
+            reminder.channels.clone()
+        };
+        let mut channels = resolve_desktop(listed, desktop_session_available());
+
+        let mut implied = Vec::new();
+        if !hooks_for(reminder, config).is_empty() {
+            implied.push(HOOK_CHANNEL);
+        }
+        if !webhooks_for(reminder, config).is_empty() {
+            implied.push(WEBHOOK_CHANNEL);
+        }
+        if !topics_for(reminder, config).is_empty() {
+            implied.push(MQTT_CHANNEL);
+        }
+        for channel in implied {
+            if !channels.iter().any(|c| c == channel) {
+                channels.push(channel.to_string());
+            }
+        }
+
+        channels
+    }
+
+    /// Deliver a reminder through all of its channels concurrently, falling
+    /// back along the configured chain when a channel fails
+    pub fn dispatch(&self, reminder: &Reminder, config: &Config) -> Vec<Delivery> {
+        warn_untrusted(reminder, config);
+        let channels = self.channels_for(reminder, config);
+        let chain = fallback_chain(config);
+
+        thread::scope(|scope| {
+            let handles: Vec<_> = channels
+                .iter()
+                .map(|channel| {
+                    let (channels, chain) = (&channels, &chain);
+                    scope.spawn(move || {
+                        self.deliver_with_fallback(channel, reminder, config, chain, channels)
+                    })
+                })
+                .collect();
+
+            handles
+                .into_iter()
+                .flat_map(|handle| handle.join().unwrap_or_default())
+                .collect()
+        })
+    }
+
+    /// Deliver through one channel, or only to `target` of it, without
+    /// fallback. Retries use this: the fallbacks already ran when the
+    /// reminder fired.
+    pub fn deliver_channel(
+        &self,
+        channel: &str,
+        target: Option<&str>,
+        reminder: &Reminder,
+        config: &Config,
+    ) -> Result<()> {
+        match target {
+            Some(target) => self.deliver_target(channel, target, reminder, config),
+            None => self.deliver(channel, reminder, config),
+        }
+    }
+
+    /// Deliver through `channel`, then through the channels after it in
+    /// `chain` until one succeeds. Channels in `skip` are left out of the
+    /// fallback since the reminder already goes there.
+    fn deliver_with_fallback(
+        &self,
+        channel: &str,
+        reminder: &Reminder,
+        config: &Config,
+        chain: &[String],
+        skip: &[String],
+    ) -> Vec<Delivery> {
+        let result = self.deliver(channel, reminder, config);
+        self.fall_back(channel, result, reminder, config, chain, skip)
+    }
+
+    /// The delivery through `channel` that gave `result`, followed by the
+    /// fallbacks tried if it failed
+    fn fall_back(
+        &self,
+        channel: &str,
+        result: Result<()>,
+        reminder: &Reminder,
+        config: &Config,
+        chain: &[String],
+        skip: &[String],
+    ) -> Vec<Delivery> {
+        let mut deliveries = vec![Delivery {
+            channel: channel.to_string(),
+            fallback_for: None,
+            result,
+        }];
+        if deliveries[0].result.is_ok() {
+            return deliveries;
+        }
+
+        let Some(position) = chain.iter().position(|c| c == channel) else {
+            return deliveries;
+        };
+        for next in chain[position + 1..].iter().filter(|c| !skip.contains(c)) {
+            let result = self.deliver(next, reminder, config);
+            let delivered = result.is_ok();
+            deliveries.push(Delivery {
+                channel: next.clone(),
+                fallback_for: Some(channel.to_string()),
+                result,
+            });
+            if delivered {
+                break;
+            }
+        }
+        deliveries
+    }
+
+    /// Deliver through a single channel, without fallback
+    pub fn deliver(&self, channel: &str, reminder: &Reminder, config: &Config) -> Result<()> {
+        let notifier = self
+            .get(channel)
+            .ok_or_else(|| anyhow!("Unknown channel: {}", channel))?;
+        panic::catch_unwind(AssertUnwindSafe(|| notifier.notify(reminder, config)))
+            .unwrap_or_else(|_| Err(anyhow!("Notifier panicked")))
+    }
+
+    /// Deliver to a single target of a channel, without fallback
+    pub fn deliver_target(
+        &self,
+        channel: &str,
+        target: &str,
+        reminder: &Reminder,
+        config: &Config,
+    ) -> Result<()> {
+        let notifier = self
+            .get(channel)
+            .ok_or_else(|| anyhow!("Unknown channel: {}", channel))?;
+        panic::catch_unwind(AssertUnwindSafe(|| {
+            notifier.notify_target(reminder, target, config)
+        }))
+        .unwrap_or_else(|_| Err(anyhow!("Notifier panicked")))
+    }
+}
+
+fn fallback_chain(config: &Config) -> Vec<String> {
+    resolve_desktop(
+        config.delivery.fallback.clone(),
+        desktop_session_available(),
+    )
+}
+
+/// Channels whose own delivery failed. A fallback that stood in for one
+/// only keeps the occurrence from being lost; the channel is still queued
+/// for retry, since `log` covers nearly every failure of the default chain.
+pub fn failed_channels(deliveries: &[Delivery]) -> Vec<String> {
+    let mut failed: Vec<String> = Vec::new();
+    for delivery in deliveries
+        .iter()
+        .filter(|d| d.fallback_for.is_none() && d.result.is_err())
+    {
+        if !failed.contains(&delivery.channel) {
+            failed.push(delivery.channel.clone());
+        }
+    }
+    failed
+}
+
+/// Swap desktop for terminal when no desktop session is available
+fn resolve_desktop(channels: Vec<String>, desktop_available: bool) -> Vec<String> {
+    if desktop_available {
+        return channels;
+    }
+
+    let mut resolved: Vec<String> = Vec::new();
+    for channel in channels {
+        let channel = if channel == DESKTOP_CHANNEL {
+            TERMINAL_CHANNEL.to_string()
+        } else {
+            channel
+        };
+        if !resolved.contains(&channel) {
+            resolved.push(channel);
+        }
+    }
+    resolved
+}
+
+/// Desktop notification with Snooze/Done/Open actions
+pub struct DesktopNotifier {
+    storage: Arc<dyn ReminderStore>,
+}
+
+impl DesktopNotifier {
+    pub fn new(storage: Arc<dyn ReminderStore>) -> Self {
+        Self { storage }
+    }
+}
+
+impl Notifier for DesktopNotifier {
+    fn name(&self) -> &str {
+        DESKTOP_CHANNEL
+    }
+
+    fn notify(&self, reminder: &Reminder, config: &Config) -> Result<()> {
+        let storage = Arc::clone(&self.storage);
+        let id = reminder.id;
+        send_notification_with_actions(reminder, config, move |action| {
+            if let Err(e) = handle_notification_action(storage.as_ref(), id, action) {
+                log_error!("Failed to handle notification action: {}", e);
+            }
+        })
+    }
+}
+
+/// Line in the daemon log
+pub struct LogNotifier;
+
+impl Notifier for LogNotifier {
+    fn name(&self) -> &str {
+        LOG_CHANNEL
+    }
+
+    fn notify(&self, reminder: &Reminder, config: &Config) -> Result<()> {
+        log_reminder(reminder, config)
+    }
+}
+
+/// Wall-style message and bell on the user's logged-in terminals
+#[derive(Default)]
+pub struct TerminalNotifier {
+    ttys: Option<Vec<PathBuf>>,
+}
+
+impl TerminalNotifier {
+    /// Writes to the terminals listed by `who` at delivery time
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Writes to the given terminal devices only
+    pub fn with_ttys(ttys: Vec<PathBuf>) -> Self {
+        Self { ttys: Some(ttys) }
+    }
+}
+
+impl Notifier for TerminalNotifier {
+    fn name(&self) -> &str {
+        TERMINAL_CHANNEL
+    }
+
+    fn notify(&self, reminder: &Reminder, config: &Config) -> Result<()> {
+        let ttys = self.ttys.clone().unwrap_or_else(logged_in_ttys);
+        write_to_terminals(&Message::render(reminder, config), &config.terminal, &ttys)
+    }
+}
+
+/// Hooks that came with the reminder from another copy are left out until
+/// they are trusted here
+fn warn_untrusted(reminder: &Reminder, config: &Config) {
+    if let Some(command) = &reminder.exec {
+        if config.trusted_exec(reminder).is_none() {
+            log_warn!(
+                "Not running '{}' for reminder {}: it was not set on this machine; add it to trusted.exec to allow it",
+                command,
+                reminder.handle()
+            );
+        }
+    }
+    if let Some(url) = &reminder.webhook {
+        if config.trusted_webhook(reminder).is_none() {
+            log_warn!(
+                "Not posting reminder {} to {}: the webhook was not set on this machine; add it to trusted.webhooks to allow it",
+                reminder.handle(),
+                url
+            );
+        }
+    }
+}
+
+/// The reminder's `--exec` command and global hooks
+pub struct HookNotifier;
+
+impl Notifier for HookNotifier {
+    fn name(&self) -> &str {
+        HOOK_CHANNEL
+    }
+
+    fn notify(&self, reminder: &Reminder, config: &Config) -> Result<()> {
+        if hooks_for(reminder, config).is_empty() {
+            bail!("No hooks configured");
+        }
+        run_hooks(reminder, config)
+    }
+
+    fn notify_target(&self, reminder: &Reminder, target: &str, config: &Config) -> Result<()> {
+        run_hook_target(reminder, config, target)
+    }
+}
+
+/// The reminder's `--webhook` URL and tag webhooks
+pub struct WebhookNotifier;
+
+impl Notifier for WebhookNotifier {
+    fn name(&self) -> &str {
+        WEBHOOK_CHANNEL
+    }
+
+    fn notify(&self, reminder: &Reminder, config: &Config) -> Result<()> {
+        deliver_all(reminder, config)
+    }
+
+    fn notify_target(&self, reminder: &Reminder, target: &str, config: &Config) -> Result<()> {
+        deliver_to(reminder, config, target)
+    }
+}
+
+/// Email through the `[email]` SMTP settings
+pub struct EmailNotifier;
+
+impl Notifier for EmailNotifier {
+    fn name(&self) -> &str {
+        EMAIL_CHANNEL
+    }
+
+    fn notify(&self, reminder: &Reminder, config: &Config) -> Result<()> {
+        let email = config
+            .email
+            .as_ref()
+            .context("The email channel needs an [email] section in the config file")?;
+        send_email(email, reminder, config)
+    }
+}
+
+/// JSON event published to the `[mqtt]` broker
+pub struct MqttNotifier;
+
+impl Notifier for MqttNotifier {
+    fn name(&self) -> &str {
+        MQTT_CHANNEL
+    }
+
+    fn notify(&self, reminder: &Reminder, config: &Config) -> Result<()> {
+        let mqtt = config
+            .mqtt
+            .as_ref()
+            .context("The mqtt channel needs an [mqtt] section in the config file")?;
+        publish(mqtt, reminder, config)
+    }
+}
+
+/// Appends each reminder as a JSON line to a file
+pub struct FileNotifier {
+    name: String,
+    path: PathBuf,
+}
+
+impl FileNotifier {
+    pub fn new(name: impl Into<String>, path: impl Into<PathBuf>) -> Self {
+        Self {
+            name: name.into(),
+            path: path.into(),
+        }
+    }
+}
+
+impl Notifier for FileNotifier {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn notify(&self, reminder: &Reminder, _config: &Config) -> Result<()> {
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .with_context(|| format!("Failed to open {}", self.path.display()))?;
+        writeln!(file, "{}", serde_json::to_string(reminder)?)?;
+        Ok(())
+    }
+}
+
+/// Keeps delivered reminders in memory, or fails every delivery; for tests
+#[derive(Clone)]
+pub struct RecordingNotifier {
+    name: String,
+    failure: Option<String>,
+    delivered: Arc<Mutex<Vec<Reminder>>>,
+}
+
+impl RecordingNotifier {
+    pub fn new(name: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            failure: None,
+            delivered: Arc::default(),
+        }
+    }
+
+    /// A notifier whose deliveries always fail with `message`
+    pub fn failing(name: impl Into<String>, message: impl Into<String>) -> Self {
+        Self {
+            failure: Some(message.into()),
+            ..Self::new(name)
+        }
+    }
+
+    /// Reminders delivered so far (attempts, for a failing notifier)
+    pub fn delivered(&self) -> Vec<Reminder> {
+        self.delivered.lock().unwrap().clone()
+    }
+}
+
+impl Notifier for RecordingNotifier {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn notify(&self, reminder: &Reminder, _config: &Config) -> Result<()> {
+        self.delivered.lock().unwrap().push(reminder.clone());
+        match &self.failure {
+            Some(message) => Err(anyhow!("{}", message)),
+            None => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::memory::MemoryStore;
+
+    #[test]
+    fn test_desktop_falls_back_to_terminal() {
+        let channels = vec![
+            DESKTOP_CHANNEL.to_string(),
+            TERMINAL_CHANNEL.to_string(),
+            EMAIL_CHANNEL.to_string(),
+        ];
+        assert_eq!(
+            resolve_desktop(channels.clone(), false),
+            [TERMINAL_CHANNEL, EMAIL_CHANNEL]
+        );
+        assert_eq!(resolve_desktop(channels.clone(), true), channels);
+    }
+
+    #[test]
+    fn test_known_channels_match_defaults() {
+        let registry = NotifierRegistry::with_defaults(Arc::new(MemoryStore::new()));
+        assert_eq!(registry.names(), KNOWN_CHANNELS);
+    }
+}
@@ -0,0 +1,139 @@
+use anyhow::{Context, Result};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+const APP_DIR: &str = "reminder-cli";
+
+/// Overrides the data directory; set by `--data-dir`
+pub const DATA_DIR_ENV: &str = "REMINDER_DATA_DIR";
+
+pub const CONFIG_FILE: &str = "config.toml";
+pub const PID_FILE: &str = "daemon.pid";
+pub const HEARTBEAT_FILE: &str = "daemon.heartbeat";
+pub const DAEMON_LOG_FILE: &str = "daemon.log";
+
+/// Data directory given through `REMINDER_DATA_DIR`, made absolute
+fn data_dir_override() -> Option<PathBuf> {
+    let dir = std::env::var_os(DATA_DIR_ENV).filter(|d| !d.is_empty())?;
+    let dir = PathBuf::from(dir);
+    if dir.is_absolute() {
+        return Some(dir);
+    }
+    Some(
+        std::env::current_dir()
+            .map(|cwd| cwd.join(&dir))
+            .unwrap_or(dir),
+    )
+}
+
+fn ensure(dir: PathBuf) -> Result<PathBuf> {
+    fs::create_dir_all(&dir)
+        .with_context(|| format!("Failed to create directory {}", dir.display()))?;
+    Ok(dir)
+}
+
+/// The platform's local data directory, where older versions kept every
+/// file
+fn default_data_dir() -> Result<PathBuf> {
+    Ok(dirs::data_local_dir()
+        .context("Failed to get local data directory")?
+        .join(APP_DIR))
+}
+
+/// Reminders, backups, failed deliveries and logs. `REMINDER_DATA_DIR`
+/// if set, otherwise the platform's local data directory.
+pub fn data_dir() -> Result<PathBuf> {
+    let dir = match data_dir_override() {
+        Some(dir) => dir,
+        None => default_data_dir()?,
+    };
+    ensure(dir)
+}
+
+/// `config.toml`: under `XDG_CONFIG_HOME` (or the platform's config
+/// directory) whichever data directory is in use
+pub fn config_dir() -> Result<PathBuf> {
+    match dirs::config_dir() {
+        Some(dir) => ensure(dir.join(APP_DIR)),
+        None => ensure(default_data_dir()?),
+    }
+}
+
+/// Daemon PID and heartbeat: under `XDG_RUNTIME_DIR` when there is one,
+/// otherwise the platform's local data directory. Each data directory
+/// given through `REMINDER_DATA_DIR` gets a directory of its own there, so
+/// a daemon can run for each.
+pub fn runtime_dir() -> Result<PathBuf> {
+    let dir = match dirs::runtime_dir() {
+        Some(dir) => dir.join(APP_DIR),
+        None => default_data_dir()?,
+    };
+    match data_dir_override() {
+        Some(data) => {
+            let data = fs::canonicalize(&data).unwrap_or(data);
+            ensure(dir.join(format!("data-{:016x}", fnv1a(&data))))
+        }
+        None => ensure(dir),
+    }
+}
+
+/// 64-bit FNV-1a of a path, which unlike `DefaultHasher` stays the same
+/// across Rust releases
+fn fnv1a(path: &Path) -> u64 {
+    path.to_string_lossy()
+        .bytes()
+        .fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+            (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3)
+        })
+}
+
+pub fn config_file() -> Result<PathBuf> {
+    Ok(config_dir()?.join(CONFIG_FILE))
+}
+
+pub fn pid_file() -> Result<PathBuf> {
+    Ok(runtime_dir()?.join(PID_FILE))
+}
+
+pub fn heartbeat_file() -> Result<PathBuf> {
+    Ok(runtime_dir()?.join(HEARTBEAT_FILE))
+}
+
+pub fn daemon_log_file() -> Result<PathBuf> {
+    Ok(data_dir()?.join(DAEMON_LOG_FILE))
+}
+
+/// Move the config and runtime files that older versions kept in the
+/// platform's data directory to their new homes. Files already present at
+/// the new location are left alone. Returns the moves made.
+pub fn migrate_legacy_layout() -> Result<Vec<(PathBuf, PathBuf)>> {
+    let data = default_data_dir()?;
+    let mut moves = vec![(CONFIG_FILE, config_dir()?)];
+    // The daemon files belong to the default data directory's daemon
+    if data_dir_override().is_none() {
+        moves.push((PID_FILE, runtime_dir()?));
+        moves.push((HEARTBEAT_FILE, runtime_dir()?));
+    }
+
+    let mut moved = Vec::new();
+    for (name, dir) in moves {
+        let (from, to) = (data.join(name), dir.join(name));
+        if from == to || !from.exists() || to.exists() {
+            continue;
+        }
+        move_file(&from, &to)?;
+        moved.push((from, to));
+    }
+    Ok(moved)
+}
+
+/// Rename, falling back to copy and delete across filesystems
+fn move_file(from: &Path, to: &Path) -> Result<()> {
+    if fs::rename(from, to).is_ok() {
+        return Ok(());
+    }
+    fs::copy(from, to)
+        .with_context(|| format!("Failed to move {} to {}", from.display(), to.display()))?;
+    fs::remove_file(from).with_context(|| format!("Failed to remove {}", from.display()))?;
+    Ok(())
+}
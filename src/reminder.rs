@@ -1,4 +1,5 @@
-use chrono::{DateTime, Local};
+use crate::template::MessageTemplate;
+use chrono::{DateTime, Duration, Local};
 use cron::Schedule;
 use serde::{Deserialize, Serialize};
 use std::collections::HashSet;
@@ -8,6 +9,12 @@ use uuid::Uuid;
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Reminder {
     pub id: Uuid,
+    /// Short sequential number assigned by `Storage`; 0 until assigned
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub number: u32,
+    /// User-defined name, unique across the store
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub alias: Option<String>,
     pub title: String,
     pub description: Option<String>,
     pub schedule: ReminderSchedule,
@@ -18,6 +25,36 @@ pub struct Reminder {
     pub paused: bool,
     #[serde(default)]
     pub tags: HashSet<String>,
+    #[serde(default, skip_serializing_if = "NotificationStyle::is_empty")]
+    pub style: NotificationStyle,
+    /// Shell command run when the reminder fires
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub exec: Option<String>,
+    /// URL the reminder is posted to when it fires
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub webhook: Option<String>,
+    /// MQTT topic the fired event is published to
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mqtt_topic: Option<String>,
+    /// Delivery channels; desktop notification when empty
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub channels: Vec<String>,
+    /// Summary and body templates
+    #[serde(default, skip_serializing_if = "MessageTemplate::is_empty")]
+    pub template: MessageTemplate,
+    /// Times the reminder has fired
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub occurrences: u32,
+    /// Bumped on every change; merges keep the copy with the higher one
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub revision: u32,
+    /// When the reminder last changed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub updated_at: Option<DateTime<Local>>,
+}
+
+fn is_zero(n: &u32) -> bool {
+    *n == 0
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -26,6 +63,131 @@ pub enum ReminderSchedule {
     Cron(String),
 }
 
+/// Notification appearance overrides; unset fields fall back to tag
+/// overrides from the config file and then to the built-in defaults
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct NotificationStyle {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub icon: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sound: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub urgency: Option<Urgency>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub category: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timeout: Option<NotificationTimeout>,
+}
+
+impl NotificationStyle {
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+
+    /// Fill fields not set here from `other`
+    pub fn or(self, other: &NotificationStyle) -> NotificationStyle {
+        NotificationStyle {
+            icon: self.icon.or_else(|| other.icon.clone()),
+            sound: self.sound.or_else(|| other.sound.clone()),
+            urgency: self.urgency.or(other.urgency),
+            category: self.category.or_else(|| other.category.clone()),
+            timeout: self.timeout.or(other.timeout),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Urgency {
+    Low,
+    Normal,
+    Critical,
+}
+
+impl FromStr for Urgency {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "low" => Ok(Urgency::Low),
+            "normal" => Ok(Urgency::Normal),
+            "critical" => Ok(Urgency::Critical),
+            _ => anyhow::bail!("Invalid urgency: {} (expected low, normal or critical)", s),
+        }
+    }
+}
+
+/// How long a notification stays on screen
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub enum NotificationTimeout {
+    /// Let the notification server decide
+    Default,
+    /// Stay until dismissed
+    Persistent,
+    Milliseconds(u32),
+}
+
+impl FromStr for NotificationTimeout {
+    type Err = anyhow::Error;
+
+    /// Accepts "default", "persistent"/"never", "10s", "1m" or plain milliseconds
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim().to_lowercase();
+        let invalid = || {
+            anyhow::anyhow!(
+                "Invalid timeout: {} (expected \"persistent\", \"default\", \"10s\", \"1m\" or milliseconds)",
+                s
+            )
+        };
+
+        match s.as_str() {
+            "default" => Ok(NotificationTimeout::Default),
+            "persistent" | "never" => Ok(NotificationTimeout::Persistent),
+            _ => {
+                let (number, factor) = if let Some(n) = s.strip_suffix("ms") {
+                    (n, 1)
+                } else if let Some(n) = s.strip_suffix('s') {
+                    (n, 1000)
+                } else if let Some(n) = s.strip_suffix('m') {
+                    (n, 60_000)
+                } else {
+                    (s.as_str(), 1)
+                };
+                let value: u32 = number.trim().parse().map_err(|_| invalid())?;
+                value
+                    .checked_mul(factor)
+                    .map(NotificationTimeout::Milliseconds)
+                    .ok_or_else(invalid)
+            }
+        }
+    }
+}
+
+impl TryFrom<String> for NotificationTimeout {
+    type Error = anyhow::Error;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+impl From<NotificationTimeout> for String {
+    fn from(timeout: NotificationTimeout) -> Self {
+        timeout.to_string()
+    }
+}
+
+impl std::fmt::Display for NotificationTimeout {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            NotificationTimeout::Default => write!(f, "default"),
+            NotificationTimeout::Persistent => write!(f, "persistent"),
+            NotificationTimeout::Milliseconds(ms) => write!(f, "{}ms", ms),
+        }
+    }
+}
+
 impl Reminder {
     pub fn new_one_time(
         title: String,
@@ -35,6 +197,8 @@ impl Reminder {
     ) -> Self {
         Self {
             id: Uuid::new_v4(),
+            number: 0,
+            alias: None,
             title,
             description,
             schedule: ReminderSchedule::OneTime(time),
@@ -43,6 +207,15 @@ impl Reminder {
             completed: false,
             paused: false,
             tags,
+            style: NotificationStyle::default(),
+            exec: None,
+            webhook: None,
+            mqtt_topic: None,
+            channels: Vec::new(),
+            template: MessageTemplate::default(),
+            occurrences: 0,
+            revision: 0,
+            updated_at: None,
         }
     }
 
@@ -57,6 +230,8 @@ impl Reminder {
 
         Ok(Self {
             id: Uuid::new_v4(),
+            number: 0,
+            alias: None,
             title,
             description,
             schedule: ReminderSchedule::Cron(cron_expr),
@@ -65,6 +240,15 @@ impl Reminder {
             completed: false,
             paused: false,
             tags,
+            style: NotificationStyle::default(),
+            exec: None,
+            webhook: None,
+            mqtt_topic: None,
+            channels: Vec::new(),
+            template: MessageTemplate::default(),
+            occurrences: 0,
+            revision: 0,
+            updated_at: None,
         })
     }
 
@@ -96,6 +280,12 @@ impl Reminder {
         self.paused = true;
     }
 
+    /// Record a change made after revision `since`
+    pub fn touch(&mut self, since: u32) {
+        self.revision = self.revision.max(since) + 1;
+        self.updated_at = Some(Local::now());
+    }
+
     pub fn resume(&mut self) {
         self.paused = false;
         // Recalculate next trigger for cron jobs
@@ -106,6 +296,34 @@ impl Reminder {
         }
     }
 
+    /// Push the next trigger out by `duration` from now
+    pub fn snooze(&mut self, duration: Duration) {
+        self.next_trigger = Some(Local::now() + duration);
+        self.completed = false;
+    }
+
+    /// Acknowledge the reminder: one-time reminders are completed, cron
+    /// reminders drop any pending snooze and go back to their schedule
+    pub fn mark_done(&mut self) {
+        self.calculate_next_trigger();
+    }
+
+    /// First http(s) link found in the description, if any
+    pub fn link(&self) -> Option<&str> {
+        self.description
+            .as_deref()?
+            .split_whitespace()
+            .find(|word| word.starts_with("http://") || word.starts_with("https://"))
+    }
+
+    /// `#12`, or the short ID for reminders not numbered yet
+    pub fn handle(&self) -> String {
+        match self.number {
+            0 => self.id.to_string()[..8].to_string(),
+            n => format!("#{}", n),
+        }
+    }
+
     pub fn status(&self) -> &'static str {
         if self.completed {
             "Completed"
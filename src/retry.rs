@@ -0,0 +1,533 @@
+use crate::config::Config;
+use crate::crypto::{self, Vault};
+use crate::notifier::{failed_channels, Delivery, FailedTargets, NotifierRegistry};
+use crate::reminder::Reminder;
+use crate::storage::{sibling, write_atomic, JsonStore, ReminderStore};
+use crate::{log_debug, log_error, log_info, log_warn};
+use anyhow::{Context, Result};
+use chrono::{DateTime, Local};
+use fs2::FileExt;
+use serde::{Deserialize, Serialize};
+use std::fs::{self, OpenOptions};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use uuid::Uuid;
+
+pub const DEFAULT_RETRIES: u32 = 3;
+pub const DEFAULT_RETRY_DELAY_MS: u64 = 30_000;
+pub const DEFAULT_MAX_RETRY_DELAY_MS: u64 = 15 * 60 * 1000;
+
+/// How failed deliveries of an occurrence are retried
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RetryConfig {
+    /// Extra attempts per failed channel before it goes to the dead-letter queue
+    #[serde(default = "default_retries")]
+    pub retries: u32,
+
+    /// Delay before the first retry, doubled for every further attempt
+    #[serde(default = "default_delay")]
+    pub delay_ms: u64,
+
+    /// Upper bound for the delay between attempts
+    #[serde(default = "default_max_delay")]
+    pub max_delay_ms: u64,
+}
+
+impl Default for RetryConfig {
+    fn default() -> Self {
+        Self {
+            retries: DEFAULT_RETRIES,
+            delay_ms: DEFAULT_RETRY_DELAY_MS,
+            max_delay_ms: DEFAULT_MAX_RETRY_DELAY_MS,
+        }
+    }
+}
+
+fn default_retries() -> u32 {
+    DEFAULT_RETRIES
+}
+
+fn default_delay() -> u64 {
+    DEFAULT_RETRY_DELAY_MS
+}
+
+fn default_max_delay() -> u64 {
+    DEFAULT_MAX_RETRY_DELAY_MS
+}
+
+/// An occurrence that could not be delivered through one channel
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FailedDelivery {
+    pub id: Uuid,
+    /// The reminder as it was when it fired
+    pub reminder: Reminder,
+    pub channel: String,
+    /// The hook command or webhook URL that failed, for channels that
+    /// deliver to several; only it is retried
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub target: Option<String>,
+    pub error: String,
+    pub attempts: u32,
+    pub failed_at: DateTime<Local>,
+    /// When the daemon tries the channel again; `None` once the retries
+    /// are used up and the entry waits for `reminder failed retry`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub next_attempt: Option<DateTime<Local>>,
+}
+
+impl FailedDelivery {
+    pub fn short_id(&self) -> String {
+        self.id.to_string()[..8].to_string()
+    }
+
+    /// The channel, and the target within it if any
+    pub fn destination(&self) -> String {
+        match &self.target {
+            Some(target) => format!("{} ({})", self.channel, target),
+            None => self.channel.clone(),
+        }
+    }
+}
+
+/// Persisted list of failed deliveries. Entries hold whole reminders, so
+/// a queue kept next to an encrypted store is encrypted with the store's
+/// key.
+#[derive(Clone)]
+pub struct DeadLetterQueue {
+    path: PathBuf,
+    /// The store whose encryption the queue follows
+    store: Option<PathBuf>,
+    vault: Arc<Vault>,
+}
+
+impl DeadLetterQueue {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self {
+            path: path.into(),
+            store: None,
+            vault: Arc::default(),
+        }
+    }
+
+    /// The queue next to the store, encrypted along with it
+    pub fn for_storage(storage: &dyn ReminderStore) -> Self {
+        let path = storage.failed_deliveries_path();
+        match (storage.path(), storage.vault()) {
+            (Some(store), Some(vault)) => Self {
+                path,
+                store: Some(store.to_path_buf()),
+                vault,
+            },
+            _ => Self::new(path),
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    fn is_sealed(&self) -> bool {
+        self.store
+            .as_ref()
+            .is_some_and(|store| JsonStore::new(store).is_encrypted())
+    }
+
+    pub fn load(&self) -> Result<Vec<FailedDelivery>> {
+        let content = match fs::read(&self.path) {
+            Ok(content) => content,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e).context("Failed to read failed deliveries file"),
+        };
+        if crypto::is_encrypted(&content) {
+            return parse_entries(&self.vault.decrypt(&content)?);
+        }
+        parse_entries(&content)
+    }
+
+    pub fn push(&self, entry: FailedDelivery) -> Result<()> {
+        self.modify(|entries| entries.push(entry))
+    }
+
+    /// Find an entry by ID prefix
+    pub fn find(&self, short_id: &str) -> Result<Option<FailedDelivery>> {
+        let matches: Vec<_> = self
+            .load()?
+            .into_iter()
+            .filter(|e| e.id.to_string().starts_with(short_id))
+            .collect();
+
+        match matches.len() {
+            0 => Ok(None),
+            1 => Ok(matches.into_iter().next()),
+            _ => anyhow::bail!(
+                "Ambiguous ID '{}': matches {} failed deliveries. Please use more characters.",
+                short_id,
+                matches.len()
+            ),
+        }
+    }
+
+    /// Remove an entry, returning whether it was present
+    pub fn remove(&self, id: Uuid) -> Result<bool> {
+        let mut removed = false;
+        self.modify(|entries| {
+            let before = entries.len();
+            entries.retain(|e| e.id != id);
+            removed = entries.len() != before;
+        })?;
+        Ok(removed)
+    }
+
+    pub fn update(&self, entry: FailedDelivery) -> Result<()> {
+        self.modify(|entries| {
+            if let Some(existing) = entries.iter_mut().find(|e| e.id == entry.id) {
+                *existing = entry;
+            }
+        })
+    }
+
+    /// Remove every entry, returning how many there were
+    pub fn clear(&self) -> Result<usize> {
+        let mut count = 0;
+        self.modify(|entries| {
+            count = entries.len();
+            entries.clear();
+        })?;
+        Ok(count)
+    }
+
+    /// Entries the daemon retries later, whose next attempt is due at
+    /// `now`. Each is counted as attempted and its next attempt scheduled
+    /// before it is handed out, so it is not delivered twice at once and a
+    /// crash mid-delivery only delays it.
+    pub fn claim_due(
+        &self,
+        now: DateTime<Local>,
+        retry: &RetryConfig,
+    ) -> Result<Vec<FailedDelivery>> {
+        let mut claimed = Vec::new();
+        self.modify(|entries| {
+            for entry in entries.iter_mut() {
+                if entry.next_attempt.is_some_and(|at| at <= now) {
+                    entry.attempts += 1;
+                    entry.next_attempt = (entry.attempts <= retry.retries)
+                        .then(|| now + backoff(retry, entry.attempts));
+                    claimed.push(entry.clone());
+                }
+            }
+        })?;
+        Ok(claimed)
+    }
+
+    /// When the earliest retry is due
+    pub fn next_attempt(&self) -> Result<Option<DateTime<Local>>> {
+        Ok(self.load()?.iter().filter_map(|e| e.next_attempt).min())
+    }
+
+    /// Rewrite the queue encrypted with `vault`, or as plaintext for
+    /// `None`. Returns the number of entries.
+    pub fn convert(&self, vault: Option<&Vault>) -> Result<usize> {
+        if !self.path.exists() {
+            return Ok(0);
+        }
+        let mut count = 0;
+        self.write_with(vault.map(Seal::With).unwrap_or(Seal::Plain), |entries| {
+            count = entries.len();
+        })?;
+        Ok(count)
+    }
+
+    /// Read-modify-write under one exclusive lock, so the daemon and the CLI
+    /// do not lose each other's changes. The file is replaced atomically,
+    /// so readers need no lock.
+    fn modify(&self, change: impl FnOnce(&mut Vec<FailedDelivery>)) -> Result<()> {
+        self.write_with(Seal::Store, change)
+    }
+
+    fn write_with(&self, seal: Seal, change: impl FnOnce(&mut Vec<FailedDelivery>)) -> Result<()> {
+        let lock = OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(sibling(&self.path, "lock"))
+            .context("Failed to open failed deliveries lock file")?;
+        lock.lock_exclusive()
+            .context("Failed to acquire write lock")?;
+
+        let mut entries = self.load()?;
+        change(&mut entries);
+
+        let content =
+            serde_json::to_vec_pretty(&entries).context("Failed to serialize failed deliveries")?;
+        let vault = match seal {
+            Seal::Store => self.is_sealed().then_some(self.vault.as_ref()),
+            Seal::With(vault) => Some(vault),
+            Seal::Plain => None,
+        };
+        match vault {
+            Some(vault) => write_atomic(&self.path, &vault.encrypt(&content)?)?,
+            None => write_atomic(&self.path, &content)?,
+        }
+
+        lock.unlock().context("Failed to release lock")?;
+        Ok(())
+    }
+}
+
+/// How a rewrite of the queue is encrypted
+enum Seal<'a> {
+    /// Like the store next to it
+    Store,
+    With(&'a Vault),
+    Plain,
+}
+
+fn parse_entries(content: &[u8]) -> Result<Vec<FailedDelivery>> {
+    if content.trim_ascii().is_empty() {
+        return Ok(Vec::new());
+    }
+    serde_json::from_slice(content).context("Failed to parse failed deliveries JSON")
+}
+
+/// Delay before the attempt after `attempts`: the configured delay, doubled
+/// for every attempt after the first and capped at the maximum
+fn backoff(retry: &RetryConfig, attempts: u32) -> chrono::Duration {
+    let doublings = attempts.saturating_sub(1).min(32);
+    let delay = retry
+        .delay_ms
+        .saturating_mul(1 << doublings)
+        .min(retry.max_delay_ms.max(retry.delay_ms));
+    chrono::Duration::milliseconds(delay.min(i64::MAX as u64) as i64)
+}
+
+/// Deliver a fired reminder and queue the channels that failed, including
+/// those a fallback stood in for. While retries remain, the daemon tries
+/// them again with exponential backoff (see [`retry_due`]); otherwise they
+/// wait for the user.
+pub fn deliver_with_retry(
+    registry: &NotifierRegistry,
+    reminder: &Reminder,
+    config: &Config,
+    queue: &DeadLetterQueue,
+) {
+    let deliveries = registry.dispatch(reminder, config);
+    log_deliveries(reminder, &deliveries);
+
+    let now = Local::now();
+    for channel in failed_channels(&deliveries) {
+        // Retrying cannot help a channel that does not exist
+        let next_attempt = (config.retry.retries > 0 && registry.get(&channel).is_some())
+            .then(|| now + backoff(&config.retry, 1));
+        let failures = match failed_targets(&deliveries, &channel) {
+            Some(targets) => targets
+                .failed
+                .iter()
+                .map(|(target, error)| (Some(target.clone()), error.clone()))
+                .collect(),
+            None => vec![(None, channel_error(&deliveries, &channel))],
+        };
+        for (target, error) in failures {
+            let entry = FailedDelivery {
+                id: Uuid::new_v4(),
+                reminder: reminder.clone(),
+                channel: channel.clone(),
+                target,
+                error,
+                attempts: 1,
+                failed_at: now,
+                next_attempt,
+            };
+            log_queued(&entry);
+            if let Err(e) = queue.push(entry) {
+                log_error!("Failed to record failed delivery: {:#}", e);
+            }
+        }
+    }
+}
+
+/// The targets that failed when the channel delivers to several, so each
+/// is queued and retried on its own
+fn failed_targets<'a>(deliveries: &'a [Delivery], channel: &str) -> Option<&'a FailedTargets> {
+    deliveries
+        .iter()
+        .filter(|d| d.channel == channel && d.fallback_for.is_none())
+        .find_map(|d| d.result.as_ref().err()?.downcast_ref::<FailedTargets>())
+}
+
+/// Deliver the queued entries whose next attempt is due on background
+/// threads, through their own channel only. Each is removed once
+/// delivered; otherwise it keeps the new error and waits for its next
+/// attempt, if any.
+pub fn retry_due(
+    registry: &Arc<NotifierRegistry>,
+    config: &Config,
+    queue: &DeadLetterQueue,
+) -> Result<Vec<JoinHandle<()>>> {
+    let claimed = queue.claim_due(Local::now(), &config.retry)?;
+    Ok(claimed
+        .into_iter()
+        .map(|mut entry| {
+            let (registry, config) = (Arc::clone(registry), config.clone());
+            let queue = queue.clone();
+            thread::spawn(move || {
+                let delivered = registry.deliver_channel(
+                    &entry.channel,
+                    entry.target.as_deref(),
+                    &entry.reminder,
+                    &config,
+                );
+
+                let result = match delivered {
+                    Ok(()) => {
+                        log_info!(
+                            "Delivered '{}' via {} on attempt {}",
+                            entry.reminder.title,
+                            entry.destination(),
+                            entry.attempts
+                        );
+                        queue.remove(entry.id).map(|_| ())
+                    }
+                    Err(e) => {
+                        entry.error = format!("{:#}", e);
+                        entry.failed_at = Local::now();
+                        log_queued(&entry);
+                        queue.update(entry)
+                    }
+                };
+                if let Err(e) = result {
+                    log_error!("Failed to update failed delivery: {:#}", e);
+                }
+            })
+        })
+        .collect())
+}
+
+fn log_queued(entry: &FailedDelivery) {
+    match entry.next_attempt {
+        Some(at) => log_warn!(
+            "Retrying '{}' via {} at {} (attempt {})",
+            entry.reminder.title,
+            entry.destination(),
+            at.format("%H:%M:%S"),
+            entry.attempts + 1
+        ),
+        None => log_error!(
+            "Giving up on '{}' via {} after {} attempt(s), queued as {}",
+            entry.reminder.title,
+            entry.destination(),
+            entry.attempts,
+            entry.short_id()
+        ),
+    }
+}
+
+/// Error of the channel's own delivery, not of the fallbacks for it
+fn channel_error(deliveries: &[Delivery], channel: &str) -> String {
+    deliveries
+        .iter()
+        .filter(|d| d.channel == channel && d.fallback_for.is_none())
+        .find_map(|d| d.result.as_ref().err())
+        .map(|e| format!("{:#}", e))
+        .unwrap_or_default()
+}
+
+pub fn log_deliveries(reminder: &Reminder, deliveries: &[Delivery]) {
+    for delivery in deliveries {
+        log_delivery(reminder, delivery);
+    }
+}
+
+fn log_delivery(reminder: &Reminder, delivery: &Delivery) {
+    match (&delivery.result, &delivery.fallback_for) {
+        (Ok(()), None) => log_debug!("Delivered '{}' via {}", reminder.title, delivery.channel),
+        (Ok(()), Some(failed)) => log_info!(
+            "Delivered '{}' via {} in place of {}",
+            reminder.title,
+            delivery.channel,
+            failed
+        ),
+        (Err(e), None) => log_error!(
+            "Delivery of '{}' via {} failed: {:#}",
+            reminder.title,
+            delivery.channel,
+            e
+        ),
+        (Err(e), Some(failed)) => log_error!(
+            "Fallback delivery of '{}' via {} (for {}) failed: {:#}",
+            reminder.title,
+            delivery.channel,
+            failed,
+            e
+        ),
+    }
+}
+
+/// Deliver a queued entry again, removing it on success or recording the
+/// new failure otherwise
+pub fn retry_failed(
+    registry: &NotifierRegistry,
+    config: &Config,
+    queue: &DeadLetterQueue,
+    mut entry: FailedDelivery,
+) -> Result<bool> {
+    let delivered = registry.deliver_channel(
+        &entry.channel,
+        entry.target.as_deref(),
+        &entry.reminder,
+        config,
+    );
+
+    match delivered {
+        Ok(()) => {
+            log_info!(
+                "Delivered '{}' via {}",
+                entry.reminder.title,
+                entry.destination()
+            );
+            queue.remove(entry.id)?;
+            Ok(true)
+        }
+        Err(e) => {
+            entry.attempts += 1;
+            entry.error = format!("{:#}", e);
+            entry.failed_at = Local::now();
+            queue.update(entry)?;
+            Ok(false)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+
+    #[test]
+    fn test_queue_round_trip() {
+        let dir = tempfile::tempdir().unwrap();
+        let queue = DeadLetterQueue::new(dir.path().join("failed.json"));
+        let reminder = Reminder::new_one_time("x".to_string(), None, Local::now(), HashSet::new());
+
+        let entry = FailedDelivery {
+            id: Uuid::new_v4(),
+            reminder,
+            channel: "webhook".to_string(),
+            target: None,
+            error: "HTTP 503".to_string(),
+            attempts: 4,
+            failed_at: Local::now(),
+            next_attempt: None,
+        };
+        queue.push(entry.clone()).unwrap();
+        assert_eq!(
+            queue.find(&entry.short_id()).unwrap().unwrap().channel,
+            "webhook"
+        );
+
+        assert!(queue.remove(entry.id).unwrap());
+        assert!(!queue.remove(entry.id).unwrap());
+        assert!(queue.load().unwrap().is_empty());
+    }
+}
@@ -0,0 +1,519 @@
+use crate::ids;
+use crate::index::ReminderIndex;
+use crate::merge;
+use crate::reminder::Reminder;
+use crate::storage::{single_match, Finish, Mutation, ReminderStore, Scope, Updater};
+use anyhow::{Context, Result};
+use chrono::{DateTime, Local};
+use rusqlite::{params, Connection, OptionalExtension, Transaction, TransactionBehavior};
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+use uuid::Uuid;
+
+const SCHEMA: &str = "
+    CREATE TABLE IF NOT EXISTS reminders (
+        id TEXT PRIMARY KEY,
+        next_trigger INTEGER,
+        completed INTEGER NOT NULL,
+        paused INTEGER NOT NULL,
+        data TEXT NOT NULL
+    );
+    CREATE INDEX IF NOT EXISTS idx_reminders_next_trigger
+        ON reminders (next_trigger) WHERE completed = 0 AND paused = 0;
+    CREATE INDEX IF NOT EXISTS idx_reminders_number
+        ON reminders (COALESCE(json_extract(data, '$.number'), 0));
+    CREATE INDEX IF NOT EXISTS idx_reminders_alias
+        ON reminders (json_extract(data, '$.alias'));
+    CREATE TABLE IF NOT EXISTS reminder_tags (
+        reminder_id TEXT NOT NULL REFERENCES reminders (id) ON DELETE CASCADE,
+        tag TEXT NOT NULL,
+        PRIMARY KEY (reminder_id, tag)
+    );
+    CREATE INDEX IF NOT EXISTS idx_reminder_tags_tag ON reminder_tags (tag);
+    CREATE TABLE IF NOT EXISTS meta (
+        key TEXT PRIMARY KEY,
+        value INTEGER NOT NULL
+    );
+";
+
+/// Queries matching the expression indexes above
+const BY_NUMBER: &str =
+    "SELECT data FROM reminders WHERE COALESCE(json_extract(data, '$.number'), 0) = ?1";
+const BY_ALIAS: &str = "SELECT data FROM reminders WHERE json_extract(data, '$.alias') = ?1";
+const HIGHEST_NUMBER: &str = "SELECT data FROM reminders
+     ORDER BY COALESCE(json_extract(data, '$.number'), 0) DESC LIMIT 1";
+
+/// Key in `meta` of the highest number handed out, deleted reminders'
+/// included
+const LAST_NUMBER: &str = "last_number";
+
+/// How long a writer waits for another process's transaction to finish
+const BUSY_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Reminders in a SQLite database. Each reminder is kept as JSON, with the
+/// columns the daemon and tag filters query broken out and indexed.
+#[derive(Debug, Clone)]
+pub struct SqliteStore {
+    path: PathBuf,
+}
+
+impl SqliteStore {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self { path: path.into() }
+    }
+
+    fn connect(&self) -> Result<Connection> {
+        let conn = Connection::open(&self.path)
+            .with_context(|| format!("Failed to open database {}", self.path.display()))?;
+        conn.busy_timeout(BUSY_TIMEOUT)?;
+        conn.pragma_update(None, "journal_mode", "WAL")?;
+        conn.pragma_update(None, "foreign_keys", "ON")?;
+        conn.execute_batch(SCHEMA)
+            .context("Failed to create database schema")?;
+        Ok(conn)
+    }
+
+    /// Run `f` inside a write transaction, committing only if it succeeds
+    fn write<T>(&self, f: impl FnOnce(&Transaction) -> Result<T>) -> Result<T> {
+        let mut conn = self.connect()?;
+        let tx = conn.transaction_with_behavior(TransactionBehavior::Immediate)?;
+        let value = f(&tx)?;
+        tx.commit().context("Failed to commit transaction")?;
+        Ok(value)
+    }
+
+    /// Write a consistent copy of the database, journal included, to a
+    /// new file at `path`
+    pub fn copy_to(&self, path: &Path) -> Result<()> {
+        let conn = self.connect()?;
+        conn.execute("VACUUM INTO ?1", params![path.to_string_lossy()])
+            .with_context(|| format!("Failed to copy database to {}", path.display()))?;
+        Ok(())
+    }
+
+    /// Reminders whose ID starts with `prefix`
+    pub fn find_by_prefix(&self, prefix: &str) -> Result<Vec<Reminder>> {
+        let conn = self.connect()?;
+        // IDs are lowercase hex and dashes, so the prefix needs no escaping
+        // once anything else is rejected
+        if !prefix.chars().all(|c| c.is_ascii_hexdigit() || c == '-') {
+            return Ok(Vec::new());
+        }
+        query(
+            &conn,
+            "SELECT data FROM reminders WHERE id LIKE ?1 || '%' ORDER BY rowid",
+            [prefix.to_lowercase()],
+        )
+    }
+
+    /// The reminder with number or alias `key`
+    pub fn find_by_name(&self, key: &str) -> Result<Option<Reminder>> {
+        let conn = self.connect()?;
+        if let Some(number) = ids::parse_number(key) {
+            let found = query(&conn, BY_NUMBER, [number])?;
+            if let Some(reminder) = found.into_iter().next() {
+                return Ok(Some(reminder));
+            }
+        }
+        let found = query(&conn, BY_ALIAS, [key])?;
+        Ok(found.into_iter().next())
+    }
+
+    /// Active reminders whose next trigger is at or before `now`
+    pub fn due(&self, now: DateTime<Local>) -> Result<Vec<Reminder>> {
+        let conn = self.connect()?;
+        query(
+            &conn,
+            "SELECT data FROM reminders
+             WHERE completed = 0 AND paused = 0 AND next_trigger <= ?1
+             ORDER BY next_trigger",
+            [now.timestamp()],
+        )
+    }
+}
+
+impl ReminderStore for SqliteStore {
+    fn load(&self) -> Result<Vec<Reminder>> {
+        let conn = self.connect()?;
+        query(&conn, "SELECT data FROM reminders ORDER BY rowid", [])
+    }
+
+    fn save(&self, reminders: &[Reminder]) -> Result<()> {
+        self.write(|tx| {
+            tx.execute("DELETE FROM reminders", [])?;
+            for reminder in reminders {
+                upsert(tx, reminder)?;
+            }
+            Ok(())
+        })
+    }
+
+    fn transaction(&self, mutation: Mutation) -> Result<()> {
+        self.apply(Scope::All, mutation, None)
+    }
+
+    /// Loads only the rows in `scope`, and for `finish` the rows sharing a
+    /// number or alias with a changed one, found through their indexes.
+    /// Only the rows that changed are written.
+    fn apply(&self, scope: Scope, mutation: Mutation, finish: Option<Finish>) -> Result<()> {
+        self.write(|tx| {
+            let mut index = ReminderIndex::new(load_scope(tx, &scope)?);
+            index.set_last_number(last_number(tx)?);
+            let changed = mutation(&mut index)?;
+            let finished = match finish {
+                Some(finish) => {
+                    // Written first, so removed and renumbered rows are
+                    // looked up as they will be
+                    write_changes(tx, &index)?;
+                    for reminder in clashing(tx, &index)? {
+                        index.include(reminder);
+                    }
+                    finish(&mut index)?
+                }
+                None => false,
+            };
+            if changed || finished {
+                // Revisions as in a JSON store; there are no tombstones
+                merge::record_changes(&mut index, &mut Vec::new(), &HashMap::new());
+                write_changes(tx, &index)?;
+                count_number(tx, index.max_number())?;
+            }
+            Ok(())
+        })
+    }
+
+    fn index(&self) -> Result<Arc<ReminderIndex>> {
+        let conn = self.connect()?;
+        let mut index = ReminderIndex::new(query(
+            &conn,
+            "SELECT data FROM reminders ORDER BY rowid",
+            [],
+        )?);
+        index.set_last_number(last_number(&conn)?);
+        Ok(Arc::new(index))
+    }
+
+    fn upsert_all(&self, reminders: &[Reminder]) -> Result<()> {
+        self.write(|tx| {
+            for reminder in reminders {
+                upsert(tx, reminder)?;
+            }
+            Ok(())
+        })
+    }
+
+    fn add(&self, mut reminder: Reminder) -> Result<()> {
+        reminder.touch(0);
+        self.upsert_all(std::slice::from_ref(&reminder))
+    }
+
+    fn get(&self, id: Uuid) -> Result<Option<Reminder>> {
+        let conn = self.connect()?;
+        let data: Option<String> = conn
+            .query_row(
+                "SELECT data FROM reminders WHERE id = ?1",
+                [id.to_string()],
+                |row| row.get(0),
+            )
+            .optional()?;
+        data.map(|d| parse(&d)).transpose()
+    }
+
+    fn update(&self, id: Uuid, updater: Updater) -> Result<bool> {
+        self.write(|tx| {
+            let data: Option<String> = tx
+                .query_row(
+                    "SELECT data FROM reminders WHERE id = ?1",
+                    [id.to_string()],
+                    |row| row.get(0),
+                )
+                .optional()?;
+            let Some(data) = data else {
+                return Ok(false);
+            };
+
+            let mut reminder = parse(&data)?;
+            updater(&mut reminder);
+            reminder.touch(0);
+            upsert(tx, &reminder)?;
+            Ok(true)
+        })
+    }
+
+    fn delete(&self, id: Uuid) -> Result<bool> {
+        self.write(|tx| {
+            let deleted = tx.execute("DELETE FROM reminders WHERE id = ?1", [id.to_string()])?;
+            Ok(deleted > 0)
+        })
+    }
+
+    fn filter_by_tag(&self, tag: &str) -> Result<Vec<Reminder>> {
+        let conn = self.connect()?;
+        query(
+            &conn,
+            "SELECT r.data FROM reminders r
+             JOIN reminder_tags t ON t.reminder_id = r.id
+             WHERE t.tag = ?1 ORDER BY r.rowid",
+            [tag],
+        )
+    }
+
+    fn get_all_tags(&self) -> Result<Vec<String>> {
+        let conn = self.connect()?;
+        let mut stmt = conn.prepare("SELECT DISTINCT tag FROM reminder_tags ORDER BY tag")?;
+        let tags = stmt
+            .query_map([], |row| row.get(0))?
+            .collect::<rusqlite::Result<Vec<String>>>()?;
+        Ok(tags)
+    }
+
+    fn tag_counts(&self) -> Result<Vec<(String, usize)>> {
+        let conn = self.connect()?;
+        let mut stmt =
+            conn.prepare("SELECT tag, COUNT(*) FROM reminder_tags GROUP BY tag ORDER BY tag")?;
+        let counts = stmt
+            .query_map([], |row| Ok((row.get(0)?, row.get::<_, i64>(1)? as usize)))?
+            .collect::<rusqlite::Result<Vec<(String, usize)>>>()?;
+        Ok(counts)
+    }
+
+    fn clean_completed(&self) -> Result<usize> {
+        self.write(|tx| Ok(tx.execute("DELETE FROM reminders WHERE completed = 1", [])?))
+    }
+
+    fn path(&self) -> Option<&Path> {
+        Some(&self.path)
+    }
+
+    fn find_by_short_id(&self, short_id: &str) -> Result<Option<Reminder>> {
+        if let Some(reminder) = self.find_by_name(short_id)? {
+            return Ok(Some(reminder));
+        }
+        single_match(short_id, self.find_by_prefix(short_id)?)
+    }
+
+    fn update_due(&self, updater: &mut dyn FnMut(&mut Reminder)) -> Result<usize> {
+        self.write(|tx| {
+            let due = query(
+                tx,
+                "SELECT data FROM reminders
+                 WHERE completed = 0 AND paused = 0 AND next_trigger <= ?1",
+                [Local::now().timestamp()],
+            )?;
+            let mut count = 0;
+            for mut reminder in due.into_iter().filter(|r| r.is_due()) {
+                updater(&mut reminder);
+                reminder.touch(0);
+                upsert(tx, &reminder)?;
+                count += 1;
+            }
+            Ok(count)
+        })
+    }
+
+    fn due_reminders(&self) -> Result<Vec<Reminder>> {
+        // Stored triggers are whole seconds; drop any still a fraction away
+        let due = self.due(Local::now())?;
+        Ok(due.into_iter().filter(|r| r.is_due()).collect())
+    }
+}
+
+fn query(conn: &Connection, sql: &str, params: impl rusqlite::Params) -> Result<Vec<Reminder>> {
+    let mut stmt = conn.prepare(sql)?;
+    let rows = stmt
+        .query_map(params, |row| row.get::<_, String>(0))?
+        .collect::<rusqlite::Result<Vec<String>>>()?;
+    rows.iter().map(|data| parse(data)).collect()
+}
+
+/// The rows a change to `scope` needs
+fn load_scope(tx: &Transaction, scope: &Scope) -> Result<Vec<Reminder>> {
+    match scope {
+        Scope::All => query(tx, "SELECT data FROM reminders ORDER BY rowid", []),
+        Scope::Ids(ids) => {
+            let mut rows = Vec::with_capacity(ids.len());
+            for id in ids {
+                rows.extend(query(
+                    tx,
+                    "SELECT data FROM reminders WHERE id = ?1",
+                    [id.to_string()],
+                )?);
+            }
+            Ok(rows)
+        }
+        Scope::Due(now) => query(
+            tx,
+            "SELECT data FROM reminders
+             WHERE completed = 0 AND paused = 0 AND next_trigger <= ?1
+             ORDER BY next_trigger",
+            [now.timestamp()],
+        ),
+        Scope::Completed => query(
+            tx,
+            "SELECT data FROM reminders WHERE completed = 1 ORDER BY rowid",
+            [],
+        ),
+    }
+}
+
+/// Rows the names of the changed reminders in `index` must be checked
+/// against: those sharing a number or alias with one, any unnumbered and
+/// the highest numbered
+fn clashing(tx: &Transaction, index: &ReminderIndex) -> Result<Vec<Reminder>> {
+    let mut numbers = HashSet::from([0]);
+    let mut aliases = HashSet::new();
+    for reminder in index.changed_ids().iter().filter_map(|&id| index.get(id)) {
+        numbers.insert(reminder.number);
+        aliases.extend(reminder.alias.as_deref());
+    }
+
+    let mut rows = query(tx, HIGHEST_NUMBER, [])?;
+    for number in numbers {
+        rows.extend(query(tx, BY_NUMBER, [number])?);
+    }
+    for alias in aliases {
+        rows.extend(query(tx, BY_ALIAS, [alias])?);
+    }
+    Ok(rows)
+}
+
+/// Write back the rows changed in `index`
+fn write_changes(tx: &Transaction, index: &ReminderIndex) -> Result<()> {
+    for &id in index.changed_ids() {
+        match index.get(id) {
+            Some(reminder) => upsert(tx, reminder)?,
+            None => {
+                tx.execute("DELETE FROM reminders WHERE id = ?1", [id.to_string()])?;
+            }
+        }
+    }
+    Ok(())
+}
+
+fn last_number(conn: &Connection) -> Result<u32> {
+    let number: Option<u32> = conn
+        .query_row(
+            "SELECT value FROM meta WHERE key = ?1",
+            [LAST_NUMBER],
+            |row| row.get(0),
+        )
+        .optional()?;
+    Ok(number.unwrap_or(0))
+}
+
+/// Count `number` as handed out, unless a higher one was
+fn count_number(tx: &Transaction, number: u32) -> Result<()> {
+    tx.execute(
+        "INSERT INTO meta (key, value) VALUES (?1, ?2)
+         ON CONFLICT (key) DO UPDATE SET value = MAX(value, excluded.value)",
+        params![LAST_NUMBER, number],
+    )?;
+    Ok(())
+}
+
+fn parse(data: &str) -> Result<Reminder> {
+    serde_json::from_str(data).context("Failed to parse stored reminder")
+}
+
+fn upsert(tx: &Transaction, reminder: &Reminder) -> Result<()> {
+    let id = reminder.id.to_string();
+    let data = serde_json::to_string(reminder).context("Failed to serialize reminder")?;
+
+    tx.execute(
+        "INSERT INTO reminders (id, next_trigger, completed, paused, data)
+         VALUES (?1, ?2, ?3, ?4, ?5)
+         ON CONFLICT (id) DO UPDATE SET
+             next_trigger = excluded.next_trigger,
+             completed = excluded.completed,
+             paused = excluded.paused,
+             data = excluded.data",
+        params![
+            id,
+            reminder.next_trigger.map(|t| t.timestamp()),
+            reminder.completed,
+            reminder.paused,
+            data
+        ],
+    )?;
+
+    count_number(tx, reminder.number)?;
+
+    tx.execute("DELETE FROM reminder_tags WHERE reminder_id = ?1", [&id])?;
+    for tag in &reminder.tags {
+        tx.execute(
+            "INSERT INTO reminder_tags (reminder_id, tag) VALUES (?1, ?2)",
+            params![id, tag],
+        )?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+
+    fn reminder(title: &str, minutes: i64, tags: &[&str]) -> Reminder {
+        Reminder::new_one_time(
+            title.to_string(),
+            None,
+            Local::now() + chrono::Duration::minutes(minutes),
+            tags.iter().map(|t| t.to_string()).collect::<HashSet<_>>(),
+        )
+    }
+
+    #[test]
+    fn test_queries_use_indexed_columns() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = SqliteStore::new(dir.path().join("reminders.db"));
+
+        let past = reminder("past", -5, &["work"]);
+        let future = reminder("future", 5, &["work", "home"]);
+        store.save(&[past.clone(), future.clone()]).unwrap();
+
+        let due: Vec<String> = store
+            .due(Local::now())
+            .unwrap()
+            .into_iter()
+            .map(|r| r.title)
+            .collect();
+        assert_eq!(due, ["past"]);
+
+        assert_eq!(store.filter_by_tag("home").unwrap()[0].id, future.id);
+        assert_eq!(store.get_all_tags().unwrap(), ["home", "work"]);
+        assert_eq!(
+            store.find_by_prefix(&past.id.to_string()[..8]).unwrap()[0].id,
+            past.id
+        );
+
+        store.update(past.id, Box::new(|r| r.tags.clear())).unwrap();
+        assert_eq!(store.filter_by_tag("work").unwrap().len(), 1);
+
+        assert!(store.delete(future.id).unwrap());
+        assert!(store.get_all_tags().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_name_lookups_use_indexes() {
+        let dir = tempfile::tempdir().unwrap();
+        let conn = SqliteStore::new(dir.path().join("reminders.db"))
+            .connect()
+            .unwrap();
+        for (sql, index) in [
+            (BY_NUMBER, "idx_reminders_number"),
+            (BY_ALIAS, "idx_reminders_alias"),
+            (HIGHEST_NUMBER, "idx_reminders_number"),
+        ] {
+            let mut stmt = conn
+                .prepare(&format!("EXPLAIN QUERY PLAN {}", sql))
+                .unwrap();
+            let params = vec![1; stmt.parameter_count()];
+            let plan: String = stmt
+                .query_row(rusqlite::params_from_iter(params), |row| row.get(3))
+                .unwrap();
+            assert!(plan.contains(index), "{}: {}", sql, plan);
+        }
+    }
+}
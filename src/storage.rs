@@ -1,227 +1,245 @@
+use crate::crypto::{self, Vault};
+use crate::events::{self, Cause, EventLog};
+use crate::git::{GitRepo, GitStore};
+use crate::ids::Names;
+use crate::index::ReminderIndex;
+use crate::merge::{self, Document, MergeReport, Tombstone};
+use crate::paths;
 use crate::reminder::Reminder;
-use anyhow::{Context, Result};
+use crate::retry::DeadLetterQueue;
+use crate::sqlite::SqliteStore;
+use crate::{log_info, log_warn};
+use anyhow::{bail, Context, Result};
+use chrono::{DateTime, Local};
 use fs2::FileExt;
+use std::collections::HashMap;
 use std::fs;
 use std::fs::{File, OpenOptions};
-use std::io::{Read, Write};
+use std::io::Write;
 use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
 use uuid::Uuid;
 
-pub struct Storage {
-    path: PathBuf,
-}
+const JSON_FILE: &str = "reminders.json";
+const SQLITE_FILE: &str = "reminders.db";
+pub const FAILED_FILE: &str = "failed.json";
 
-impl Storage {
-    pub fn new() -> Result<Self> {
-        let data_dir = dirs::data_local_dir()
-            .context("Failed to get local data directory")?
-            .join("reminder-cli");
+/// Suffix of the file next to a JSON store that keeps its tombstones and
+/// the last number handed out
+const META_SUFFIX: &str = "meta";
+/// The tombstones file next to `reminders.json`
+pub const META_FILE: &str = "reminders.json.meta";
 
-        fs::create_dir_all(&data_dir)?;
+/// Previous versions of a JSON store kept by each save
+pub const BACKUP_COUNT: usize = 5;
 
-        Ok(Self {
-            path: data_dir.join("reminders.json"),
-        })
-    }
+/// Change applied to a single reminder by [`ReminderStore::update`]
+pub type Updater<'a> = Box<dyn FnOnce(&mut Reminder) + 'a>;
 
-    pub fn load(&self) -> Result<Vec<Reminder>> {
-        if !self.path.exists() {
-            return Ok(Vec::new());
-        }
+/// Change applied to the indexed reminders by [`ReminderStore::transaction`].
+/// Returns whether anything changed and should be saved.
+pub type Mutation<'a> = Box<dyn FnOnce(&mut ReminderIndex) -> Result<bool> + 'a>;
 
-        let file = File::open(&self.path).context("Failed to open reminders file")?;
-        file.lock_shared().context("Failed to acquire read lock")?;
+/// Run by [`ReminderStore::apply`] after the mutation. Returns whether it
+/// changed anything too.
+pub type Finish<'a> = Box<dyn FnOnce(&mut ReminderIndex) -> Result<bool> + 'a>;
 
-        let mut content = String::new();
-        let mut reader = &file;
-        reader
-            .read_to_string(&mut content)
-            .context("Failed to read reminders file")?;
+/// The reminders a change needs, so stores that can load some reminders
+/// without the rest load only those
+#[derive(Debug, Clone)]
+pub enum Scope {
+    All,
+    Ids(Vec<Uuid>),
+    /// Active reminders due at the given time
+    Due(DateTime<Local>),
+    Completed,
+}
 
-        file.unlock().context("Failed to release lock")?;
+/// A place reminders are kept. Only `load`, `save` and `transaction` are
+/// required; every other change goes through `transaction`, and stores may
+/// override operations they can do better than rewriting everything.
+pub trait ReminderStore: Send + Sync {
+    fn load(&self) -> Result<Vec<Reminder>>;
 
-        if content.trim().is_empty() {
-            return Ok(Vec::new());
-        }
+    /// Replace every stored reminder
+    fn save(&self, reminders: &[Reminder]) -> Result<()>;
 
-        let reminders: Vec<Reminder> =
-            serde_json::from_str(&content).context("Failed to parse reminders JSON")?;
+    /// Load, apply `mutation` and save, with no other writer able to change
+    /// the store in between. Nothing is saved if `mutation` fails or
+    /// reports no change. The index tracks which reminders `mutation`
+    /// changed, so stores can write back just those.
+    fn transaction(&self, mutation: Mutation) -> Result<()>;
 
-        Ok(reminders)
+    /// [`ReminderStore::transaction`] for a change to the reminders in
+    /// `scope`; the index may hold only those. `finish` runs after
+    /// `mutation`, once the index also holds every reminder sharing a
+    /// number or alias with a changed one, any still unnumbered and the
+    /// highest numbered, so it can settle names.
+    fn apply(&self, scope: Scope, mutation: Mutation, finish: Option<Finish>) -> Result<()> {
+        let _ = scope;
+        self.transaction(Box::new(|index| {
+            let changed = mutation(index)?;
+            let finished = match finish {
+                Some(finish) => finish(index)?,
+                None => false,
+            };
+            Ok(changed || finished)
+        }))
     }
 
-    pub fn save(&self, reminders: &[Reminder]) -> Result<()> {
-        let file = OpenOptions::new()
-            .write(true)
-            .create(true)
-            .truncate(true)
-            .open(&self.path)
-            .context("Failed to open reminders file for writing")?;
-
-        file.lock_exclusive()
-            .context("Failed to acquire write lock")?;
-
-        let content =
-            serde_json::to_string_pretty(reminders).context("Failed to serialize reminders")?;
-
-        let mut writer = &file;
-        writer
-            .write_all(content.as_bytes())
-            .context("Failed to write reminders file")?;
-
-        file.unlock().context("Failed to release lock")?;
-
-        Ok(())
+    /// Backing file, if the store has one
+    fn path(&self) -> Option<&Path> {
+        None
     }
 
-    pub fn add(&self, reminder: Reminder) -> Result<()> {
-        let mut reminders = self.load()?;
-        reminders.push(reminder);
-        self.save(&reminders)
+    /// The reminders, indexed for the lookups below. Stores that keep
+    /// reminders in memory hand out the same index until they change.
+    fn index(&self) -> Result<Arc<ReminderIndex>> {
+        Ok(Arc::new(ReminderIndex::new(self.load()?)))
     }
 
-    pub fn delete(&self, id: Uuid) -> Result<bool> {
-        let mut reminders = self.load()?;
-        let initial_len = reminders.len();
-        reminders.retain(|r| r.id != id);
+    /// Key the store is encrypted with, if it can be, so the files kept
+    /// next to it can follow its encryption
+    fn vault(&self) -> Option<Arc<Vault>> {
+        None
+    }
 
-        if reminders.len() == initial_len {
-            return Ok(false);
+    /// Dead-letter queue of failed deliveries, next to the reminders file.
+    /// Stores without a file use a per-process file in the temp directory.
+    fn failed_deliveries_path(&self) -> PathBuf {
+        match self.path() {
+            Some(path) => path.with_file_name(FAILED_FILE),
+            None => std::env::temp_dir().join(format!(
+                "reminder-cli-{}-{}",
+                std::process::id(),
+                FAILED_FILE
+            )),
         }
-
-        self.save(&reminders)?;
-        Ok(true)
     }
 
-    pub fn update(&self, id: Uuid, updater: impl FnOnce(&mut Reminder)) -> Result<bool> {
-        let mut reminders = self.load()?;
-
-        if let Some(reminder) = reminders.iter_mut().find(|r| r.id == id) {
-            updater(reminder);
-            self.save(&reminders)?;
+    fn add(&self, reminder: Reminder) -> Result<()> {
+        let scope = Scope::Ids(vec![reminder.id]);
+        let insert = Box::new(|index: &mut ReminderIndex| {
+            index.insert(reminder);
             Ok(true)
-        } else {
-            Ok(false)
-        }
+        });
+        self.apply(scope, insert, None)
     }
 
-    pub fn get(&self, id: Uuid) -> Result<Option<Reminder>> {
-        let reminders = self.load()?;
-        Ok(reminders.into_iter().find(|r| r.id == id))
+    fn delete(&self, id: Uuid) -> Result<bool> {
+        let mut deleted = false;
+        let remove = Box::new(|index: &mut ReminderIndex| {
+            deleted = index.remove(id).is_some();
+            Ok(deleted)
+        });
+        self.apply(Scope::Ids(vec![id]), remove, None)?;
+        Ok(deleted)
     }
 
-    /// Find reminder by short ID (prefix match)
-    pub fn find_by_short_id(&self, short_id: &str) -> Result<Option<Reminder>> {
-        let reminders = self.load()?;
-        let matches: Vec<_> = reminders
-            .into_iter()
-            .filter(|r| r.id.to_string().starts_with(short_id))
-            .collect();
+    fn update(&self, id: Uuid, updater: Updater) -> Result<bool> {
+        let mut found = false;
+        let update = Box::new(|index: &mut ReminderIndex| {
+            found = index.update(id, updater);
+            Ok(found)
+        });
+        self.apply(Scope::Ids(vec![id]), update, None)?;
+        Ok(found)
+    }
 
-        match matches.len() {
-            0 => Ok(None),
-            1 => Ok(Some(matches.into_iter().next().unwrap())),
-            _ => anyhow::bail!(
-                "Ambiguous ID '{}': matches {} reminders. Please use more characters.",
-                short_id,
-                matches.len()
-            ),
-        }
+    fn get(&self, id: Uuid) -> Result<Option<Reminder>> {
+        Ok(self.index()?.get(id).cloned())
     }
 
-    /// Delete reminder by short ID
-    pub fn delete_by_short_id(&self, short_id: &str) -> Result<Option<Uuid>> {
-        let mut reminders = self.load()?;
-        let matches: Vec<_> = reminders
-            .iter()
-            .filter(|r| r.id.to_string().starts_with(short_id))
-            .map(|r| r.id)
-            .collect();
+    /// Find reminder by number, alias or short ID (prefix match)
+    fn find_by_short_id(&self, short_id: &str) -> Result<Option<Reminder>> {
+        let index = self.index()?;
+        let matches = index.find(short_id).into_iter().cloned().collect();
+        single_match(short_id, matches)
+    }
 
-        match matches.len() {
-            0 => Ok(None),
-            1 => {
-                let id = matches[0];
-                reminders.retain(|r| r.id != id);
-                self.save(&reminders)?;
-                Ok(Some(id))
-            }
-            _ => anyhow::bail!(
-                "Ambiguous ID '{}': matches {} reminders. Please use more characters.",
-                short_id,
-                matches.len()
-            ),
-        }
+    /// Delete reminder by short ID
+    fn delete_by_short_id(&self, short_id: &str) -> Result<Option<Uuid>> {
+        let Some(reminder) = self.find_by_short_id(short_id)? else {
+            return Ok(None);
+        };
+        self.delete(reminder.id)?;
+        Ok(Some(reminder.id))
     }
 
     /// Clean completed reminders
-    pub fn clean_completed(&self) -> Result<usize> {
-        let mut reminders = self.load()?;
-        let initial_len = reminders.len();
-        reminders.retain(|r| !r.completed);
-        let removed = initial_len - reminders.len();
-
-        if removed > 0 {
-            self.save(&reminders)?;
-        }
-
+    fn clean_completed(&self) -> Result<usize> {
+        let mut removed = 0;
+        let clean = Box::new(|index: &mut ReminderIndex| {
+            let completed: Vec<Uuid> = index.iter().filter(|r| r.completed).map(|r| r.id).collect();
+            for &id in &completed {
+                index.remove(id);
+            }
+            removed = completed.len();
+            Ok(removed > 0)
+        });
+        self.apply(Scope::Completed, clean, None)?;
         Ok(removed)
     }
 
-    pub fn pid_file_path() -> Result<PathBuf> {
-        let data_dir = dirs::data_local_dir()
-            .context("Failed to get local data directory")?
-            .join("reminder-cli");
-
-        fs::create_dir_all(&data_dir)?;
-        Ok(data_dir.join("daemon.pid"))
+    /// Filter reminders by tag
+    fn filter_by_tag(&self, tag: &str) -> Result<Vec<Reminder>> {
+        Ok(self.index()?.with_tag(tag).into_iter().cloned().collect())
     }
 
-    pub fn log_file_path() -> Result<PathBuf> {
-        let data_dir = dirs::data_local_dir()
-            .context("Failed to get local data directory")?
-            .join("reminder-cli");
-
-        fs::create_dir_all(&data_dir)?;
-        Ok(data_dir.join("daemon.log"))
+    /// Get all unique tags
+    fn get_all_tags(&self) -> Result<Vec<String>> {
+        Ok(self.tag_counts()?.into_iter().map(|(tag, _)| tag).collect())
     }
 
-    pub fn heartbeat_file_path() -> Result<PathBuf> {
-        let data_dir = dirs::data_local_dir()
-            .context("Failed to get local data directory")?
-            .join("reminder-cli");
+    /// Every tag in use and how many reminders have it, sorted by tag
+    fn tag_counts(&self) -> Result<Vec<(String, usize)>> {
+        Ok(self
+            .index()?
+            .tags()
+            .into_iter()
+            .map(|(tag, count)| (tag.to_string(), count))
+            .collect())
+    }
 
-        fs::create_dir_all(&data_dir)?;
-        Ok(data_dir.join("daemon.heartbeat"))
+    /// Reminders that should fire now
+    fn due_reminders(&self) -> Result<Vec<Reminder>> {
+        let index = self.index()?;
+        Ok(index.due(Local::now()).into_iter().cloned().collect())
     }
 
-    /// Filter reminders by tag
-    pub fn filter_by_tag(&self, tag: &str) -> Result<Vec<Reminder>> {
-        let reminders = self.load()?;
-        Ok(reminders
-            .into_iter()
-            .filter(|r| r.tags.contains(tag))
-            .collect())
+    /// Write back changed reminders, leaving the rest of the store as is
+    fn upsert_all(&self, changed: &[Reminder]) -> Result<()> {
+        let scope = Scope::Ids(changed.iter().map(|r| r.id).collect());
+        let upsert = Box::new(|index: &mut ReminderIndex| {
+            for reminder in changed {
+                index.insert(reminder.clone());
+            }
+            Ok(!changed.is_empty())
+        });
+        self.apply(scope, upsert, None)
     }
 
-    /// Get all unique tags
-    pub fn get_all_tags(&self) -> Result<Vec<String>> {
-        let reminders = self.load()?;
-        let mut tags: Vec<String> = reminders
-            .iter()
-            .flat_map(|r| r.tags.iter().cloned())
-            .collect();
-        tags.sort();
-        tags.dedup();
-        Ok(tags)
+    /// Apply `updater` to every reminder that is due, atomically. Returns
+    /// how many were updated.
+    fn update_due(&self, updater: &mut dyn FnMut(&mut Reminder)) -> Result<usize> {
+        let mut count = 0;
+        let now = Local::now();
+        let update = Box::new(|index: &mut ReminderIndex| {
+            count = update_due_in(index, now, updater);
+            Ok(count > 0)
+        });
+        self.apply(Scope::Due(now), update, None)?;
+        Ok(count)
     }
 
     /// Pause reminder by short ID
-    pub fn pause_by_short_id(&self, short_id: &str) -> Result<Option<Uuid>> {
+    fn pause_by_short_id(&self, short_id: &str) -> Result<Option<Uuid>> {
         let reminder = self.find_by_short_id(short_id)?;
         if let Some(r) = reminder {
             let id = r.id;
-            self.update(id, |rem| rem.pause())?;
+            self.update(id, Box::new(|rem| rem.pause()))?;
             Ok(Some(id))
         } else {
             Ok(None)
@@ -229,11 +247,11 @@ impl Storage {
     }
 
     /// Resume reminder by short ID
-    pub fn resume_by_short_id(&self, short_id: &str) -> Result<Option<Uuid>> {
+    fn resume_by_short_id(&self, short_id: &str) -> Result<Option<Uuid>> {
         let reminder = self.find_by_short_id(short_id)?;
         if let Some(r) = reminder {
             let id = r.id;
-            self.update(id, |rem| rem.resume())?;
+            self.update(id, Box::new(|rem| rem.resume()))?;
             Ok(Some(id))
         } else {
             Ok(None)
@@ -241,7 +259,7 @@ impl Storage {
     }
 
     /// Export all reminders to a JSON file
-    pub fn export_to_file(&self, path: &Path) -> Result<usize> {
+    fn export_to_file(&self, path: &Path) -> Result<usize> {
         let reminders = self.load()?;
         let count = reminders.len();
 
@@ -255,34 +273,1043 @@ impl Storage {
 
     /// Import reminders from a JSON file
     /// Returns (imported_count, skipped_count)
-    pub fn import_from_file(&self, path: &Path, overwrite: bool) -> Result<(usize, usize)> {
-        let content = fs::read_to_string(path).context("Failed to read import file")?;
-
-        let imported: Vec<Reminder> =
-            serde_json::from_str(&content).context("Failed to parse import JSON")?;
-
-        let mut existing = self.load()?;
-        let existing_ids: std::collections::HashSet<Uuid> = existing.iter().map(|r| r.id).collect();
-
-        let mut imported_count = 0;
-        let mut skipped_count = 0;
-
-        for reminder in imported {
-            if existing_ids.contains(&reminder.id) {
-                if overwrite {
-                    existing.retain(|r| r.id != reminder.id);
-                    existing.push(reminder);
-                    imported_count += 1;
-                } else {
-                    skipped_count += 1;
+    fn import_from_file(&self, path: &Path, overwrite: bool) -> Result<(usize, usize)> {
+        let imported = read_import(path)?;
+        let scope = Scope::Ids(imported.iter().map(|r| r.id).collect());
+        let mut counts = (0, 0);
+        let import = Box::new(|index: &mut ReminderIndex| {
+            counts = import_into(index, imported, overwrite);
+            Ok(true)
+        });
+        self.apply(scope, import, None)?;
+        Ok(counts)
+    }
+
+    /// Merge another copy of the reminders into the store, keeping the
+    /// newest version of each, then run `finish` in the same transaction.
+    /// Stores without tombstones cannot tell a deleted reminder from one
+    /// they never had, so the other copy may bring it back.
+    fn merge(&self, other: Document, finish: Option<Finish>) -> Result<MergeReport> {
+        let mut report = MergeReport::default();
+        self.transaction(Box::new(|index| {
+            let mut document = Document {
+                reminders: index.to_vec(),
+                tombstones: Vec::new(),
+                last_number: index.max_number(),
+            };
+            report = document.merge(other);
+            index.set_last_number(document.last_number);
+            if !report.is_empty() {
+                index.replace_all(document.reminders);
+            }
+            let finished = match finish {
+                Some(finish) => finish(index)?,
+                None => false,
+            };
+            Ok(!report.is_empty() || finished)
+        }))?;
+        Ok(report)
+    }
+}
+
+fn read_import(path: &Path) -> Result<Vec<Reminder>> {
+    let content = fs::read_to_string(path).context("Failed to read import file")?;
+
+    // Exports are plain lists; a copy of a store also has tombstones
+    Ok(Document::parse(content.as_bytes())
+        .context("Failed to parse import JSON")?
+        .reminders)
+}
+
+/// Add `imported` to `index`, replacing reminders with the same ID only if
+/// `overwrite` is set. Returns (imported_count, skipped_count).
+fn import_into(
+    index: &mut ReminderIndex,
+    imported: Vec<Reminder>,
+    overwrite: bool,
+) -> (usize, usize) {
+    let mut imported_count = 0;
+    let mut skipped_count = 0;
+
+    for reminder in imported {
+        if index.get(reminder.id).is_some() {
+            if overwrite {
+                // Replaced reminders move to the end, like added ones
+                index.remove(reminder.id);
+                index.insert(reminder);
+                imported_count += 1;
+            } else {
+                skipped_count += 1;
+            }
+        } else {
+            index.insert(reminder);
+            imported_count += 1;
+        }
+    }
+    (imported_count, skipped_count)
+}
+
+/// Apply `updater` to every reminder in `index` due at `now`. Returns how
+/// many were updated.
+fn update_due_in(
+    index: &mut ReminderIndex,
+    now: DateTime<Local>,
+    updater: &mut dyn FnMut(&mut Reminder),
+) -> usize {
+    let due: Vec<Uuid> = index.due(now).into_iter().map(|r| r.id).collect();
+    for &id in &due {
+        index.update(id, &mut *updater);
+    }
+    due.len()
+}
+
+/// The one reminder matching a short ID, or an error if several do
+pub(crate) fn single_match(short_id: &str, matches: Vec<Reminder>) -> Result<Option<Reminder>> {
+    match matches.len() {
+        0 => Ok(None),
+        1 => Ok(matches.into_iter().next()),
+        _ => bail!(
+            "Ambiguous ID '{}': matches {} reminders. Please use more characters.",
+            short_id,
+            matches.len()
+        ),
+    }
+}
+
+/// Reminders in a pretty-printed JSON file. Saves go through a temp file
+/// that is fsynced and renamed over the old one, so readers always see a
+/// complete file; the previous good versions are kept as numbered backups.
+/// Once the file is encrypted, every save is encrypted too.
+///
+/// Every change bumps the revision of the reminders it touches and leaves
+/// tombstones for deleted ones, so copies edited on different devices can
+/// be merged. Copies left by sync tools after a conflict are merged in
+/// automatically.
+///
+/// The parsed reminders are kept in memory and only read again once the
+/// file changes on disk, so repeated lookups in a long-running process
+/// cost no IO. Clones share what they have read.
+#[derive(Debug, Clone)]
+pub struct JsonStore {
+    path: PathBuf,
+    vault: Arc<Vault>,
+    cache: Arc<Mutex<Option<Snapshot>>>,
+}
+
+/// The file as last read or written
+#[derive(Debug)]
+struct Snapshot {
+    version: Option<FileVersion>,
+    index: Arc<ReminderIndex>,
+    tombstones: Vec<Tombstone>,
+}
+
+/// Tells one version of a file from the next. Saves rename a new file
+/// into place, so the inode changes even when size and time do not.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct FileVersion {
+    modified: Option<SystemTime>,
+    len: u64,
+    inode: u64,
+}
+
+impl FileVersion {
+    fn of(path: &Path) -> Option<Self> {
+        fs::metadata(path)
+            .ok()
+            .map(|meta| Self::from_metadata(&meta))
+    }
+
+    fn from_metadata(meta: &fs::Metadata) -> Self {
+        #[cfg(unix)]
+        let inode = std::os::unix::fs::MetadataExt::ino(meta);
+        #[cfg(not(unix))]
+        let inode = 0;
+        Self {
+            modified: meta.modified().ok(),
+            len: meta.len(),
+            inode,
+        }
+    }
+}
+
+impl JsonStore {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self::with_vault(path, Arc::new(Vault::default()))
+    }
+
+    /// Open an encrypted file with a given key instead of looking one up
+    pub fn with_vault(path: impl Into<PathBuf>, vault: Arc<Vault>) -> Self {
+        Self {
+            path: path.into(),
+            vault,
+            cache: Arc::default(),
+        }
+    }
+
+    pub fn is_encrypted(&self) -> bool {
+        let mut magic = [0u8; 8];
+        File::open(&self.path)
+            .and_then(|mut file| std::io::Read::read_exact(&mut file, &mut magic))
+            .is_ok()
+            && crypto::is_encrypted(&magic)
+    }
+
+    /// Plaintext of a store or backup file
+    fn decode(&self, content: Vec<u8>) -> Result<Vec<u8>> {
+        if crypto::is_encrypted(&content) {
+            self.vault.decrypt(&content)
+        } else {
+            Ok(content)
+        }
+    }
+
+    fn parse(&self, content: Vec<u8>) -> Result<Document> {
+        Document::parse(&self.decode(content)?)
+    }
+
+    /// `reminders.json.meta`, where the tombstones and the last number
+    /// handed out are kept
+    fn meta_path(&self) -> PathBuf {
+        sibling(&self.path, META_SUFFIX)
+    }
+
+    /// Reminders in the file and tombstones next to it
+    pub fn document(&self) -> Result<Document> {
+        if !self.path.exists() {
+            return Ok(Document::default());
+        }
+
+        let content = fs::read(&self.path).context("Failed to read reminders file")?;
+        let content = self.decode(content)?;
+        let mut document = Document::parse(&content).with_context(|| {
+            format!(
+                "{} is corrupted; run `reminder storage recover` to restore the latest backup",
+                self.path.display()
+            )
+        })?;
+
+        let meta = self.meta_path();
+        if let Ok(content) = fs::read(&meta) {
+            // Only tombstones are lost, so the reminders are still usable
+            if let Err(e) = document.parse_meta(&content) {
+                log_warn!("Ignoring unreadable {}: {:#}", meta.display(), e);
+            }
+        }
+        Ok(document)
+    }
+
+    /// The cached contents, read again if the file changed since
+    fn snapshot(&self) -> Result<(Arc<ReminderIndex>, Vec<Tombstone>)> {
+        let mut cache = self.cache.lock().unwrap_or_else(|e| e.into_inner());
+        let version = FileVersion::of(&self.path);
+        if let Some(snapshot) = cache.as_ref().filter(|s| s.version == version) {
+            return Ok((Arc::clone(&snapshot.index), snapshot.tombstones.clone()));
+        }
+
+        let document = self.document()?;
+        let mut index = ReminderIndex::new(document.reminders);
+        index.set_last_number(document.last_number);
+        let index = Arc::new(index);
+        *cache = Some(Snapshot {
+            version,
+            index: Arc::clone(&index),
+            tombstones: document.tombstones.clone(),
+        });
+        Ok((index, document.tombstones))
+    }
+
+    /// Copies of the file, or of the tombstones next to it, left by sync
+    /// tools after conflicting changes
+    pub fn conflict_copies(&self) -> Vec<PathBuf> {
+        let dir = match self.path.parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir,
+            _ => Path::new("."),
+        };
+        let Ok(entries) = fs::read_dir(dir) else {
+            return Vec::new();
+        };
+
+        let meta = self.meta_path();
+        let mut copies: Vec<PathBuf> = entries
+            .filter_map(|entry| entry.ok())
+            .map(|entry| entry.path())
+            .filter(|path| {
+                (is_conflict_copy(&self.path, path) || is_conflict_copy(&meta, path))
+                    && path.is_file()
+            })
+            .collect();
+        copies.sort();
+        copies
+    }
+
+    /// Merge the conflict copies into `index` and `tombstones`; the caller
+    /// holds the lock. Copies that cannot be read are left for the user.
+    /// Returns the copies merged, to be removed once the result is saved.
+    fn absorb_conflicts(
+        &self,
+        index: &mut ReminderIndex,
+        tombstones: &mut Vec<Tombstone>,
+    ) -> Vec<PathBuf> {
+        let mut merged = Vec::new();
+        let mut document: Option<Document> = None;
+        let meta = self.meta_path();
+        for copy in self.conflict_copies() {
+            let other = fs::read(&copy)
+                .context("Failed to read file")
+                .and_then(|content| {
+                    if is_conflict_copy(&meta, &copy) {
+                        let mut other = Document::default();
+                        other.parse_meta(&content)?;
+                        Ok(other)
+                    } else {
+                        self.parse(content)
+                    }
+                });
+            match other {
+                Ok(other) => {
+                    let document = document.get_or_insert_with(|| Document {
+                        reminders: index.to_vec(),
+                        tombstones: std::mem::take(tombstones),
+                        last_number: index.max_number(),
+                    });
+                    let report = document.merge(other);
+                    log_info!("Merged conflict copy {}: {}", copy.display(), report);
+                    merged.push(copy);
                 }
+                Err(e) => log_warn!("Skipping conflict copy {}: {:#}", copy.display(), e),
+            }
+        }
+        if let Some(document) = document {
+            index.replace_all(document.reminders);
+            index.set_last_number(document.last_number);
+            *tombstones = document.tombstones;
+        }
+        merged
+    }
+
+    fn remove_copies(copies: &[PathBuf]) {
+        for copy in copies {
+            if let Err(e) = fs::remove_file(copy) {
+                log_warn!("Failed to remove conflict copy {}: {}", copy.display(), e);
+            }
+        }
+    }
+
+    /// Rewrite the file and its backups encrypted with `vault`, or as
+    /// plaintext for `None`. Backups that cannot be read are left alone.
+    /// Returns the number of reminders.
+    pub fn convert(&self, vault: Option<&Vault>) -> Result<usize> {
+        let lock = self.lock()?;
+        let document = self.document()?;
+
+        for backup in self.backup_paths() {
+            let Ok(content) = fs::read(&backup) else {
+                continue;
+            };
+            let Ok(plaintext) = self.decode(content) else {
+                continue;
+            };
+            write_atomic(&backup, &encode(plaintext, vault)?)?;
+        }
+        self.write_meta(&document)?;
+        write_atomic(&self.path, &encode(document.to_json()?, vault)?)?;
+        lock.unlock().context("Failed to release lock")?;
+
+        Ok(document.reminders.len())
+    }
+
+    /// Load, merge in `other` if given, apply `mutation` and save under
+    /// the lock. With `overwrite`, a file that does not parse counts as
+    /// empty instead of failing. Returns what merging `other` changed.
+    fn commit(
+        &self,
+        other: Option<Document>,
+        mutation: Mutation,
+        overwrite: bool,
+    ) -> Result<MergeReport> {
+        let lock = self.lock()?;
+        let (index, mut tombstones) = match self.snapshot() {
+            Err(_) if overwrite => Default::default(),
+            snapshot => snapshot?,
+        };
+        let mut index = (*index).clone();
+        let merged = self.absorb_conflicts(&mut index, &mut tombstones);
+
+        let mut report = MergeReport::default();
+        let mut buried = false;
+        if let Some(other) = other {
+            let mut document = Document {
+                reminders: index.to_vec(),
+                tombstones,
+                last_number: index.max_number(),
+            };
+            let count = document.tombstones.len();
+            report = document.merge(other);
+            buried = document.tombstones.len() != count;
+            index.set_last_number(document.last_number);
+            if !report.is_empty() {
+                index.replace_all(document.reminders);
+            }
+            tombstones = document.tombstones;
+        }
+        // Merged versions keep the revisions they had; they stay among the
+        // index's changes, so a log sees them
+        let absorbed: HashMap<Uuid, Option<Reminder>> = index
+            .changed_ids()
+            .iter()
+            .map(|&id| (id, index.get(id).cloned()))
+            .collect();
+
+        let changed = mutation(&mut index)?;
+        if changed {
+            merge::record_changes(&mut index, &mut tombstones, &absorbed);
+        }
+        if changed || !merged.is_empty() || !report.is_empty() || buried {
+            self.write(index, tombstones)?;
+        }
+        Self::remove_copies(&merged);
+        lock.unlock().context("Failed to release lock")?;
+
+        Ok(report)
+    }
+
+    /// `reminders.json.bak1` (newest) to `reminders.json.bak<BACKUP_COUNT>`
+    pub fn backup_paths(&self) -> Vec<PathBuf> {
+        (1..=BACKUP_COUNT)
+            .map(|n| sibling(&self.path, &format!("bak{}", n)))
+            .collect()
+    }
+
+    /// Serializes writers; readers never need it because saves are atomic
+    pub fn lock(&self) -> Result<File> {
+        let file = OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(sibling(&self.path, "lock"))
+            .context("Failed to open reminders lock file")?;
+        file.lock_exclusive()
+            .context("Failed to acquire write lock")?;
+        Ok(file)
+    }
+
+    /// Replace the file and the tombstones next to it; the caller holds
+    /// the lock
+    fn write(&self, mut index: ReminderIndex, tombstones: Vec<Tombstone>) -> Result<()> {
+        let document = Document {
+            reminders: index.to_vec(),
+            tombstones,
+            last_number: index.max_number(),
+        };
+        // Tombstones first: a crash in between leaves a reminder that was
+        // just deleted, not one that can come back from an older copy
+        self.write_meta(&document)?;
+        let content = document.to_json()?;
+        let vault = self.is_encrypted().then_some(&*self.vault);
+        let content = encode(content, vault)?;
+        self.rotate_backups()?;
+        write_atomic(&self.path, &content)?;
+
+        index.clear_changes();
+        index.set_last_number(document.last_number);
+        *self.cache.lock().unwrap_or_else(|e| e.into_inner()) = Some(Snapshot {
+            version: FileVersion::of(&self.path),
+            index: Arc::new(index),
+            tombstones: document.tombstones,
+        });
+        Ok(())
+    }
+
+    /// Write the tombstones and last number of `document` next to the
+    /// file, unless there is nothing to keep and never was
+    fn write_meta(&self, document: &Document) -> Result<()> {
+        let meta = self.meta_path();
+        if document.tombstones.is_empty() && document.last_number == 0 && !meta.exists() {
+            return Ok(());
+        }
+        write_atomic(&meta, &document.meta_to_json()?)
+    }
+
+    /// Shift the backups along and copy the current file in as the newest,
+    /// unless it is unreadable. A file this store last read or wrote is
+    /// known to be readable; only one changed since is parsed to check.
+    fn rotate_backups(&self) -> Result<()> {
+        let Ok(mut file) = File::open(&self.path) else {
+            return Ok(());
+        };
+        let mut current = Vec::new();
+        if std::io::Read::read_to_end(&mut file, &mut current).is_err() {
+            return Ok(());
+        }
+        let version = file.metadata().ok().map(|m| FileVersion::from_metadata(&m));
+        let cached = self
+            .cache
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .as_ref()
+            .is_some_and(|s| version.is_some() && s.version == version);
+        if !cached && self.parse(current.clone()).is_err() {
+            return Ok(());
+        }
+
+        let backups = self.backup_paths();
+        for pair in backups.windows(2).rev() {
+            if pair[0].exists() {
+                fs::rename(&pair[0], &pair[1]).context("Failed to rotate backups")?;
+            }
+        }
+        write_atomic(&backups[0], &current)
+    }
+
+    /// Replace a corrupted reminders file with the newest backup that
+    /// parses. The corrupted file is kept next to it.
+    pub fn recover(&self) -> Result<Recovery> {
+        if let Ok(content) = fs::read(&self.path) {
+            if self.parse(content).is_ok() {
+                bail!("{} is valid; nothing to recover", self.path.display());
+            }
+        }
+
+        let _lock = self.lock()?;
+        for backup in self.backup_paths() {
+            let Ok(content) = fs::read(&backup) else {
+                continue;
+            };
+            let Ok(document) = self.parse(content.clone()) else {
+                continue;
+            };
+
+            let corrupted = if self.path.exists() {
+                let stamp = chrono::Local::now().format("%Y%m%d%H%M%S");
+                let corrupted = sibling(&self.path, &format!("corrupt-{}", stamp));
+                fs::rename(&self.path, &corrupted)
+                    .context("Failed to move corrupted reminders file aside")?;
+                Some(corrupted)
             } else {
-                existing.push(reminder);
-                imported_count += 1;
+                None
+            };
+            write_atomic(&self.path, &content)?;
+
+            return Ok(Recovery {
+                backup,
+                corrupted,
+                count: document.reminders.len(),
+            });
+        }
+
+        bail!("No valid backup of {} found", self.path.display())
+    }
+}
+
+impl ReminderStore for JsonStore {
+    fn load(&self) -> Result<Vec<Reminder>> {
+        if !self.conflict_copies().is_empty() {
+            // Merges the copies in under the lock
+            self.transaction(Box::new(|_| Ok(false)))?;
+        }
+        Ok(self.index()?.to_vec())
+    }
+
+    /// A transaction replacing every reminder, so revisions and tombstones
+    /// record the change like any other. Also replaces a corrupted file.
+    fn save(&self, reminders: &[Reminder]) -> Result<()> {
+        let replace = Box::new(|index: &mut ReminderIndex| {
+            index.replace_all(reminders.to_vec());
+            Ok(true)
+        });
+        self.commit(None, replace, true)?;
+        Ok(())
+    }
+
+    fn transaction(&self, mutation: Mutation) -> Result<()> {
+        self.commit(None, mutation, false)?;
+        Ok(())
+    }
+
+    fn merge(&self, other: Document, finish: Option<Finish>) -> Result<MergeReport> {
+        let finish = finish.unwrap_or_else(|| Box::new(|_| Ok(false)));
+        self.commit(Some(other), finish, false)
+    }
+
+    fn path(&self) -> Option<&Path> {
+        Some(&self.path)
+    }
+
+    fn vault(&self) -> Option<Arc<Vault>> {
+        Some(self.vault.clone())
+    }
+
+    fn index(&self) -> Result<Arc<ReminderIndex>> {
+        Ok(self.snapshot()?.0)
+    }
+}
+
+/// Result of [`JsonStore::recover`]
+#[derive(Debug)]
+pub struct Recovery {
+    /// Backup the reminders were restored from
+    pub backup: PathBuf,
+    /// Where the corrupted file was moved, if there was one
+    pub corrupted: Option<PathBuf>,
+    pub count: usize,
+}
+
+/// Whether `path` is a copy of the `store` file left by a sync tool after
+/// conflicting changes, such as Syncthing's
+/// `reminders.sync-conflict-<stamp>.json` or Dropbox's
+/// `reminders (<name>'s conflicted copy).json`
+pub fn is_conflict_copy(store: &Path, path: &Path) -> bool {
+    let (Some(stem), Some(extension), Some(name)) = (
+        store.file_stem().and_then(|s| s.to_str()),
+        store.extension().and_then(|e| e.to_str()),
+        path.file_name().and_then(|n| n.to_str()),
+    ) else {
+        return false;
+    };
+    Some(name) != store.file_name().and_then(|n| n.to_str())
+        && name.starts_with(stem)
+        && name.ends_with(&format!(".{}", extension))
+        && name.to_lowercase().contains("conflict")
+}
+
+fn encode(plaintext: Vec<u8>, vault: Option<&Vault>) -> Result<Vec<u8>> {
+    match vault {
+        Some(vault) => vault.encrypt(&plaintext),
+        None => Ok(plaintext),
+    }
+}
+
+/// `reminders.json` → `reminders.json.<suffix>`
+pub(crate) fn sibling(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(".");
+    name.push(suffix);
+    PathBuf::from(name)
+}
+
+/// Write `content` to a temp file in the same directory, fsync it and
+/// rename it over `path`, so `path` is never seen half-written
+pub fn write_atomic(path: &Path, content: &[u8]) -> Result<()> {
+    let tmp = sibling(path, &format!("tmp{}", std::process::id()));
+
+    let result = (|| {
+        let mut file = File::create(&tmp).context("Failed to create temp file")?;
+        file.write_all(content)
+            .context("Failed to write temp file")?;
+        file.sync_all().context("Failed to sync temp file")?;
+        fs::rename(&tmp, path).context("Failed to replace file")?;
+
+        // Persist the rename itself
+        #[cfg(unix)]
+        if let Some(dir) = path.parent().filter(|d| !d.as_os_str().is_empty()) {
+            File::open(dir)
+                .and_then(|d| d.sync_all())
+                .context("Failed to sync directory")?;
+        }
+        Ok(())
+    })();
+
+    if result.is_err() {
+        let _ = fs::remove_file(&tmp);
+    }
+    result
+}
+
+/// On-disk format of the reminders store
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Backend {
+    Json,
+    Sqlite,
+}
+
+impl Backend {
+    /// Pick the backend from a file's extension, defaulting to JSON
+    pub fn for_path(path: &Path) -> Self {
+        match path.extension().and_then(|e| e.to_str()) {
+            Some("db" | "sqlite" | "sqlite3") => Backend::Sqlite,
+            _ => Backend::Json,
+        }
+    }
+
+    pub const fn file_name(self) -> &'static str {
+        match self {
+            Backend::Json => JSON_FILE,
+            Backend::Sqlite => SQLITE_FILE,
+        }
+    }
+
+    /// Open the store of this format at `path`
+    pub fn open(self, path: impl Into<PathBuf>) -> Arc<dyn ReminderStore> {
+        match self {
+            Backend::Json => Arc::new(JsonStore::new(path)),
+            Backend::Sqlite => Arc::new(SqliteStore::new(path)),
+        }
+    }
+}
+
+impl FromStr for Backend {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_lowercase().as_str() {
+            "json" => Ok(Backend::Json),
+            "sqlite" => Ok(Backend::Sqlite),
+            other => bail!(
+                "Unknown storage backend '{}': expected json or sqlite",
+                other
+            ),
+        }
+    }
+}
+
+impl std::fmt::Display for Backend {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Backend::Json => write!(f, "json"),
+            Backend::Sqlite => write!(f, "sqlite"),
+        }
+    }
+}
+
+/// The reminders file in use, opened with the backend matching its format.
+/// Cheap to clone; clones share the underlying store.
+///
+/// Every change made through it numbers new reminders and keeps numbers
+/// and aliases unique; see [`Names::settle`]. Each change is also recorded
+/// in the event log next to the file.
+#[derive(Clone)]
+pub struct Storage {
+    path: PathBuf,
+    backend: Backend,
+    store: Arc<dyn ReminderStore>,
+    events: EventLog,
+    /// Shared by the store and the log, so the key is looked up once
+    vault: Arc<Vault>,
+}
+
+impl Storage {
+    /// The reminders file in the data directory
+    pub fn new() -> Result<Self> {
+        let data_dir = paths::data_dir()?;
+
+        // A migrated database takes precedence over the JSON file
+        let sqlite = data_dir.join(SQLITE_FILE);
+        if sqlite.exists() {
+            return Ok(Self::with_path(sqlite));
+        }
+        Ok(Self::with_path(data_dir.join(JSON_FILE)))
+    }
+
+    /// Use a specific reminders file instead of the default data directory.
+    /// `.db`, `.sqlite` and `.sqlite3` files use the SQLite backend. A JSON
+    /// file in a directory set up with `reminder sync init` commits every
+    /// change.
+    pub fn with_path(path: impl Into<PathBuf>) -> Self {
+        Self::with_vault(path, Arc::new(Vault::default()))
+    }
+
+    /// [`Storage::with_path`] decrypting with `vault` instead of the
+    /// configured key
+    pub fn with_vault(path: impl Into<PathBuf>, vault: Arc<Vault>) -> Self {
+        let path = path.into();
+        let backend = Backend::for_path(&path);
+        let mut store: Arc<dyn ReminderStore> = match backend {
+            Backend::Json => Arc::new(JsonStore::with_vault(&path, vault.clone())),
+            Backend::Sqlite => Arc::new(SqliteStore::new(&path)),
+        };
+        if backend == Backend::Json {
+            if let Some(repo) = path.parent().and_then(GitRepo::open) {
+                store = Arc::new(GitStore::new(store, repo));
+            }
+        }
+        Self {
+            store,
+            events: EventLog::for_store(&path, vault.clone()),
+            vault,
+            path,
+            backend,
+        }
+    }
+
+    /// The git repository the store is synced through, if any
+    pub fn repo(&self) -> Option<GitRepo> {
+        match self.backend {
+            Backend::Json => self.path.parent().and_then(GitRepo::open),
+            Backend::Sqlite => None,
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn backend(&self) -> Backend {
+        self.backend
+    }
+
+    /// Every change made through this storage
+    pub fn events(&self) -> &EventLog {
+        &self.events
+    }
+
+    /// Apply `mutation` to the reminders in `scope`, keeping names unique
+    /// and logging what changed. A new log starts with the reminders
+    /// already there.
+    fn logged(
+        &self,
+        cause: Cause,
+        scope: Scope,
+        mutation: Mutation,
+        finish: Option<Finish>,
+    ) -> Result<()> {
+        self.start_log()?;
+        self.store
+            .apply(scope, mutation, Some(self.settle_and_log(cause, finish)))
+    }
+
+    /// Run `finish`, then settle names and log the change, all under the
+    /// store's lock
+    fn settle_and_log<'a>(&'a self, cause: Cause, finish: Option<Finish<'a>>) -> Finish<'a> {
+        Box::new(move |index: &mut ReminderIndex| {
+            let finished = match finish {
+                Some(finish) => finish(index)?,
+                None => false,
+            };
+            let settled = Names::settle_changes(index)?;
+            if index.has_changes() {
+                // Written under the store's lock, so the log has changes
+                // in the order they were saved
+                let (before, after) = index.changes();
+                self.events.append(&events::diff(&before, &after, cause))?;
             }
+            Ok(finished || settled)
+        })
+    }
+
+    fn start_log(&self) -> Result<()> {
+        if !self.events.exists() {
+            self.events.append(&events::existing(&self.store.load()?))?;
+        }
+        Ok(())
+    }
+
+    /// Log changes made to the store by other means since it held
+    /// `before`, such as reminders pulled in by `reminder sync`
+    pub fn log_changes(&self, before: &[Reminder]) -> Result<()> {
+        let after = self.store.load()?;
+        self.events
+            .append(&events::diff(before, &after, Cause::Edit))
+    }
+
+    /// Replace the reminders with those rebuilt from the event log.
+    /// Returns the number of reminders.
+    pub fn rebuild(&self) -> Result<usize> {
+        if !self.events.exists() {
+            bail!("No event log at {}", self.events.path().display());
+        }
+        let reminders = events::replay(&self.events.read()?)?;
+        self.store.save(&reminders)?;
+        Ok(reminders.len())
+    }
+
+    /// This storage for code that works with any store, such as the
+    /// notification actions, so their changes are logged too
+    pub fn shared(&self) -> Arc<dyn ReminderStore> {
+        Arc::new(self.clone())
+    }
+
+    /// Copy every reminder into a store of the other format next to this
+    /// one and move this file aside as `<name>.migrated`. Returns the new
+    /// storage and the number of reminders copied.
+    pub fn migrate(&self, to: Backend) -> Result<(Storage, usize)> {
+        if self.backend == to {
+            bail!("Reminders are already stored as {}", to);
+        }
+
+        if self.is_encrypted() {
+            bail!("Encrypted stores cannot be migrated; run `reminder storage decrypt` first");
+        }
+
+        let target_path = self.path.with_file_name(to.file_name());
+        if target_path.exists() {
+            bail!(
+                "{} already exists; move it away before migrating",
+                target_path.display()
+            );
+        }
+
+        let reminders = self.load()?;
+        let last_number = self.store.index()?.max_number();
+        // Saved past the log, which both stores share
+        let target = Storage::with_path(&target_path);
+        target.store.save(&reminders)?;
+        target.store.transaction(Box::new(|index| {
+            index.set_last_number(last_number);
+            Ok(true)
+        }))?;
+
+        if self.path.exists() {
+            let mut migrated = self.path.clone().into_os_string();
+            migrated.push(".migrated");
+            fs::rename(&self.path, &migrated).context("Failed to move old reminders file")?;
+        }
+        let meta = sibling(&self.path, META_SUFFIX);
+        if meta.exists() {
+            fs::rename(&meta, sibling(&meta, "migrated"))
+                .context("Failed to move old tombstones file")?;
+        }
+
+        Ok((target, reminders.len()))
+    }
+
+    pub fn is_encrypted(&self) -> bool {
+        self.backend == Backend::Json && JsonStore::new(&self.path).is_encrypted()
+    }
+
+    /// Encrypt the JSON store, its backups, the event log and the failed
+    /// deliveries with `vault`.
+    /// Returns the number of reminders.
+    pub fn encrypt(&self, vault: &Vault) -> Result<usize> {
+        if self.backend != Backend::Json {
+            bail!(
+                "Only JSON stores can be encrypted; run `reminder storage migrate --to json` first"
+            );
+        }
+        if self.is_encrypted() {
+            bail!("{} is already encrypted", self.path.display());
+        }
+        if self.repo().is_some() {
+            bail!("Stores synced with git cannot be encrypted, as they could no longer be merged");
+        }
+        let store = JsonStore::new(&self.path);
+        let count = store.convert(Some(vault))?;
+        self.convert_log(&store, Some(vault))?;
+        Ok(count)
+    }
+
+    /// Turn an encrypted JSON store, its backups, the event log and the
+    /// failed deliveries back into plaintext. Returns the number of reminders.
+    pub fn decrypt(&self) -> Result<usize> {
+        if !self.is_encrypted() {
+            bail!("{} is not encrypted", self.path.display());
         }
+        let store = JsonStore::with_vault(&self.path, self.vault.clone());
+        let count = store.convert(None)?;
+        self.convert_log(&store, None)?;
+        Ok(count)
+    }
+
+    /// Under the store's lock, which appends to the log also hold
+    fn convert_log(&self, store: &JsonStore, vault: Option<&Vault>) -> Result<()> {
+        let lock = store.lock()?;
+        self.events.convert(vault)?;
+        lock.unlock().context("Failed to release lock")?;
+        DeadLetterQueue::for_storage(self).convert(vault)?;
+        Ok(())
+    }
+
+    /// Restore a corrupted JSON store from its newest valid backup
+    pub fn recover(&self) -> Result<Recovery> {
+        match self.backend {
+            Backend::Json => JsonStore::new(&self.path).recover(),
+            Backend::Sqlite => {
+                bail!("Backups are only kept for JSON stores; SQLite recovers from its own journal")
+            }
+        }
+    }
+}
+
+impl ReminderStore for Storage {
+    fn load(&self) -> Result<Vec<Reminder>> {
+        if self.backend == Backend::Json && !JsonStore::new(&self.path).conflict_copies().is_empty()
+        {
+            // Merges the copies in through the log
+            self.transaction(Box::new(|_| Ok(false)))?;
+        }
+        self.store.load()
+    }
+
+    fn save(&self, reminders: &[Reminder]) -> Result<()> {
+        let replace = Box::new(|index: &mut ReminderIndex| {
+            index.replace_all(reminders.to_vec());
+            Ok(true)
+        });
+        self.transaction(replace)
+    }
+
+    fn transaction(&self, mutation: Mutation) -> Result<()> {
+        self.apply(Scope::All, mutation, None)
+    }
+
+    fn apply(&self, scope: Scope, mutation: Mutation, finish: Option<Finish>) -> Result<()> {
+        self.logged(Cause::Edit, scope, mutation, finish)
+    }
+
+    fn path(&self) -> Option<&Path> {
+        Some(&self.path)
+    }
+
+    fn vault(&self) -> Option<Arc<Vault>> {
+        Some(self.vault.clone())
+    }
+
+    fn index(&self) -> Result<Arc<ReminderIndex>> {
+        self.store.index()
+    }
+
+    fn get(&self, id: Uuid) -> Result<Option<Reminder>> {
+        self.store.get(id)
+    }
+
+    fn find_by_short_id(&self, short_id: &str) -> Result<Option<Reminder>> {
+        self.store.find_by_short_id(short_id)
+    }
+
+    fn filter_by_tag(&self, tag: &str) -> Result<Vec<Reminder>> {
+        self.store.filter_by_tag(tag)
+    }
+
+    fn get_all_tags(&self) -> Result<Vec<String>> {
+        self.store.get_all_tags()
+    }
+
+    fn tag_counts(&self) -> Result<Vec<(String, usize)>> {
+        self.store.tag_counts()
+    }
+
+    fn due_reminders(&self) -> Result<Vec<Reminder>> {
+        self.store.due_reminders()
+    }
+
+    fn update_due(&self, updater: &mut dyn FnMut(&mut Reminder)) -> Result<usize> {
+        let mut count = 0;
+        let now = Local::now();
+        let update = Box::new(|index: &mut ReminderIndex| {
+            count = update_due_in(index, now, updater);
+            Ok(count > 0)
+        });
+        self.logged(Cause::Fire, Scope::Due(now), update, None)?;
+        Ok(count)
+    }
+
+    fn import_from_file(&self, path: &Path, overwrite: bool) -> Result<(usize, usize)> {
+        let imported = read_import(path)?;
+        let scope = Scope::Ids(imported.iter().map(|r| r.id).collect());
+        let mut counts = (0, 0);
+        let import = Box::new(|index: &mut ReminderIndex| {
+            counts = import_into(index, imported, overwrite);
+            Ok(true)
+        });
+        self.logged(Cause::Import, scope, import, None)?;
+        Ok(counts)
+    }
 
-        self.save(&existing)?;
-        Ok((imported_count, skipped_count))
+    /// Numbers both copies gave to different reminders are sorted out in
+    /// the same transaction, and the merge is logged as one
+    fn merge(&self, other: Document, finish: Option<Finish>) -> Result<MergeReport> {
+        self.start_log()?;
+        self.store
+            .merge(other, Some(self.settle_and_log(Cause::Merge, finish)))
     }
 }
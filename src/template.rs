@@ -0,0 +1,265 @@
+use crate::config::Config;
+use crate::reminder::Reminder;
+use chrono::{DateTime, Local};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt::Write;
+
+pub const DEFAULT_SUMMARY_TEMPLATE: &str = "{title}";
+pub const DEFAULT_BODY_TEMPLATE: &str = "{description}";
+const DEFAULT_DATE_FORMAT: &str = "%Y-%m-%d %H:%M";
+
+/// Summary and body templates; unset fields fall back to the global
+/// `[template]` config and then to the title and description
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct MessageTemplate {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub summary: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub body: Option<String>,
+}
+
+impl MessageTemplate {
+    pub fn is_empty(&self) -> bool {
+        self.summary.is_none() && self.body.is_none()
+    }
+}
+
+/// Notification text shared by every delivery channel
+#[derive(Debug, Clone, PartialEq)]
+pub struct Message {
+    pub summary: String,
+    pub body: String,
+}
+
+impl Message {
+    /// Render the reminder's templates, falling back to the config file's
+    pub fn render(reminder: &Reminder, config: &Config) -> Self {
+        let placeholders = Placeholders::new(reminder);
+        let summary = reminder
+            .template
+            .summary
+            .as_deref()
+            .or(config.template.summary.as_deref())
+            .unwrap_or(DEFAULT_SUMMARY_TEMPLATE);
+        let body = reminder
+            .template
+            .body
+            .as_deref()
+            .or(config.template.body.as_deref())
+            .unwrap_or(DEFAULT_BODY_TEMPLATE);
+
+        Self {
+            summary: placeholders.expand(summary),
+            body: placeholders.expand(body),
+        }
+    }
+
+    /// Summary and body on one line
+    pub fn one_line(&self) -> String {
+        if self.body.is_empty() {
+            self.summary.clone()
+        } else {
+            format!("{} - {}", self.summary, self.body)
+        }
+    }
+}
+
+/// JSON event describing a fired reminder, sent by webhooks and MQTT
+pub fn fired_event(reminder: &Reminder, message: &Message) -> serde_json::Value {
+    let mut tags: Vec<&String> = reminder.tags.iter().collect();
+    tags.sort();
+
+    serde_json::json!({
+        "event": "reminder.fired",
+        "id": reminder.id,
+        "title": reminder.title,
+        "description": reminder.description,
+        "summary": message.summary,
+        "body": message.body,
+        "tags": tags,
+        "scheduled_time": reminder.next_trigger.map(|t| t.to_rfc3339()),
+        "occurrence_number": reminder.occurrences.max(1),
+        "fired_at": Local::now().to_rfc3339(),
+    })
+}
+
+/// Values substituted for `{name}` and `{name:<strftime format>}` in templates.
+/// Unknown placeholders are left as written.
+pub struct Placeholders<'a> {
+    reminder: &'a Reminder,
+    now: DateTime<Local>,
+    extra: BTreeMap<&'static str, String>,
+}
+
+impl<'a> Placeholders<'a> {
+    pub fn new(reminder: &'a Reminder) -> Self {
+        Self {
+            reminder,
+            now: Local::now(),
+            extra: BTreeMap::new(),
+        }
+    }
+
+    /// Add a placeholder, overriding the reminder's own when used without
+    /// a format
+    pub fn with(mut self, name: &'static str, value: impl Into<String>) -> Self {
+        self.extra.insert(name, value.into());
+        self
+    }
+
+    pub fn expand(&self, template: &str) -> String {
+        self.expand_with(template, |value| value.to_string())
+    }
+
+    /// Expand, passing every substituted value through `escape`
+    pub fn expand_with(&self, template: &str, escape: impl Fn(&str) -> String) -> String {
+        let mut output = String::with_capacity(template.len());
+        let mut rest = template;
+
+        while let Some(start) = rest.find('{') {
+            let after = &rest[start + 1..];
+            let Some(end) = after.find('}') else {
+                break;
+            };
+            output.push_str(&rest[..start]);
+
+            let placeholder = &after[..end];
+            let (name, format) = match placeholder.split_once(':') {
+                Some((name, format)) => (name, Some(format)),
+                None => (placeholder, None),
+            };
+            match self.value(name, format) {
+                Some(value) => {
+                    output.push_str(&escape(&value));
+                    rest = &after[end + 1..];
+                }
+                None => {
+                    // Not a placeholder (e.g. a JSON brace); keep scanning after it
+                    output.push('{');
+                    rest = after;
+                }
+            }
+        }
+
+        output.push_str(rest);
+        output
+    }
+
+    fn value(&self, name: &str, format: Option<&str>) -> Option<String> {
+        if let Some(value) = self.extra.get(name).filter(|_| format.is_none()) {
+            return Some(value.clone());
+        }
+
+        let reminder = self.reminder;
+        let value = match name {
+            "id" => reminder.id.to_string(),
+            "short_id" => reminder.id.to_string()[..8].to_string(),
+            "title" => reminder.title.clone(),
+            "description" => reminder.description.clone().unwrap_or_default(),
+            "tags" => {
+                let mut tags: Vec<&str> = reminder.tags.iter().map(String::as_str).collect();
+                tags.sort();
+                tags.join(", ")
+            }
+            "scheduled_time" => match reminder.next_trigger {
+                Some(time) => format_date(time, format)?,
+                None => String::new(),
+            },
+            "now" => format_date(self.now, format)?,
+            "occurrence_number" => reminder.occurrences.max(1).to_string(),
+            "overdue_by" => match reminder.next_trigger {
+                Some(time) => format_duration(self.now - time),
+                None => String::new(),
+            },
+            _ => return None,
+        };
+        Some(value)
+    }
+}
+
+/// `None` for an invalid strftime format
+fn format_date(time: DateTime<Local>, format: Option<&str>) -> Option<String> {
+    let mut formatted = String::new();
+    write!(
+        formatted,
+        "{}",
+        time.format(format.unwrap_or(DEFAULT_DATE_FORMAT))
+    )
+    .ok()?;
+    Some(formatted)
+}
+
+/// Compact duration such as `45s`, `5m`, `1h 20m` or `2d 3h`; zero when negative
+pub fn format_duration(duration: chrono::Duration) -> String {
+    let secs = duration.num_seconds().max(0);
+    let (days, hours, minutes) = (secs / 86400, secs % 86400 / 3600, secs % 3600 / 60);
+
+    if days > 0 {
+        format!("{}d {}h", days, hours)
+    } else if hours > 0 {
+        format!("{}h {}m", hours, minutes)
+    } else if minutes > 0 {
+        format!("{}m", minutes)
+    } else {
+        format!("{}s", secs)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+    use std::collections::HashSet;
+
+    fn reminder() -> Reminder {
+        let time = Local.with_ymd_and_hms(2026, 3, 4, 9, 30, 0).unwrap();
+        let tags: HashSet<String> = ["work", "daily"].iter().map(|t| t.to_string()).collect();
+        let mut reminder = Reminder::new_one_time(
+            "Stand-up".to_string(),
+            Some("Room 4".to_string()),
+            time,
+            tags,
+        );
+        reminder.occurrences = 3;
+        reminder
+    }
+
+    #[test]
+    fn test_expand_placeholders() {
+        let reminder = reminder();
+        let placeholders = Placeholders::new(&reminder);
+
+        assert_eq!(
+            placeholders.expand(
+                "#{occurrence_number} {title} [{tags}] at {scheduled_time:%H:%M} ({description})"
+            ),
+            "#3 Stand-up [daily, work] at 09:30 (Room 4)"
+        );
+        assert_eq!(
+            placeholders.expand("{scheduled_time} {unknown} {title"),
+            "2026-03-04 09:30 {unknown} {title"
+        );
+    }
+
+    #[test]
+    fn test_reminder_template_overrides_config() {
+        let mut reminder = reminder();
+        reminder.template.summary = Some("⏰ {title}".to_string());
+        let mut config = Config::default();
+        config.template.summary = Some("ignored".to_string());
+        config.template.body = Some("{description} ({scheduled_time:%d/%m})".to_string());
+
+        let message = Message::render(&reminder, &config);
+        assert_eq!(message.summary, "⏰ Stand-up");
+        assert_eq!(message.body, "Room 4 (04/03)");
+    }
+
+    #[test]
+    fn test_format_duration() {
+        assert_eq!(format_duration(chrono::Duration::seconds(-5)), "0s");
+        assert_eq!(format_duration(chrono::Duration::seconds(45)), "45s");
+        assert_eq!(format_duration(chrono::Duration::minutes(80)), "1h 20m");
+        assert_eq!(format_duration(chrono::Duration::hours(51)), "2d 3h");
+    }
+}
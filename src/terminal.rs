@@ -0,0 +1,212 @@
+use crate::template::Message;
+use anyhow::{bail, Context, Result};
+use chrono::Local;
+use serde::{Deserialize, Serialize};
+use std::fs::OpenOptions;
+use std::io::{IsTerminal, Write};
+use std::path::PathBuf;
+use std::process::Command;
+
+/// How the `terminal` channel reaches the user
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TerminalConfig {
+    /// Write the reminder to every terminal the user is logged in on
+    #[serde(default = "default_true")]
+    pub wall: bool,
+
+    /// Ring the terminal bell
+    #[serde(default = "default_true")]
+    pub bell: bool,
+}
+
+impl Default for TerminalConfig {
+    fn default() -> Self {
+        Self {
+            wall: true,
+            bell: true,
+        }
+    }
+}
+
+fn default_true() -> bool {
+    true
+}
+
+/// Whether a desktop notification server can be reached. Only meaningful on
+/// Linux and the BSDs, where notifications go over the D-Bus session bus.
+pub fn desktop_session_available() -> bool {
+    #[cfg(all(unix, not(target_os = "macos")))]
+    {
+        if std::env::var_os("DBUS_SESSION_BUS_ADDRESS").is_some_and(|a| !a.is_empty()) {
+            return true;
+        }
+        std::env::var_os("XDG_RUNTIME_DIR")
+            .map(|dir| PathBuf::from(dir).join("bus").exists())
+            .unwrap_or(false)
+    }
+
+    #[cfg(not(all(unix, not(target_os = "macos"))))]
+    {
+        true
+    }
+}
+
+/// Terminal devices the current user is logged in on, as listed by `who`
+pub fn logged_in_ttys() -> Vec<PathBuf> {
+    let Some(user) = current_user() else {
+        return Vec::new();
+    };
+    let Ok(output) = Command::new("who").output() else {
+        return Vec::new();
+    };
+
+    let mut ttys: Vec<PathBuf> = Vec::new();
+    for line in String::from_utf8_lossy(&output.stdout).lines() {
+        let mut fields = line.split_whitespace();
+        if fields.next() != Some(user.as_str()) {
+            continue;
+        }
+        if let Some(tty) = fields.next() {
+            let path = PathBuf::from("/dev").join(tty);
+            if !ttys.contains(&path) {
+                ttys.push(path);
+            }
+        }
+    }
+    ttys
+}
+
+pub(crate) fn current_user() -> Option<String> {
+    std::env::var("USER")
+        .or_else(|_| std::env::var("LOGNAME"))
+        .ok()
+        .filter(|u| !u.is_empty())
+        .or_else(|| {
+            let output = Command::new("whoami").output().ok()?;
+            let user = String::from_utf8_lossy(&output.stdout).trim().to_string();
+            (!user.is_empty()).then_some(user)
+        })
+}
+
+/// Text written to a terminal for a fired reminder
+pub fn format_message(message: &Message, config: &TerminalConfig) -> String {
+    let mut text = String::new();
+    if config.bell {
+        text.push('\x07');
+    }
+    if config.wall {
+        text.push_str(&format!(
+            "\r\nReminder at {}:\r\n⏰ {}\r\n",
+            Local::now().format("%H:%M"),
+            printable(&message.one_line())
+        ));
+    }
+    text
+}
+
+/// `text` without control characters other than newlines and tabs, so a
+/// reminder cannot send escape sequences to someone else's terminal
+fn printable(text: &str) -> String {
+    text.chars()
+        .filter(|&c| !c.is_control() || c == '\n' || c == '\t')
+        .collect()
+}
+
+/// Write the reminder to `ttys`, or to standard output when there are none
+/// and it is a terminal. Fails only if nothing could be written anywhere.
+pub fn write_to_terminals(
+    message: &Message,
+    config: &TerminalConfig,
+    ttys: &[PathBuf],
+) -> Result<()> {
+    let text = format_message(message, config);
+    if text.is_empty() {
+        bail!("Both wall and bell are disabled for the terminal channel");
+    }
+
+    if ttys.is_empty() {
+        let mut stdout = std::io::stdout();
+        if !stdout.is_terminal() {
+            bail!("No logged-in terminals found");
+        }
+        stdout.write_all(text.as_bytes())?;
+        stdout.flush()?;
+        return Ok(());
+    }
+
+    let mut last_error = None;
+    let mut written = 0;
+    for tty in ttys {
+        let result = OpenOptions::new()
+            .append(true)
+            .open(tty)
+            .and_then(|mut file| file.write_all(text.as_bytes()))
+            .with_context(|| format!("Failed to write to {}", tty.display()));
+        match result {
+            Ok(()) => written += 1,
+            Err(e) => last_error = Some(e),
+        }
+    }
+
+    match last_error {
+        Some(e) if written == 0 => Err(e),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn message() -> Message {
+        Message {
+            summary: "Deploy".to_string(),
+            body: "Window closes at 6".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_bell_only() {
+        let config = TerminalConfig {
+            wall: false,
+            bell: true,
+        };
+        assert_eq!(format_message(&message(), &config), "\x07");
+    }
+
+    #[test]
+    fn test_strips_control_characters() {
+        let message = Message {
+            summary: "Deploy\x1b]0;owned\x07\x1b[2J".to_string(),
+            body: "line one\nline\ttwo\u{9b}".to_string(),
+        };
+        let config = TerminalConfig {
+            wall: true,
+            bell: false,
+        };
+        let text = format_message(&message, &config);
+        assert!(!text.contains('\x1b') && !text.contains('\x07') && !text.contains('\u{9b}'));
+        assert!(text.contains("Deploy]0;owned[2J - line one\nline\ttwo"));
+    }
+
+    #[test]
+    fn test_writes_to_every_tty() {
+        let dir = tempfile::tempdir().unwrap();
+        let ttys = vec![dir.path().join("pts1"), dir.path().join("pts2")];
+        for tty in &ttys {
+            std::fs::write(tty, "").unwrap();
+        }
+        let missing = dir.path().join("missing").join("pts3");
+
+        let mut all = ttys.clone();
+        all.push(missing);
+        write_to_terminals(&message(), &TerminalConfig::default(), &all).unwrap();
+
+        for tty in &ttys {
+            let written = std::fs::read_to_string(tty).unwrap();
+            assert!(written.starts_with('\x07'));
+            assert!(written.contains("⏰ Deploy - Window closes at 6"));
+        }
+    }
+}
@@ -1,12 +1,53 @@
 use anyhow::{bail, Result};
 use chrono::{DateTime, Datelike, Duration, Local, NaiveDateTime, NaiveTime, Weekday};
 use regex::Regex;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+/// `[time]` settings
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TimeConfig {
+    /// Time of day for dates given without one, like "tomorrow"
+    #[serde(
+        serialize_with = "serialize_clock",
+        deserialize_with = "deserialize_clock"
+    )]
+    pub default_time: NaiveTime,
+}
+
+impl Default for TimeConfig {
+    fn default() -> Self {
+        Self {
+            default_time: NaiveTime::from_hms_opt(9, 0, 0).unwrap(),
+        }
+    }
+}
+
+fn serialize_clock<S: Serializer>(time: &NaiveTime, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(&time.format("%H:%M").to_string())
+}
+
+fn deserialize_clock<'de, D: Deserializer<'de>>(deserializer: D) -> Result<NaiveTime, D::Error> {
+    let value = String::deserialize(deserializer)?;
+    NaiveTime::parse_from_str(&value, "%H:%M").map_err(|_| {
+        serde::de::Error::custom(format!(
+            "invalid time of day \"{}\", expected HH:MM such as \"09:00\"",
+            value
+        ))
+    })
+}
 
 /// Parse time string supporting multiple formats:
 /// - Absolute: "2025-12-25 10:00"
 /// - Relative: "30m", "2h", "1d", "1w"
 /// - Natural: "tomorrow 9am", "next monday 14:00", "today 18:30"
 pub fn parse_time(input: &str) -> Result<DateTime<Local>> {
+    parse_time_with(input, TimeConfig::default().default_time)
+}
+
+/// Like `parse_time`, with dates given without a time of day falling at
+/// `default_time`
+pub fn parse_time_with(input: &str, default_time: NaiveTime) -> Result<DateTime<Local>> {
     let input = input.trim().to_lowercase();
 
     // Try absolute format first
@@ -20,7 +61,7 @@ pub fn parse_time(input: &str) -> Result<DateTime<Local>> {
     }
 
     // Try natural language
-    if let Ok(dt) = parse_natural(&input) {
+    if let Ok(dt) = parse_natural(&input, default_time) {
         return Ok(dt);
     }
 
@@ -63,7 +104,7 @@ fn parse_relative(input: &str) -> Result<DateTime<Local>> {
     bail!("Not a relative time format")
 }
 
-fn parse_natural(input: &str) -> Result<DateTime<Local>> {
+fn parse_natural(input: &str, default_time: NaiveTime) -> Result<DateTime<Local>> {
     let now = Local::now();
     let today = now.date_naive();
 
@@ -105,22 +146,18 @@ fn parse_natural(input: &str) -> Result<DateTime<Local>> {
 
     // Parse the time part
     let target_time = if time_part.is_empty() {
-        NaiveTime::from_hms_opt(9, 0, 0).unwrap() // Default to 9:00 AM
+        default_time
     } else if let Some(caps) = time_re.captures(time_part) {
         let mut hour: u32 = caps[1].parse()?;
         let minute: u32 = caps.get(2).map(|m| m.as_str().parse().unwrap()).unwrap_or(0);
         let ampm = caps.get(3).map(|m| m.as_str());
 
         match ampm {
-            Some("am") => {
-                if hour == 12 {
-                    hour = 0;
-                }
+            Some("am") if hour == 12 => {
+                hour = 0;
             }
-            Some("pm") => {
-                if hour != 12 {
-                    hour += 12;
-                }
+            Some("pm") if hour != 12 => {
+                hour += 12;
             }
             _ => {
                 // 24-hour format or unknown, no change needed
@@ -192,4 +229,17 @@ mod tests {
         let result = parse_time("next monday 14:00");
         assert!(result.is_ok());
     }
+
+    #[test]
+    fn test_default_time_of_day() {
+        let evening = NaiveTime::from_hms_opt(18, 30, 0).unwrap();
+        assert_eq!(
+            parse_time_with("tomorrow", evening).unwrap().time(),
+            evening
+        );
+        assert_eq!(
+            parse_time("tomorrow").unwrap().time(),
+            NaiveTime::from_hms_opt(9, 0, 0).unwrap()
+        );
+    }
 }
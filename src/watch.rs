@@ -0,0 +1,106 @@
+use anyhow::{Context, Result};
+use notify::event::ModifyKind;
+use notify::{Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use std::collections::BTreeSet;
+use std::path::Path;
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError};
+use std::thread;
+use std::time::Duration;
+
+/// Events this close together are taken as one change, so a save (temp
+/// file, rename, backups) wakes the daemon once
+const SETTLE: Duration = Duration::from_millis(50);
+
+/// Wakes a waiting thread when files change, using inotify, FSEvents or
+/// whatever the platform offers. Directories are watched rather than the
+/// files themselves, as saves replace files by renaming over them.
+pub struct FileWatcher {
+    _watcher: RecommendedWatcher,
+    changes: Receiver<()>,
+}
+
+impl FileWatcher {
+    /// Watch `dirs` for changes to files `relevant` accepts. Paths passed
+    /// to it are inside the canonical form of one of `dirs`.
+    pub fn new<'a>(
+        dirs: impl IntoIterator<Item = &'a Path>,
+        relevant: impl Fn(&Path) -> bool + Send + 'static,
+    ) -> Result<Self> {
+        let (sender, changes) = mpsc::channel();
+        let mut watcher = notify::recommended_watcher(move |event: notify::Result<Event>| {
+            let wake = match event {
+                Ok(event) => matters(&event.kind) && event.paths.iter().any(|path| relevant(path)),
+                // Events may have been lost; have the owner look again
+                Err(_) => true,
+            };
+            if wake {
+                let _ = sender.send(());
+            }
+        })
+        .context("Failed to start file watcher")?;
+
+        let dirs: BTreeSet<_> = dirs
+            .into_iter()
+            .filter_map(|dir| dir.canonicalize().ok())
+            .collect();
+        for dir in dirs {
+            watcher
+                .watch(&dir, RecursiveMode::NonRecursive)
+                .with_context(|| format!("Failed to watch {}", dir.display()))?;
+        }
+
+        Ok(Self {
+            _watcher: watcher,
+            changes,
+        })
+    }
+
+    /// Block until a relevant file changes or `timeout` passes. Returns
+    /// whether anything changed.
+    pub fn wait(&self, timeout: Duration) -> bool {
+        match self.changes.recv_timeout(timeout) {
+            Ok(()) => {
+                while self.changes.recv_timeout(SETTLE).is_ok() {}
+                true
+            }
+            Err(RecvTimeoutError::Timeout) => false,
+            Err(RecvTimeoutError::Disconnected) => {
+                thread::sleep(timeout);
+                true
+            }
+        }
+    }
+}
+
+fn matters(kind: &EventKind) -> bool {
+    match kind {
+        EventKind::Create(_) | EventKind::Remove(_) => true,
+        EventKind::Modify(ModifyKind::Metadata(_)) => false,
+        EventKind::Modify(_) => true,
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn test_wakes_on_relevant_changes_only() {
+        let dir = tempfile::tempdir().unwrap();
+        let watched = dir.path().canonicalize().unwrap().join("reminders.json");
+        let target = watched.clone();
+        let watcher = FileWatcher::new([dir.path()], move |path| path == target).unwrap();
+
+        fs::write(dir.path().join("other.txt"), "x").unwrap();
+        assert!(!watcher.wait(Duration::from_millis(200)));
+
+        // Saves write a temp file and rename it into place
+        let tmp = dir.path().join("reminders.json.tmp1");
+        fs::write(&tmp, "[]").unwrap();
+        fs::rename(&tmp, &watched).unwrap();
+        assert!(watcher.wait(Duration::from_secs(5)));
+        assert!(!watcher.wait(Duration::from_millis(200)));
+    }
+}
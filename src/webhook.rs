@@ -0,0 +1,200 @@
+use crate::config::Config;
+use crate::notifier::FailedTargets;
+use crate::reminder::Reminder;
+use crate::template::{fired_event, Message, Placeholders};
+use crate::{log_error, log_info};
+use anyhow::{bail, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+pub const DEFAULT_WEBHOOK_TIMEOUT_SECS: u64 = 10;
+
+/// Where and how to deliver a fired reminder over HTTP
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct WebhookConfig {
+    #[serde(default)]
+    pub url: String,
+
+    #[serde(default = "default_method")]
+    pub method: String,
+
+    #[serde(default)]
+    pub headers: BTreeMap<String, String>,
+
+    /// JSON body using the notification template placeholders plus
+    /// `{summary}` and `{body}`; the built-in payload when unset
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub template: Option<String>,
+
+    #[serde(default = "default_timeout")]
+    pub timeout_secs: u64,
+}
+
+impl Default for WebhookConfig {
+    fn default() -> Self {
+        Self {
+            url: String::new(),
+            method: default_method(),
+            headers: BTreeMap::new(),
+            template: None,
+            timeout_secs: default_timeout(),
+        }
+    }
+}
+
+fn default_method() -> String {
+    "POST".to_string()
+}
+
+fn default_timeout() -> u64 {
+    DEFAULT_WEBHOOK_TIMEOUT_SECS
+}
+
+/// Webhooks for a reminder: its own `--webhook` URL if trusted here (using
+/// the global `[webhook]` settings) followed by those of its tags
+pub fn webhooks_for(reminder: &Reminder, config: &Config) -> Vec<WebhookConfig> {
+    let mut webhooks = Vec::new();
+
+    if let Some(url) = config.trusted_webhook(reminder) {
+        webhooks.push(WebhookConfig {
+            url: url.clone(),
+            ..config.webhook.clone()
+        });
+    }
+
+    let mut tags: Vec<&String> = reminder.tags.iter().collect();
+    tags.sort();
+    for tag in tags {
+        if let Some(webhook) = config.tags.get(tag).and_then(|t| t.webhook.as_ref()) {
+            if !webhooks.iter().any(|w| w.url == webhook.url) {
+                webhooks.push(webhook.clone());
+            }
+        }
+    }
+
+    webhooks
+}
+
+/// Deliver the reminder to all of its webhooks; fails with
+/// [`FailedTargets`] naming the URLs that failed
+pub fn deliver_all(reminder: &Reminder, config: &Config) -> Result<()> {
+    let webhooks = webhooks_for(reminder, config);
+    let total = webhooks.len();
+    let mut failed = Vec::new();
+
+    for webhook in webhooks {
+        match deliver(&webhook, reminder, config) {
+            Ok(()) => log_info!("Webhook delivered to {}", webhook.url),
+            Err(e) => {
+                log_error!("Webhook to {} failed: {:#}", webhook.url, e);
+                failed.push((webhook.url, format!("{:#}", e)));
+            }
+        }
+    }
+
+    if !failed.is_empty() {
+        return Err(FailedTargets {
+            kind: "webhook",
+            failed,
+            total,
+        }
+        .into());
+    }
+    Ok(())
+}
+
+/// Deliver the reminder to its webhook at `url` only, as a retry of one
+/// that failed
+pub fn deliver_to(reminder: &Reminder, config: &Config, url: &str) -> Result<()> {
+    let Some(webhook) = webhooks_for(reminder, config)
+        .into_iter()
+        .find(|w| w.url == url)
+    else {
+        bail!("Webhook to {} is no longer configured", url);
+    };
+    deliver(&webhook, reminder, config)?;
+    log_info!("Webhook delivered to {}", webhook.url);
+    Ok(())
+}
+
+/// Send the reminder to one webhook. Failures are not retried here: the
+/// daemon's failed delivery queue retries them with the `[retry]` backoff.
+pub fn deliver(webhook: &WebhookConfig, reminder: &Reminder, config: &Config) -> Result<()> {
+    let body = render_payload(webhook, reminder, &Message::render(reminder, config))?;
+    let agent = ureq::AgentBuilder::new()
+        .timeout(Duration::from_secs(webhook.timeout_secs))
+        .build();
+    send(&agent, webhook, &body)
+}
+
+fn send(agent: &ureq::Agent, webhook: &WebhookConfig, body: &str) -> Result<()> {
+    let mut request = agent
+        .request(&webhook.method.to_uppercase(), &webhook.url)
+        .set("Content-Type", "application/json");
+    for (name, value) in &webhook.headers {
+        request = request.set(name, value);
+    }
+
+    match request.send_string(body) {
+        Ok(_) => Ok(()),
+        Err(ureq::Error::Status(code, response)) => {
+            let text = response.into_string().unwrap_or_default();
+            bail!("HTTP {}: {}", code, text.trim())
+        }
+        Err(e) => Err(e.into()),
+    }
+}
+
+fn render_payload(
+    webhook: &WebhookConfig,
+    reminder: &Reminder,
+    message: &Message,
+) -> Result<String> {
+    let Some(template) = &webhook.template else {
+        return Ok(fired_event(reminder, message).to_string());
+    };
+
+    // Placeholders sit inside JSON strings, so substitute escaped contents
+    let escape = |value: &str| {
+        let quoted = serde_json::to_string(value).unwrap_or_default();
+        quoted[1..quoted.len() - 1].to_string()
+    };
+    let scheduled_time = reminder
+        .next_trigger
+        .map(|t| t.to_rfc3339())
+        .unwrap_or_default();
+
+    Ok(Placeholders::new(reminder)
+        .with("summary", message.summary.clone())
+        .with("body", message.body.clone())
+        .with("scheduled_time", scheduled_time)
+        .expand_with(template, escape))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Local;
+    use std::collections::HashSet;
+
+    #[test]
+    fn test_template_escapes_values() {
+        let webhook = WebhookConfig {
+            template: Some(r#"{"text": "⏰ {title}: {description}"}"#.to_string()),
+            ..Default::default()
+        };
+        let reminder = Reminder::new_one_time(
+            "Say \"hi\"".to_string(),
+            Some("line1\nline2".to_string()),
+            Local::now(),
+            HashSet::new(),
+        );
+
+        let message = Message::render(&reminder, &Config::default());
+        let body = render_payload(&webhook, &reminder, &message).unwrap();
+        let value: serde_json::Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(value["text"], "⏰ Say \"hi\": line1\nline2");
+    }
+}
@@ -0,0 +1,122 @@
+//! Snapshots of the data directory: creating, restoring and the daemon's
+//! daily backup.
+
+mod common;
+
+use common::{isolate, reminder};
+use reminder_cli::backup::{BackupConfig, Backups};
+use reminder_cli::daemon::run_daemon_until;
+use reminder_cli::events::EventKind;
+use reminder_cli::notifier::NotifierRegistry;
+use reminder_cli::paths;
+use reminder_cli::storage::{ReminderStore, Storage};
+use std::fs;
+use std::time::Instant;
+
+fn titles(storage: &Storage) -> Vec<String> {
+    storage
+        .load()
+        .unwrap()
+        .into_iter()
+        .map(|r| r.title)
+        .collect()
+}
+
+#[test]
+fn restores_store_and_config_keeping_history() {
+    isolate();
+    let dir = tempfile::tempdir().unwrap();
+    let config_file = dir.path().join("config").join("config.toml");
+    fs::create_dir_all(config_file.parent().unwrap()).unwrap();
+    fs::write(&config_file, "[time]\ndefault_time = \"08:00\"\n").unwrap();
+
+    let storage = Storage::with_path(dir.path().join("reminders.json"));
+    storage.add(reminder("Kept")).unwrap();
+    let backups = Backups::new(storage.path(), &config_file, &BackupConfig::default());
+    let snapshot = backups.create().unwrap();
+    assert_eq!(
+        snapshot.files().unwrap(),
+        [
+            "data/reminders.json",
+            "data/reminders.json.meta",
+            "data/history.jsonl",
+            "config/config.toml"
+        ]
+    );
+
+    storage.add(reminder("Added later")).unwrap();
+    fs::write(&config_file, "[time]\ndefault_time = \"10:00\"\n").unwrap();
+    let events = storage.events().read().unwrap().len();
+
+    let restore = backups.restore(&backups.find("latest").unwrap()).unwrap();
+    assert_eq!(restore.files.len(), 3);
+    assert_eq!(titles(&storage), ["Kept"]);
+    assert!(fs::read_to_string(&config_file).unwrap().contains("08:00"));
+
+    // The log keeps its history and records the restore
+    let log = storage.events().read().unwrap();
+    assert_eq!(log.len(), events + 1);
+    assert_eq!(log.last().unwrap().kind, EventKind::Deleted);
+    storage.rebuild().unwrap();
+    assert_eq!(titles(&storage), ["Kept"]);
+
+    // The state before the restore can be brought back in turn
+    assert_eq!(backups.list().unwrap().len(), 2);
+    backups.restore(&restore.safety).unwrap();
+    assert_eq!(titles(&storage), ["Kept", "Added later"]);
+    let log = storage.events().read().unwrap();
+    assert_eq!(log.last().unwrap().kind, EventKind::Created);
+    assert!(fs::read_to_string(&config_file).unwrap().contains("10:00"));
+    assert!(backups.find("reminders-19700101-000000").is_err());
+}
+
+#[test]
+fn restores_sqlite_over_json() {
+    isolate();
+    let dir = tempfile::tempdir().unwrap();
+    let config_file = dir.path().join("config.toml");
+    let sqlite = Storage::with_path(dir.path().join("reminders.db"));
+    sqlite.add(reminder("From sqlite")).unwrap();
+
+    let backups = Backups::new(sqlite.path(), &config_file, &BackupConfig::default());
+    let snapshot = backups.create().unwrap();
+    fs::remove_file(sqlite.path()).unwrap();
+
+    // Meanwhile the data directory went back to JSON
+    let json = Storage::with_path(dir.path().join("reminders.json"));
+    json.add(reminder("From json")).unwrap();
+    let backups = Backups::new(json.path(), &config_file, &BackupConfig::default());
+    let restore = backups.restore(&snapshot).unwrap();
+
+    assert_eq!(titles(&sqlite), ["From sqlite"]);
+    assert_eq!(
+        restore.moved,
+        [dir.path().join("reminders.json.pre-restore")]
+    );
+    assert!(!json.path().exists());
+}
+
+#[test]
+fn daemon_takes_one_backup_a_day() {
+    isolate();
+    let dir = tempfile::tempdir().unwrap();
+    let config_file = paths::config_file().unwrap();
+    let storage = Storage::with_path(dir.path().join("reminders.json"));
+    storage.add(reminder("Daily")).unwrap();
+    fs::write(&config_file, "[backup]\ndaily = false\n").unwrap();
+    run_daemon_until(&storage, NotifierRegistry::new(), Some(Instant::now())).unwrap();
+    assert!(!dir.path().join("backups").exists());
+
+    // On by default
+    fs::remove_file(&config_file).unwrap();
+    let backups = Backups::new(storage.path(), &config_file, &BackupConfig::default());
+    for _ in 0..2 {
+        run_daemon_until(&storage, NotifierRegistry::new(), Some(Instant::now())).unwrap();
+    }
+    assert_eq!(backups.list().unwrap().len(), 1);
+
+    fs::write(&config_file, "[backup]\ndaily = false\n").unwrap();
+    fs::remove_dir_all(backups.dir()).unwrap();
+    run_daemon_until(&storage, NotifierRegistry::new(), Some(Instant::now())).unwrap();
+    assert!(backups.list().unwrap().is_empty());
+}
@@ -0,0 +1,82 @@
+//! Fixtures shared by the integration tests. Each test file uses only some
+//! of them.
+#![allow(dead_code)]
+
+use reminder_cli::crypto::{KEY_FILE_ENV, PASSPHRASE_ENV};
+use reminder_cli::paths::DATA_DIR_ENV;
+use reminder_cli::reminder::Reminder;
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output};
+use std::sync::OnceLock;
+
+/// One-time reminder without tags, due `delay` from now
+pub fn reminder_in(title: &str, delay: chrono::Duration) -> Reminder {
+    Reminder::new_one_time(
+        title.to_string(),
+        None,
+        chrono::Local::now() + delay,
+        HashSet::new(),
+    )
+}
+
+/// One-time reminder without tags, due in an hour
+pub fn reminder(title: &str) -> Reminder {
+    reminder_in(title, chrono::Duration::hours(1))
+}
+
+/// The `reminder` binary, ignoring the data directory, key and editor set
+/// in the environment running the tests
+pub fn cli() -> Command {
+    let mut command = Command::new(env!("CARGO_BIN_EXE_reminder"));
+    command
+        .env_remove(DATA_DIR_ENV)
+        .env_remove(KEY_FILE_ENV)
+        .env_remove(PASSPHRASE_ENV)
+        .env_remove("VISUAL");
+    command
+}
+
+/// Point the data, config and daemon directories of this test process at a
+/// directory of its own, so the log and anything else written in-process
+/// stays out of the user's. The environment is shared by the tests running
+/// in parallel, so it is set once for all of them; each keeps its store in
+/// its own temp dir.
+pub fn isolate() -> &'static Path {
+    static DIR: OnceLock<PathBuf> = OnceLock::new();
+    DIR.get_or_init(|| {
+        let dir = Path::new(env!("CARGO_TARGET_TMPDIR")).join(env!("CARGO_CRATE_NAME"));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::env::set_var(DATA_DIR_ENV, &dir);
+        std::env::set_var("XDG_CONFIG_HOME", &dir);
+        std::env::set_var("XDG_RUNTIME_DIR", &dir);
+        dir
+    })
+}
+
+/// [`cli`] with `--data-dir dir`, and the config and daemon files under
+/// `dir` too
+pub fn cli_in(dir: &Path) -> Command {
+    let mut command = cli();
+    command
+        .arg("--data-dir")
+        .arg(dir)
+        .env("XDG_CONFIG_HOME", dir)
+        .env("XDG_RUNTIME_DIR", dir);
+    command
+}
+
+/// `config.toml` of [`cli_in`]
+pub fn config_file(dir: &Path) -> PathBuf {
+    dir.join("reminder-cli").join("config.toml")
+}
+
+/// Standard output of a command that must have succeeded
+pub fn stdout(output: Output) -> String {
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    String::from_utf8(output.stdout).unwrap()
+}
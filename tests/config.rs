@@ -0,0 +1,82 @@
+//! `reminder config` commands and the settings they control.
+
+mod common;
+
+use common::{cli_in, config_file, stdout};
+use reminder_cli::storage::{ReminderStore, Storage};
+use std::fs;
+use std::path::Path;
+use std::process::Output;
+
+fn reminder(dir: &Path, args: &[&str], editor: Option<&str>) -> Output {
+    let mut command = cli_in(dir);
+    command.args(args);
+    if let Some(editor) = editor {
+        command.env("EDITOR", editor);
+    }
+    command.output().unwrap()
+}
+
+#[test]
+fn set_get_and_use_settings() {
+    let dir = tempfile::tempdir().unwrap();
+    let dir = dir.path();
+
+    let list = stdout(reminder(dir, &["config", "list"], None));
+    assert!(list.contains("daemon.poll_interval_secs = 10"));
+    assert!(list.contains("time.default_time = \"09:00\""));
+
+    stdout(reminder(
+        dir,
+        &["config", "set", "time.default_time", "18:30"],
+        None,
+    ));
+    assert_eq!(
+        stdout(reminder(dir, &["config", "get", "time.default_time"], None)),
+        "18:30\n"
+    );
+
+    // Dates without a time of day now fall at the configured time
+    stdout(reminder(
+        dir,
+        &["add", "--title", "Evening", "-T", "tomorrow"],
+        None,
+    ));
+    let added = Storage::with_path(dir.join("reminders.json"))
+        .load()
+        .unwrap();
+    let trigger = added[0].next_trigger.unwrap();
+    assert_eq!(trigger.format("%H:%M").to_string(), "18:30");
+
+    let unknown = reminder(dir, &["config", "get", "daemon.speed"], None);
+    assert!(!unknown.status.success());
+    assert!(String::from_utf8_lossy(&unknown.stderr).contains("reminder config list"));
+}
+
+#[test]
+fn edit_rejects_invalid_config() {
+    let dir = tempfile::tempdir().unwrap();
+    let dir = dir.path();
+    let config = config_file(dir);
+    fs::create_dir_all(config.parent().unwrap()).unwrap();
+    fs::write(&config, "[log]\nmax_size_kb = 64\n").unwrap();
+
+    let output = reminder(
+        dir,
+        &["config", "edit"],
+        Some("sed -i s/max_size_kb/max_size/"),
+    );
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("unknown field `max_size`"));
+    assert_eq!(
+        fs::read_to_string(&config).unwrap(),
+        "[log]\nmax_size_kb = 64\n"
+    );
+
+    stdout(reminder(dir, &["config", "edit"], Some("sed -i s/64/128/")));
+    assert_eq!(
+        fs::read_to_string(&config).unwrap(),
+        "[log]\nmax_size_kb = 128\n"
+    );
+    assert!(!config.with_extension("toml.edit").exists());
+}
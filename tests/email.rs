@@ -0,0 +1,124 @@
+//! Email delivery against a local SMTP sink.
+
+use reminder_cli::config::Config;
+use reminder_cli::email::{send_email, EmailConfig, DEFAULT_PASSWORD_ENV};
+use reminder_cli::reminder::Reminder;
+use std::collections::HashSet;
+use std::io::{BufRead, BufReader, Write};
+use std::net::TcpListener;
+use std::sync::mpsc::{channel, Receiver};
+use std::thread;
+
+struct Envelope {
+    from: String,
+    to: Vec<String>,
+    data: String,
+}
+
+/// Minimal SMTP server accepting a single message
+fn smtp_sink() -> (u16, Receiver<Envelope>) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let (tx, rx) = channel();
+
+    thread::spawn(move || {
+        let (stream, _) = listener.accept().unwrap();
+        let mut reader = BufReader::new(stream.try_clone().unwrap());
+        let mut writer = stream;
+        let mut envelope = Envelope {
+            from: String::new(),
+            to: Vec::new(),
+            data: String::new(),
+        };
+
+        writer.write_all(b"220 sink ESMTP\r\n").unwrap();
+        loop {
+            let mut line = String::new();
+            if reader.read_line(&mut line).unwrap() == 0 {
+                break;
+            }
+            let command = line.trim_end().to_string();
+            let upper = command.to_uppercase();
+
+            if upper.starts_with("EHLO") || upper.starts_with("HELO") {
+                writer.write_all(b"250-sink\r\n250 8BITMIME\r\n").unwrap();
+            } else if upper.starts_with("MAIL FROM:") {
+                envelope.from = command[10..].trim().to_string();
+                writer.write_all(b"250 OK\r\n").unwrap();
+            } else if upper.starts_with("RCPT TO:") {
+                envelope.to.push(command[8..].trim().to_string());
+                writer.write_all(b"250 OK\r\n").unwrap();
+            } else if upper == "DATA" {
+                writer.write_all(b"354 go ahead\r\n").unwrap();
+                loop {
+                    let mut line = String::new();
+                    reader.read_line(&mut line).unwrap();
+                    if line == ".\r\n" {
+                        break;
+                    }
+                    envelope.data.push_str(&line);
+                }
+                writer.write_all(b"250 queued\r\n").unwrap();
+            } else if upper == "QUIT" {
+                writer.write_all(b"221 bye\r\n").unwrap();
+                break;
+            } else {
+                writer.write_all(b"250 OK\r\n").unwrap();
+            }
+        }
+
+        let _ = tx.send(envelope);
+    });
+
+    (port, rx)
+}
+
+fn config(port: u16) -> EmailConfig {
+    EmailConfig {
+        host: "127.0.0.1".to_string(),
+        port,
+        starttls: false,
+        username: None,
+        password_env: DEFAULT_PASSWORD_ENV.to_string(),
+        from: "Reminder <reminder@example.com>".to_string(),
+        to: vec!["ops@example.com".to_string(), "me@example.com".to_string()],
+        timeout_secs: 5,
+    }
+}
+
+#[test]
+fn delivers_reminder_to_all_recipients() {
+    let (port, rx) = smtp_sink();
+    let tags: HashSet<String> = ["oncall".to_string()].into_iter().collect();
+    let reminder = Reminder::new_one_time(
+        "Rotate certificates".to_string(),
+        Some("Certs expire Friday".to_string()),
+        chrono::Local::now(),
+        tags,
+    );
+
+    send_email(&config(port), &reminder, &Config::default()).unwrap();
+
+    let envelope = rx.recv().unwrap();
+    assert_eq!(envelope.from, "<reminder@example.com>");
+    assert_eq!(envelope.to, ["<ops@example.com>", "<me@example.com>"]);
+    assert!(envelope
+        .data
+        .contains("Subject: Reminder: Rotate certificates"));
+    assert!(envelope.data.contains("Certs expire Friday"));
+    assert!(envelope.data.contains("Tags: oncall"));
+}
+
+#[test]
+fn missing_password_env_is_reported() {
+    let mut config = config(1);
+    config.username = Some("me".to_string());
+    config.password_env = "REMINDER_TEST_UNSET_SMTP_PASSWORD".to_string();
+
+    let reminder =
+        Reminder::new_one_time("x".to_string(), None, chrono::Local::now(), HashSet::new());
+    let err = send_email(&config, &reminder, &Config::default()).unwrap_err();
+    assert!(err
+        .to_string()
+        .contains("REMINDER_TEST_UNSET_SMTP_PASSWORD"));
+}
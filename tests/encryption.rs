@@ -0,0 +1,150 @@
+//! At-rest encryption of the JSON store and its backups.
+
+mod common;
+
+use common::{cli_in, reminder};
+use reminder_cli::crypto::{KeySource, Vault, KEY_FILE_ENV};
+use reminder_cli::retry::{DeadLetterQueue, FailedDelivery};
+use reminder_cli::storage::{JsonStore, ReminderStore, Storage};
+use std::fs;
+use std::path::Path;
+use std::process::Output;
+use std::sync::Arc;
+
+fn mentions(path: &Path, text: &str) -> bool {
+    String::from_utf8_lossy(&fs::read(path).unwrap()).contains(text)
+}
+
+#[test]
+fn encrypts_store_and_backups() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("reminders.json");
+    let key_file = dir.path().join("key");
+    fs::write(&key_file, "not a real key\n").unwrap();
+
+    let storage = Storage::with_path(&path);
+    storage.add(reminder("Call Acme about the outage")).unwrap();
+    storage.add(reminder("Second")).unwrap();
+
+    let vault = Vault::new(KeySource::KeyFile(key_file.clone()));
+    assert_eq!(storage.encrypt(&vault).unwrap(), 2);
+    assert!(storage.is_encrypted());
+    assert!(!mentions(&path, "Acme"));
+    assert!(!mentions(&dir.path().join("reminders.json.bak1"), "Acme"));
+    let history = dir.path().join("history.jsonl");
+    assert!(!mentions(&history, "Acme"));
+
+    // Saves stay encrypted, and so do the backups they make
+    let store = JsonStore::with_vault(&path, Arc::new(vault));
+    store.add(reminder("Third")).unwrap();
+    assert!(store.is_encrypted());
+    assert!(!mentions(&dir.path().join("reminders.json.bak1"), "Acme"));
+    assert_eq!(store.load().unwrap().len(), 3);
+
+    let wrong = Vault::new(KeySource::Passphrase("guess".to_string().into()));
+    let err = JsonStore::with_vault(&path, Arc::new(wrong))
+        .load()
+        .unwrap_err();
+    assert!(err.to_string().contains("wrong passphrase"), "{:#}", err);
+
+    // The key is looked up when none is given, and new events are
+    // encrypted as well
+    std::env::set_var(KEY_FILE_ENV, &key_file);
+    storage.add(reminder("Fourth")).unwrap();
+    assert!(!mentions(&history, "Fourth"));
+    let events = storage.events().read().unwrap();
+    assert_eq!(events.last().unwrap().title, "Fourth");
+
+    assert_eq!(storage.decrypt().unwrap(), 4);
+    assert!(!storage.is_encrypted());
+    assert!(mentions(&path, "Acme"));
+    assert!(mentions(&dir.path().join("reminders.json.bak1"), "Acme"));
+    assert!(mentions(&history, "Acme") && mentions(&history, "Fourth"));
+}
+
+fn failed(title: &str) -> FailedDelivery {
+    FailedDelivery {
+        id: uuid::Uuid::new_v4(),
+        reminder: reminder(title),
+        channel: "webhook".to_string(),
+        target: None,
+        error: "HTTP 503".to_string(),
+        attempts: 1,
+        failed_at: chrono::Local::now(),
+        next_attempt: None,
+    }
+}
+
+#[test]
+fn encrypts_failed_deliveries() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("reminders.json");
+    let failed_file = dir.path().join("failed.json");
+    let key_file = dir.path().join("key");
+    fs::write(&key_file, "not a real key\n").unwrap();
+
+    let storage = Storage::with_path(&path);
+    storage.add(reminder("Call Acme about the outage")).unwrap();
+    let queue = DeadLetterQueue::for_storage(&storage);
+    queue.push(failed("Call Acme about the outage")).unwrap();
+    assert!(mentions(&failed_file, "Acme"));
+
+    let vault = Arc::new(Vault::new(KeySource::KeyFile(key_file)));
+    storage.encrypt(&vault).unwrap();
+    assert!(!mentions(&failed_file, "Acme"));
+
+    // Entries queued while the store is encrypted are encrypted too
+    let storage = Storage::with_vault(&path, vault);
+    let queue = DeadLetterQueue::for_storage(&storage);
+    queue.push(failed("Renew the Globex contract")).unwrap();
+    assert!(!mentions(&failed_file, "Globex"));
+    assert!(!mentions(&failed_file, "Acme"));
+
+    storage.decrypt().unwrap();
+    let titles: Vec<String> = queue
+        .load()
+        .unwrap()
+        .into_iter()
+        .map(|e| e.reminder.title)
+        .collect();
+    assert_eq!(
+        titles,
+        ["Call Acme about the outage", "Renew the Globex contract"]
+    );
+    assert!(mentions(&failed_file, "Globex"));
+}
+
+fn reminder_cli(dir: &Path, args: &[&str]) -> Output {
+    cli_in(dir).args(args).output().unwrap()
+}
+
+#[test]
+fn cli_uses_configured_key_file() {
+    let dir = tempfile::tempdir().unwrap();
+    let dir = dir.path();
+    let key = dir.join("keys/reminders.key");
+    fs::create_dir(dir.join("keys")).unwrap();
+
+    assert!(reminder_cli(dir, &["add", "--title", "Acme", "-T", "1h"])
+        .status
+        .success());
+    let output = reminder_cli(
+        dir,
+        &["storage", "encrypt", "--key-file", key.to_str().unwrap()],
+    );
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    assert!(key.exists());
+
+    // No prompt needed: the key file is recorded in the config
+    let list = reminder_cli(dir, &["list"]);
+    assert!(String::from_utf8_lossy(&list.stdout).contains("Acme"));
+
+    fs::remove_file(&key).unwrap();
+    let list = reminder_cli(dir, &["list"]);
+    assert!(!list.status.success());
+    assert!(String::from_utf8_lossy(&list.stderr).contains("Failed to read key file"));
+}
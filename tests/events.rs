@@ -0,0 +1,182 @@
+//! The event log `Storage` keeps of every change, and rebuilding the
+//! store from it.
+
+mod common;
+
+use chrono::Duration;
+use common::{isolate, reminder_in};
+use reminder_cli::events::EventKind;
+use reminder_cli::reminder::Reminder;
+use reminder_cli::storage::{JsonStore, ReminderStore, Storage};
+use std::fs;
+
+#[test]
+fn logs_every_change_and_rebuilds_the_store() {
+    isolate();
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("reminders.json");
+
+    // Reminders from before the log existed are its starting point
+    let old = reminder_in("Old", Duration::minutes(60));
+    JsonStore::new(&path).add(old.clone()).unwrap();
+
+    let storage = Storage::with_path(&path);
+    let due = reminder_in("Due", Duration::minutes(-1));
+    storage.add(due.clone()).unwrap();
+    storage
+        .update(old.id, Box::new(|r| r.title = "Renamed".to_string()))
+        .unwrap();
+    storage.pause_by_short_id("1").unwrap();
+    storage
+        .update_due(&mut |r| {
+            r.occurrences += 1;
+            r.calculate_next_trigger();
+        })
+        .unwrap();
+
+    let export = dir.path().join("export.json");
+    let imported = reminder_in("Imported", Duration::minutes(30));
+    fs::write(&export, serde_json::to_string(&[&imported]).unwrap()).unwrap();
+    storage.import_from_file(&export, false).unwrap();
+    storage.delete(imported.id).unwrap();
+
+    let events = storage.events().read().unwrap();
+    let kinds: Vec<EventKind> = events.iter().map(|e| e.kind).collect();
+    assert_eq!(
+        kinds,
+        [
+            EventKind::Existing,
+            // The first change numbers the reminder that was already there
+            EventKind::Edited,
+            EventKind::Created,
+            EventKind::Edited,
+            EventKind::Paused,
+            EventKind::Fired,
+            EventKind::Imported,
+            EventKind::Deleted,
+        ]
+    );
+    let renamed = &events[3].changes["title"];
+    assert_eq!(
+        (renamed.old.as_str(), renamed.new.as_str()),
+        (Some("Old"), Some("Renamed"))
+    );
+    assert!(events
+        .iter()
+        .all(|e| !e.user.is_empty() && !e.host.is_empty()));
+
+    // Rebuilding is a change of its own, with a new revision and time
+    let comparable = |reminders: Vec<Reminder>| {
+        reminders
+            .into_iter()
+            .map(|mut r| {
+                assert!(r.updated_at.is_some());
+                r.updated_at = None;
+                r.revision = 0;
+                serde_json::to_value(r).unwrap()
+            })
+            .collect::<Vec<_>>()
+    };
+    let expected = comparable(storage.load().unwrap());
+    fs::remove_file(&path).unwrap();
+    assert_eq!(storage.rebuild().unwrap(), 2);
+    let rebuilt = storage.load().unwrap();
+    assert!(rebuilt.iter().all(|r| r.revision > 0));
+    assert_eq!(comparable(rebuilt), expected);
+}
+
+#[test]
+fn logs_cleaning_and_merged_conflict_copies() {
+    isolate();
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("reminders.json");
+    let storage = Storage::with_path(&path);
+    let (done, kept) = (
+        reminder_in("Done", Duration::minutes(-5)),
+        reminder_in("Kept", Duration::minutes(60)),
+    );
+    storage.add(done.clone()).unwrap();
+    storage.add(kept.clone()).unwrap();
+    storage
+        .update(done.id, Box::new(|r| r.mark_done()))
+        .unwrap();
+    assert_eq!(storage.clean_completed().unwrap(), 1);
+
+    // Another device renamed "Kept" and added a reminder of its own
+    let copy = dir
+        .path()
+        .join("reminders.sync-conflict-20240101-120000-ABCDEFG.json");
+    fs::copy(&path, &copy).unwrap();
+    let other = JsonStore::new(&copy);
+    other
+        .update(kept.id, Box::new(|r| r.title = "Kept at 3pm".to_string()))
+        .unwrap();
+    other
+        .add(reminder_in("From desktop", Duration::minutes(90)))
+        .unwrap();
+
+    let mut titles: Vec<String> = storage
+        .load()
+        .unwrap()
+        .into_iter()
+        .map(|r| r.title)
+        .collect();
+    titles.sort();
+    assert_eq!(titles, ["From desktop", "Kept at 3pm"]);
+    assert!(!copy.exists());
+
+    let events = storage.events().read().unwrap();
+    let kinds: Vec<EventKind> = events.iter().map(|e| e.kind).collect();
+    assert_eq!(
+        kinds,
+        [
+            EventKind::Created,
+            EventKind::Created,
+            EventKind::Edited,
+            EventKind::Deleted,
+            EventKind::Edited,
+            EventKind::Created,
+        ]
+    );
+
+    let expected = storage.load().unwrap();
+    fs::remove_file(&path).unwrap();
+    assert_eq!(storage.rebuild().unwrap(), 2);
+    let rebuilt = storage.load().unwrap();
+    let ids = |reminders: &[Reminder]| {
+        reminders
+            .iter()
+            .map(|r| (r.id, r.title.clone(), r.number))
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(ids(&rebuilt), ids(&expected));
+}
+
+#[test]
+fn merge_is_logged_as_one_change() {
+    isolate();
+    let dir = tempfile::tempdir().unwrap();
+    let storage = Storage::with_path(dir.path().join("reminders.json"));
+    storage
+        .add(reminder_in("Local", Duration::minutes(60)))
+        .unwrap();
+
+    // A copy from another device that numbered its own reminder 1 too
+    let other = JsonStore::new(dir.path().join("other.json"));
+    let mut theirs = reminder_in("Theirs", Duration::minutes(30));
+    theirs.number = 1;
+    other.add(theirs.clone()).unwrap();
+
+    let report = storage.merge(other.document().unwrap(), None).unwrap();
+    assert_eq!(report.added, 1);
+
+    // Renumbered in the same change, so the log has it as merged
+    let events = storage.events().read().unwrap();
+    let last = events.last().unwrap();
+    assert_eq!((last.kind, last.id), (EventKind::Merged, theirs.id));
+    assert_eq!(last.reminder.as_ref().unwrap().number, 2);
+    assert_eq!(
+        storage.find_by_short_id("2").unwrap().unwrap().id,
+        theirs.id
+    );
+}
@@ -0,0 +1,70 @@
+//! Numbers and aliases assigned by `Storage`, on both backends.
+
+mod common;
+
+use common::reminder;
+use reminder_cli::storage::{ReminderStore, Storage};
+
+fn numbers_and_aliases_resolve(file: &str) {
+    let dir = tempfile::tempdir().unwrap();
+    let storage = Storage::with_path(dir.path().join(file));
+
+    let mut standup = reminder("Stand-up");
+    standup.alias = Some("standup".to_string());
+    storage.add(standup.clone()).unwrap();
+    storage.add(reminder("Lunch")).unwrap();
+    let dentist = reminder("Dentist");
+    storage.add(dentist.clone()).unwrap();
+
+    let numbers: Vec<u32> = storage.load().unwrap().iter().map(|r| r.number).collect();
+    assert_eq!(numbers, [1, 2, 3]);
+
+    let find = |key: &str| storage.find_by_short_id(key).unwrap().unwrap().title;
+    assert_eq!(find("2"), "Lunch");
+    assert_eq!(find("#3"), "Dentist");
+    assert_eq!(find("standup"), "Stand-up");
+    assert_eq!(find(&dentist.id.to_string()[..8]), "Dentist");
+
+    // Names stay unique, and numbers are not handed out twice
+    let err = storage
+        .update(
+            dentist.id,
+            Box::new(|r| r.alias = Some("standup".to_string())),
+        )
+        .unwrap_err();
+    assert!(err.to_string().contains("already used"), "{}", err);
+    let mut taken = reminder("Taken");
+    taken.alias = Some("standup".to_string());
+    assert!(storage.add(taken).is_err());
+
+    storage.delete(standup.id).unwrap();
+    storage.add(reminder("Gym")).unwrap();
+    assert_eq!(find("4"), "Gym");
+    assert!(storage.find_by_short_id("standup").unwrap().is_none());
+    assert_eq!(find("2"), "Lunch");
+
+    // Not even the highest number, once its reminder is deleted
+    let gym = storage.find_by_short_id("4").unwrap().unwrap();
+    storage.delete(gym.id).unwrap();
+    storage.add(reminder("Swim")).unwrap();
+    assert_eq!(find("5"), "Swim");
+    assert!(storage.load().unwrap().iter().all(|r| r.number != 4));
+
+    // The highest number handed out is kept with the store
+    let reopened = Storage::with_path(dir.path().join(file));
+    let swim = reopened.find_by_short_id("5").unwrap().unwrap();
+    reopened.delete(swim.id).unwrap();
+    reopened.add(reminder("Yoga")).unwrap();
+    let yoga = reopened.find_by_short_id("6").unwrap().unwrap();
+    assert_eq!(yoga.title, "Yoga");
+}
+
+#[test]
+fn json_store_numbers_and_names_reminders() {
+    numbers_and_aliases_resolve("reminders.json");
+}
+
+#[test]
+fn sqlite_store_numbers_and_names_reminders() {
+    numbers_and_aliases_resolve("reminders.db");
+}
@@ -0,0 +1,193 @@
+//! Merging copies of the JSON store edited on different devices.
+
+mod common;
+
+use common::{cli_in, config_file, isolate, reminder, stdout};
+use reminder_cli::config::Config;
+use reminder_cli::hooks::hooks_for;
+use reminder_cli::reminder::Reminder;
+use reminder_cli::storage::{JsonStore, ReminderStore};
+use std::fs;
+use std::path::Path;
+
+fn titles(store: &JsonStore) -> Vec<String> {
+    let mut titles: Vec<String> = store.load().unwrap().into_iter().map(|r| r.title).collect();
+    titles.sort();
+    titles
+}
+
+/// Two devices start from the same file and change it independently
+fn diverge(laptop: &Path, desktop: &Path) {
+    let store = JsonStore::new(laptop);
+    let (a, b) = (reminder("Dentist"), reminder("Rent"));
+    let (a_id, b_id) = (a.id, b.id);
+    store.add(a).unwrap();
+    store.add(b).unwrap();
+    fs::copy(laptop, desktop).unwrap();
+
+    store.update(a_id, Box::new(|r| r.paused = true)).unwrap();
+    store.delete(b_id).unwrap();
+
+    let other = JsonStore::new(desktop);
+    other
+        .update(a_id, Box::new(|r| r.title = "Dentist at 3pm".to_string()))
+        .unwrap();
+    other.update(a_id, Box::new(|r| r.paused = false)).unwrap();
+    other.add(reminder("Groceries")).unwrap();
+}
+
+#[test]
+fn merges_sync_conflict_copy_on_load() {
+    isolate();
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("reminders.json");
+    let desktop = dir.path().join("desktop");
+    fs::create_dir_all(&desktop).unwrap();
+    diverge(&path, &desktop.join("reminders.json"));
+
+    // The sync tool keeps the desktop's version next to the laptop's
+    let copy = dir
+        .path()
+        .join("reminders.sync-conflict-20240101-120000-ABCDEFG.json");
+    fs::rename(desktop.join("reminders.json"), &copy).unwrap();
+
+    // The desktop's two edits win over the laptop's one; the laptop's
+    // delete wins over the untouched copy of "Rent"
+    let store = JsonStore::new(&path);
+    assert_eq!(titles(&store), ["Dentist at 3pm", "Groceries"]);
+    assert!(!copy.exists());
+    assert!(store.load().unwrap().iter().all(|r| !r.paused));
+}
+
+#[test]
+fn cli_merge_is_symmetric() {
+    isolate();
+    let dir = tempfile::tempdir().unwrap();
+    let (laptop, desktop) = (dir.path().join("laptop"), dir.path().join("desktop"));
+    fs::create_dir_all(&laptop).unwrap();
+    fs::create_dir_all(&desktop).unwrap();
+    let (ours, theirs) = (
+        laptop.join("reminders.json"),
+        desktop.join("reminders.json"),
+    );
+    diverge(&ours, &theirs);
+    let theirs_before = fs::read(&theirs).unwrap();
+    fs::copy(&ours, dir.path().join("ours.json")).unwrap();
+    // The laptop's delete is in the tombstones next to its file
+    fs::copy(
+        laptop.join("reminders.json.meta"),
+        dir.path().join("ours.json.meta"),
+    )
+    .unwrap();
+
+    let merge = |data_dir: &Path, file: &Path| {
+        let output = cli_in(data_dir).arg("merge").arg(file).output().unwrap();
+        assert!(
+            output.status.success(),
+            "{}",
+            String::from_utf8_lossy(&output.stderr)
+        );
+        String::from_utf8_lossy(&output.stdout).into_owned()
+    };
+
+    let out = merge(&laptop, &theirs);
+    assert!(out.contains("1 added, 1 updated, 0 deleted"), "{}", out);
+    fs::write(&theirs, theirs_before).unwrap();
+    let out = merge(&desktop, &dir.path().join("ours.json"));
+    assert!(out.contains("0 added, 0 updated, 1 deleted"), "{}", out);
+
+    assert_eq!(
+        titles(&JsonStore::new(&ours)),
+        titles(&JsonStore::new(&theirs))
+    );
+}
+
+#[test]
+fn saving_a_deleted_reminder_replaces_its_tombstone() {
+    isolate();
+    let dir = tempfile::tempdir().unwrap();
+    let store = JsonStore::new(dir.path().join("reminders.json"));
+    let gone = reminder("Gone");
+    store.add(gone.clone()).unwrap();
+    store.delete(gone.id).unwrap();
+    let tombstones = store.document().unwrap().tombstones;
+    assert_eq!(tombstones.len(), 1);
+
+    store.save(&[gone]).unwrap();
+    let document = store.document().unwrap();
+    assert!(document.tombstones.is_empty());
+    // Newer than the delete, so merging an older copy keeps it
+    assert!(document.reminders[0].revision > tombstones[0].revision);
+}
+
+#[test]
+fn tombstones_are_kept_next_to_a_plain_reminders_file() {
+    isolate();
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("reminders.json");
+    let store = JsonStore::new(&path);
+    let (kept, gone) = (reminder("Kept"), reminder("Gone"));
+    store.add(kept.clone()).unwrap();
+    store.add(gone.clone()).unwrap();
+    let before_delete = fs::read(&path).unwrap();
+    store.delete(gone.id).unwrap();
+
+    // Older versions read the file as a plain list of reminders
+    let plain: Vec<Reminder> = serde_json::from_slice(&fs::read(&path).unwrap()).unwrap();
+    assert_eq!(plain.len(), 1);
+    assert_eq!(store.document().unwrap().tombstones.len(), 1);
+
+    // A copy from before the delete does not bring it back
+    let copy = dir
+        .path()
+        .join("reminders.sync-conflict-20240101-120000-ABCDEFG.json");
+    fs::write(&copy, before_delete).unwrap();
+    assert_eq!(titles(&store), ["Kept"]);
+
+    // Nor does a conflicting copy of the tombstones lose the other
+    // device's delete
+    let desktop = dir.path().join("desktop");
+    fs::create_dir_all(&desktop).unwrap();
+    fs::copy(&path, desktop.join("reminders.json")).unwrap();
+    let other = JsonStore::new(desktop.join("reminders.json"));
+    other.delete(kept.id).unwrap();
+    fs::copy(
+        desktop.join("reminders.json.meta"),
+        dir.path()
+            .join("reminders.json.sync-conflict-20240101-120000-ABCDEFG.meta"),
+    )
+    .unwrap();
+    assert!(titles(&store).is_empty());
+    assert_eq!(store.document().unwrap().tombstones.len(), 2);
+    assert!(store.conflict_copies().is_empty());
+}
+
+#[test]
+fn merged_hooks_do_not_run_until_trusted_here() {
+    isolate();
+    let dir = tempfile::tempdir().unwrap();
+    let (laptop, desktop) = (dir.path().join("laptop"), dir.path().join("desktop"));
+    let add = ["add", "--title", "Hooked", "-T", "1h"];
+    let hook = ["--exec", "touch hooked"];
+    stdout(cli_in(&desktop).args(add).args(hook).output().unwrap());
+    let merge = cli_in(&laptop)
+        .arg("merge")
+        .arg(desktop.join("reminders.json"))
+        .output()
+        .unwrap();
+    stdout(merge);
+
+    let hooked = JsonStore::new(laptop.join("reminders.json"));
+    let hooked = hooked.load().unwrap();
+    let config = |dir: &Path| Config::load_from(&config_file(dir)).unwrap();
+    assert!(hooks_for(&hooked[0], &config(&laptop)).is_empty());
+    assert_eq!(hooks_for(&hooked[0], &config(&desktop)), ["touch hooked"]);
+
+    let shown = stdout(cli_in(&laptop).args(["show", "1"]).output().unwrap());
+    assert!(shown.contains("touch hooked (not trusted on this machine)"));
+
+    // Setting it again here trusts it
+    let edit = ["edit", "--id", "1", "--exec", "touch hooked"];
+    stdout(cli_in(&laptop).args(edit).output().unwrap());
+    assert_eq!(hooks_for(&hooked[0], &config(&laptop)), ["touch hooked"]);
+}
@@ -0,0 +1,201 @@
+//! MQTT publishing against a minimal in-process broker, and against a real
+//! Mosquitto broker when `mosquitto` is installed.
+
+use reminder_cli::config::{Config, TagConfig};
+use reminder_cli::mqtt::{publish, MqttConfig, DEFAULT_MQTT_PASSWORD_ENV};
+use reminder_cli::reminder::Reminder;
+use std::collections::HashSet;
+use std::io::{Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::process::{Child, Command, Stdio};
+use std::sync::mpsc::{channel, Receiver};
+use std::thread;
+use std::time::Duration;
+
+struct Published {
+    topic: String,
+    qos: u8,
+    payload: serde_json::Value,
+}
+
+fn read_packet(stream: &mut TcpStream) -> Option<(u8, Vec<u8>)> {
+    let mut header = [0u8; 1];
+    stream.read_exact(&mut header).ok()?;
+
+    let (mut length, mut shift) = (0usize, 0);
+    loop {
+        let mut byte = [0u8; 1];
+        stream.read_exact(&mut byte).ok()?;
+        length |= ((byte[0] & 0x7f) as usize) << shift;
+        if byte[0] & 0x80 == 0 {
+            break;
+        }
+        shift += 7;
+    }
+
+    let mut body = vec![0u8; length];
+    stream.read_exact(&mut body).ok()?;
+    Some((header[0], body))
+}
+
+/// Accepts one client and reports every PUBLISH it sends
+fn stand_in_broker() -> (u16, Receiver<Published>) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let (tx, rx) = channel();
+
+    thread::spawn(move || {
+        let (mut stream, _) = listener.accept().unwrap();
+        while let Some((header, body)) = read_packet(&mut stream) {
+            match header >> 4 {
+                // CONNECT
+                1 => stream.write_all(&[0x20, 0x02, 0x00, 0x00]).unwrap(),
+                // PUBLISH
+                3 => {
+                    let qos = (header >> 1) & 0x03;
+                    let topic_len = u16::from_be_bytes([body[0], body[1]]) as usize;
+                    let topic = String::from_utf8(body[2..2 + topic_len].to_vec()).unwrap();
+                    let mut offset = 2 + topic_len;
+                    if qos > 0 {
+                        let id = [body[offset], body[offset + 1]];
+                        offset += 2;
+                        stream.write_all(&[0x40, 0x02, id[0], id[1]]).unwrap();
+                    }
+                    let payload = serde_json::from_slice(&body[offset..]).unwrap();
+                    let _ = tx.send(Published {
+                        topic,
+                        qos,
+                        payload,
+                    });
+                }
+                // PINGREQ
+                12 => stream.write_all(&[0xd0, 0x00]).unwrap(),
+                // DISCONNECT
+                14 => break,
+                _ => {}
+            }
+        }
+    });
+
+    (port, rx)
+}
+
+fn mqtt_config(port: u16) -> MqttConfig {
+    MqttConfig {
+        host: "127.0.0.1".to_string(),
+        port,
+        client_id: "reminder-cli-test".to_string(),
+        username: None,
+        password_env: DEFAULT_MQTT_PASSWORD_ENV.to_string(),
+        topic: "home/reminders/{short_id}".to_string(),
+        qos: 1,
+        retain: false,
+        timeout_secs: 5,
+    }
+}
+
+fn reminder(tags: &[&str]) -> Reminder {
+    let tags: HashSet<String> = tags.iter().map(|t| t.to_string()).collect();
+    Reminder::new_one_time(
+        "Lights on".to_string(),
+        Some("Living room".to_string()),
+        chrono::Local::now(),
+        tags,
+    )
+}
+
+#[test]
+fn publishes_event_to_reminder_and_tag_topics() {
+    let (port, rx) = stand_in_broker();
+    let mut config = Config::default();
+    config.tags.insert(
+        "home".to_string(),
+        TagConfig {
+            mqtt_topic: Some("home/speakers".to_string()),
+            ..Default::default()
+        },
+    );
+
+    let mut reminder = reminder(&["home"]);
+    reminder.mqtt_topic = Some("home/lights".to_string());
+    publish(&mqtt_config(port), &reminder, &config).unwrap();
+
+    let first = rx.recv_timeout(Duration::from_secs(5)).unwrap();
+    let second = rx.recv_timeout(Duration::from_secs(5)).unwrap();
+    assert_eq!(first.topic, "home/lights");
+    assert_eq!(second.topic, "home/speakers");
+    assert_eq!(first.qos, 1);
+    assert_eq!(first.payload["event"], "reminder.fired");
+    assert_eq!(first.payload["title"], "Lights on");
+    assert_eq!(first.payload["body"], "Living room");
+    assert_eq!(first.payload["tags"][0], "home");
+    assert_eq!(first.payload, second.payload);
+}
+
+#[test]
+fn default_topic_is_templated() {
+    let (port, rx) = stand_in_broker();
+    let reminder = reminder(&[]);
+    publish(&mqtt_config(port), &reminder, &Config::default()).unwrap();
+
+    let published = rx.recv_timeout(Duration::from_secs(5)).unwrap();
+    assert_eq!(
+        published.topic,
+        format!("home/reminders/{}", &reminder.id.to_string()[..8])
+    );
+}
+
+#[test]
+fn unreachable_broker_is_an_error() {
+    let port = TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .port();
+    let err = publish(&mqtt_config(port), &reminder(&[]), &Config::default()).unwrap_err();
+    assert!(err.to_string().contains("MQTT connection"), "{:#}", err);
+}
+
+struct Mosquitto(Child);
+
+impl Drop for Mosquitto {
+    fn drop(&mut self) {
+        let _ = self.0.kill();
+    }
+}
+
+#[test]
+fn publishes_to_mosquitto() {
+    let port = TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .port();
+    let Ok(child) = Command::new("mosquitto")
+        .args(["-p", &port.to_string()])
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()
+    else {
+        eprintln!("mosquitto not available, skipping");
+        return;
+    };
+    let _broker = Mosquitto(child);
+    thread::sleep(Duration::from_millis(500));
+
+    let subscriber = Command::new("mosquitto_sub")
+        .args(["-p", &port.to_string(), "-t", "home/lights", "-C", "1"])
+        .stdout(Stdio::piped())
+        .spawn()
+        .expect("mosquitto_sub should come with mosquitto");
+    thread::sleep(Duration::from_millis(500));
+
+    let mut reminder = reminder(&[]);
+    reminder.mqtt_topic = Some("home/lights".to_string());
+    publish(&mqtt_config(port), &reminder, &Config::default()).unwrap();
+
+    let output = subscriber.wait_with_output().unwrap();
+    let output = String::from_utf8(output.stdout).unwrap();
+    let event: serde_json::Value = serde_json::from_str(output.trim()).unwrap();
+    assert_eq!(event["id"], reminder.id.to_string());
+}
@@ -0,0 +1,177 @@
+//! Drives notification actions end to end against a private session bus with
+//! a mock `org.freedesktop.Notifications` server. Skipped when `dbus-daemon`
+//! is not installed.
+#![cfg(all(unix, not(target_os = "macos")))]
+
+mod common;
+
+use common::isolate;
+use reminder_cli::config::Config;
+use reminder_cli::daemon::handle_notification_action;
+use reminder_cli::notification::{send_notification_with_actions, NotificationAction};
+use reminder_cli::reminder::Reminder;
+use reminder_cli::storage::{ReminderStore, Storage};
+use std::collections::{HashMap, HashSet};
+use std::io::{BufRead, BufReader};
+use std::process::{Child, Command, Stdio};
+use std::sync::mpsc::{channel, Sender};
+use std::sync::Mutex;
+use std::time::Duration;
+use zbus::zvariant::OwnedValue;
+
+const PATH: &str = "/org/freedesktop/Notifications";
+const INTERFACE: &str = "org.freedesktop.Notifications";
+
+struct MockServer {
+    shown: Mutex<Sender<(u32, Vec<String>)>>,
+}
+
+#[zbus::interface(name = "org.freedesktop.Notifications")]
+impl MockServer {
+    #[allow(clippy::too_many_arguments)]
+    fn notify(
+        &self,
+        _app_name: String,
+        _replaces_id: u32,
+        _app_icon: String,
+        _summary: String,
+        _body: String,
+        actions: Vec<String>,
+        _hints: HashMap<String, OwnedValue>,
+        _timeout: i32,
+    ) -> u32 {
+        let _ = self.shown.lock().unwrap().send((7, actions));
+        7
+    }
+
+    fn get_capabilities(&self) -> Vec<String> {
+        vec!["actions".to_string(), "body".to_string()]
+    }
+
+    fn get_server_information(&self) -> (String, String, String, String) {
+        (
+            "mock".to_string(),
+            "reminder-cli".to_string(),
+            "0.1".to_string(),
+            "1.2".to_string(),
+        )
+    }
+
+    fn close_notification(&self, _id: u32) {}
+}
+
+struct Bus(Child);
+
+impl Drop for Bus {
+    fn drop(&mut self) {
+        let _ = self.0.kill();
+    }
+}
+
+fn start_bus() -> Option<Bus> {
+    let mut child = Command::new("dbus-daemon")
+        .args(["--session", "--nofork", "--print-address"])
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .ok()?;
+
+    let mut address = String::new();
+    BufReader::new(child.stdout.take()?)
+        .read_line(&mut address)
+        .ok()?;
+    std::env::set_var("DBUS_SESSION_BUS_ADDRESS", address.trim());
+    Some(Bus(child))
+}
+
+#[test]
+fn notification_actions_update_reminder() {
+    isolate();
+    let Some(_bus) = start_bus() else {
+        eprintln!("dbus-daemon not available, skipping");
+        return;
+    };
+
+    let (shown_tx, shown_rx) = channel();
+    let server = zbus::blocking::connection::Builder::session()
+        .unwrap()
+        .name(INTERFACE)
+        .unwrap()
+        .serve_at(
+            PATH,
+            MockServer {
+                shown: Mutex::new(shown_tx),
+            },
+        )
+        .unwrap()
+        .build()
+        .unwrap();
+
+    let dir = tempfile::tempdir().unwrap();
+    let storage = Storage::with_path(dir.path().join("reminders.json"));
+
+    for (action, key) in [
+        (NotificationAction::Snooze, "snooze"),
+        (NotificationAction::Done, "done"),
+    ] {
+        let mut reminder = Reminder::new_one_time(
+            "Stand-up".to_string(),
+            Some("Notes at https://example.com/notes".to_string()),
+            chrono::Local::now(),
+            HashSet::new(),
+        );
+        let id = reminder.id;
+        // What the daemon does after firing a one-time reminder
+        reminder.calculate_next_trigger();
+        storage.add(reminder).unwrap();
+
+        let (done_tx, done_rx) = channel();
+        let action_storage = storage.clone();
+        send_notification_with_actions(
+            &storage.get(id).unwrap().unwrap(),
+            &Config::default(),
+            move |chosen| {
+                handle_notification_action(&action_storage, id, chosen).unwrap();
+                done_tx.send(chosen).unwrap();
+            },
+        )
+        .unwrap();
+
+        let (notification_id, actions) = shown_rx.recv_timeout(Duration::from_secs(5)).unwrap();
+        assert_eq!(
+            actions,
+            ["snooze", "Snooze 10m", "done", "Done", "open", "Open"]
+        );
+
+        // Give the client time to subscribe before "clicking" the button
+        std::thread::sleep(Duration::from_millis(500));
+        server
+            .emit_signal(
+                None::<&str>,
+                PATH,
+                INTERFACE,
+                "ActionInvoked",
+                &(notification_id, key),
+            )
+            .unwrap();
+
+        assert_eq!(
+            done_rx.recv_timeout(Duration::from_secs(5)).unwrap(),
+            action
+        );
+
+        let updated = storage.get(id).unwrap().unwrap();
+        match action {
+            NotificationAction::Snooze => {
+                assert!(!updated.completed);
+                let next = updated.next_trigger.unwrap();
+                let minutes = (next - chrono::Local::now()).num_minutes();
+                assert!((9..=10).contains(&minutes));
+            }
+            _ => {
+                assert!(updated.completed);
+                assert!(updated.next_trigger.is_none());
+            }
+        }
+    }
+}
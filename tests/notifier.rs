@@ -0,0 +1,194 @@
+//! Fan-out of fired reminders through a registry of custom notifiers.
+
+use anyhow::Result;
+use reminder_cli::config::Config;
+use reminder_cli::daemon::fire_due_reminders;
+use reminder_cli::memory::MemoryStore;
+use reminder_cli::notifier::{
+    FileNotifier, Notifier, NotifierRegistry, RecordingNotifier, TerminalNotifier, DESKTOP_CHANNEL,
+};
+use reminder_cli::reminder::Reminder;
+use reminder_cli::retry::DeadLetterQueue;
+use reminder_cli::storage::{ReminderStore, Storage};
+use std::collections::HashSet;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+/// A library user's own channel
+struct Counter(Arc<AtomicUsize>);
+
+impl Notifier for Counter {
+    fn name(&self) -> &str {
+        "counter"
+    }
+
+    fn notify(&self, _reminder: &Reminder, _config: &Config) -> Result<()> {
+        self.0.fetch_add(1, Ordering::SeqCst);
+        Ok(())
+    }
+}
+
+fn due_reminder(title: &str, channels: &[&str]) -> Reminder {
+    let mut reminder = Reminder::new_one_time(
+        title.to_string(),
+        None,
+        chrono::Local::now() - chrono::Duration::minutes(1),
+        HashSet::new(),
+    );
+    reminder.channels = channels.iter().map(|c| c.to_string()).collect();
+    reminder
+}
+
+#[test]
+fn daemon_fans_out_to_every_channel() {
+    let dir = tempfile::tempdir().unwrap();
+    std::env::set_var("XDG_DATA_HOME", dir.path());
+    // Desktop is only used when a session bus is around
+    std::env::set_var("DBUS_SESSION_BUS_ADDRESS", "unix:path=/nonexistent");
+    let storage = Storage::with_path(dir.path().join("reminders.json"));
+    let events = dir.path().join("events.jsonl");
+
+    let desktop = RecordingNotifier::new(DESKTOP_CHANNEL);
+    let count = Arc::new(AtomicUsize::new(0));
+    let mut registry = NotifierRegistry::new();
+    registry.register(desktop.clone());
+    registry.register(FileNotifier::new("file", &events));
+    registry.register(Counter(Arc::clone(&count)));
+
+    storage.add(due_reminder("default", &[])).unwrap();
+    storage
+        .add(due_reminder("fan-out", &["desktop", "file", "counter"]))
+        .unwrap();
+    storage.add(due_reminder("unknown", &["pager"])).unwrap();
+
+    let registry = Arc::new(registry);
+    for handle in fire_due_reminders(&storage, &registry, &Config::default()).unwrap() {
+        handle.join().unwrap();
+    }
+
+    let mut desktop_titles: Vec<String> =
+        desktop.delivered().into_iter().map(|r| r.title).collect();
+    desktop_titles.sort();
+    assert_eq!(desktop_titles, ["default", "fan-out"]);
+    assert_eq!(count.load(Ordering::SeqCst), 1);
+
+    let lines = std::fs::read_to_string(&events).unwrap();
+    let written: Vec<Reminder> = lines
+        .lines()
+        .map(|l| serde_json::from_str(l).unwrap())
+        .collect();
+    assert_eq!(written.len(), 1);
+    assert_eq!(written[0].title, "fan-out");
+
+    // Every fired reminder moves on, even when its channel is unknown; the
+    // undeliverable occurrence is kept in the dead-letter queue
+    assert!(storage.load().unwrap().iter().all(|r| r.completed));
+    let failed = DeadLetterQueue::for_storage(&storage).load().unwrap();
+    assert_eq!(failed.len(), 1);
+    assert_eq!(failed[0].channel, "pager");
+    assert_eq!(failed[0].reminder.title, "unknown");
+}
+
+#[test]
+fn daemon_runs_against_in_memory_store() {
+    let store = MemoryStore::with_reminders(vec![due_reminder("in memory", &["recorder"])]);
+    let recorder = RecordingNotifier::new("recorder");
+    let mut registry = NotifierRegistry::new();
+    registry.register(recorder.clone());
+
+    for handle in fire_due_reminders(&store, &Arc::new(registry), &Config::default()).unwrap() {
+        handle.join().unwrap();
+    }
+
+    assert_eq!(recorder.delivered()[0].title, "in memory");
+    assert!(store.load().unwrap()[0].completed);
+}
+
+#[test]
+fn terminal_channel_writes_to_ttys() {
+    let dir = tempfile::tempdir().unwrap();
+    std::env::set_var("XDG_DATA_HOME", dir.path());
+    let tty = dir.path().join("pts0");
+    std::fs::write(&tty, "").unwrap();
+
+    let mut registry = NotifierRegistry::new();
+    registry.register(TerminalNotifier::with_ttys(vec![tty.clone()]));
+
+    let deliveries = registry.dispatch(
+        &due_reminder("Backup check", &["terminal"]),
+        &Config::default(),
+    );
+    assert!(deliveries[0].result.is_ok());
+
+    let written = std::fs::read_to_string(&tty).unwrap();
+    assert!(written.starts_with('\x07'));
+    assert!(written.contains("⏰ Backup check"));
+}
+
+#[test]
+fn dispatch_reports_each_channel() {
+    let mut registry = NotifierRegistry::new();
+    registry.register(RecordingNotifier::new("ok"));
+    registry.register(RecordingNotifier::failing("broken", "server down"));
+
+    // The default config, not whatever the user's or a sibling test's is
+    let deliveries = registry.dispatch(
+        &due_reminder("x", &["ok", "broken", "missing"]),
+        &Config::default(),
+    );
+
+    let summary: Vec<(String, Option<String>)> = deliveries
+        .into_iter()
+        .map(|d| (d.channel, d.result.err().map(|e| e.to_string())))
+        .collect();
+    assert_eq!(
+        summary,
+        [
+            ("ok".to_string(), None),
+            ("broken".to_string(), Some("server down".to_string())),
+            (
+                "missing".to_string(),
+                Some("Unknown channel: missing".to_string())
+            ),
+        ]
+    );
+}
+
+#[test]
+fn failed_channel_falls_back_along_chain() {
+    let mut registry = NotifierRegistry::new();
+    let primary = RecordingNotifier::failing("primary", "no display");
+    let secondary = RecordingNotifier::failing("secondary", "no tty");
+    let tertiary = RecordingNotifier::new("tertiary");
+    let last = RecordingNotifier::new("last");
+    for notifier in [&primary, &secondary, &tertiary, &last] {
+        registry.register(notifier.clone());
+    }
+
+    let mut config = Config::default();
+    config.delivery.fallback = ["primary", "secondary", "tertiary", "last"]
+        .iter()
+        .map(|c| c.to_string())
+        .collect();
+
+    let deliveries = registry.dispatch(&due_reminder("x", &["primary"]), &config);
+    let summary: Vec<(String, Option<String>, bool)> = deliveries
+        .into_iter()
+        .map(|d| (d.channel, d.fallback_for, d.result.is_ok()))
+        .collect();
+    assert_eq!(
+        summary,
+        [
+            ("primary".to_string(), None, false),
+            ("secondary".to_string(), Some("primary".to_string()), false),
+            ("tertiary".to_string(), Some("primary".to_string()), true),
+        ]
+    );
+    assert!(last.delivered().is_empty());
+
+    // A channel the reminder already uses is not tried again as a fallback
+    let deliveries = registry.dispatch(&due_reminder("y", &["primary", "tertiary"]), &config);
+    assert_eq!(deliveries.len(), 4);
+    assert_eq!(tertiary.delivered().len(), 2);
+    assert_eq!(last.delivered().len(), 1);
+}
@@ -0,0 +1,114 @@
+//! Where the CLI keeps its files: `--data-dir`, `REMINDER_DATA_DIR`, the XDG
+//! layout and moving files left behind by older versions.
+
+mod common;
+
+use common::{cli, stdout};
+use std::fs;
+use std::path::Path;
+
+fn reminder(args: &[&str], env: &[(&str, &Path)]) -> String {
+    stdout(cli().args(args).envs(env.iter().copied()).output().unwrap())
+}
+
+#[test]
+fn data_dir_flag_and_env_select_the_store() {
+    let dir = tempfile::tempdir().unwrap();
+    let (flag_dir, env_dir) = (dir.path().join("flag"), dir.path().join("env"));
+    let home = [("XDG_DATA_HOME", dir.path())];
+
+    let flag = flag_dir.to_str().unwrap();
+    reminder(
+        &["--data-dir", flag, "add", "--title", "Flag", "-T", "1h"],
+        &home,
+    );
+    let mut env = home.to_vec();
+    env.push(("REMINDER_DATA_DIR", &env_dir));
+    reminder(&["add", "--title", "Env", "-T", "1h"], &env);
+
+    assert!(flag_dir.join("reminders.json").exists());
+    assert!(env_dir.join("reminders.json").exists());
+    assert!(!dir.path().join("reminder-cli/reminders.json").exists());
+
+    // The flag is global and wins over the environment
+    let listed = reminder(&["list", "--data-dir", flag], &env);
+    assert!(
+        listed.contains("Flag") && !listed.contains("Env"),
+        "{}",
+        listed
+    );
+}
+
+#[test]
+fn legacy_files_move_to_config_and_runtime_dirs() {
+    let dir = tempfile::tempdir().unwrap();
+    let (data, config, runtime) = (
+        dir.path().join("data"),
+        dir.path().join("config"),
+        dir.path().join("runtime"),
+    );
+    let legacy = data.join("reminder-cli");
+    fs::create_dir_all(&legacy).unwrap();
+    fs::write(legacy.join("config.toml"), "[webhook]\n").unwrap();
+    fs::write(legacy.join("daemon.pid"), "999999").unwrap();
+    fs::write(legacy.join("reminders.json"), "[]").unwrap();
+
+    let env = [
+        ("XDG_DATA_HOME", data.as_path()),
+        ("XDG_CONFIG_HOME", config.as_path()),
+        ("XDG_RUNTIME_DIR", runtime.as_path()),
+    ];
+    reminder(&["list"], &env);
+
+    assert_eq!(
+        fs::read_to_string(config.join("reminder-cli/config.toml")).unwrap(),
+        "[webhook]\n"
+    );
+    assert!(runtime.join("reminder-cli/daemon.pid").exists());
+    assert!(!legacy.join("config.toml").exists());
+    assert!(!legacy.join("daemon.pid").exists());
+    assert!(legacy.join("reminders.json").exists());
+}
+
+#[test]
+fn data_dir_leaves_config_where_xdg_puts_it() {
+    let dir = tempfile::tempdir().unwrap();
+    let (data, config) = (dir.path().join("data"), dir.path().join("config"));
+    fs::create_dir_all(config.join("reminder-cli")).unwrap();
+    fs::write(
+        config.join("reminder-cli/config.toml"),
+        "[time]\ndefault_time = \"18:30\"\n",
+    )
+    .unwrap();
+
+    let env = [
+        ("XDG_DATA_HOME", dir.path()),
+        ("XDG_CONFIG_HOME", config.as_path()),
+        ("XDG_RUNTIME_DIR", dir.path()),
+    ];
+    let (flag, key) = (data.to_str().unwrap(), "time.default_time");
+    let get = ["--data-dir", flag, "config", "get", key];
+    assert_eq!(reminder(&get, &env), "18:30\n");
+    assert!(!data.join("config.toml").exists());
+}
+
+#[test]
+fn each_data_dir_runs_its_own_daemon() {
+    let dir = tempfile::tempdir().unwrap();
+    let env = [
+        ("XDG_DATA_HOME", dir.path()),
+        ("XDG_CONFIG_HOME", dir.path()),
+        ("XDG_RUNTIME_DIR", dir.path()),
+    ];
+    let (home, work) = (dir.path().join("home"), dir.path().join("work"));
+    let daemon = |data: &Path, action: &str| {
+        let flag = data.to_str().unwrap();
+        reminder(&["--data-dir", flag, "daemon", action], &env)
+    };
+
+    assert!(daemon(&home, "start").starts_with("Daemon started"));
+    let started = daemon(&work, "start");
+    daemon(&home, "stop");
+    daemon(&work, "stop");
+    assert!(started.starts_with("Daemon started"), "{}", started);
+}
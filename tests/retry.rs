@@ -0,0 +1,259 @@
+//! Retries with backoff and the dead-letter queue for failed deliveries.
+
+mod common;
+
+use anyhow::{bail, Result};
+use common::isolate;
+use reminder_cli::config::Config;
+use reminder_cli::daemon::run_daemon_until;
+use reminder_cli::notifier::{
+    FailedTargets, Notifier, NotifierRegistry, RecordingNotifier, DESKTOP_CHANNEL, LOG_CHANNEL,
+};
+use reminder_cli::paths;
+use reminder_cli::reminder::Reminder;
+use reminder_cli::retry::{deliver_with_retry, retry_due, retry_failed, DeadLetterQueue};
+use reminder_cli::storage::{ReminderStore, Storage};
+use std::collections::HashSet;
+use std::fs;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Fails until it has been called `failures` times
+struct Flaky {
+    name: &'static str,
+    failures: u32,
+    calls: Arc<AtomicU32>,
+}
+
+impl Notifier for Flaky {
+    fn name(&self) -> &str {
+        self.name
+    }
+
+    fn notify(&self, _reminder: &Reminder, _config: &Config) -> Result<()> {
+        let call = self.calls.fetch_add(1, Ordering::SeqCst) + 1;
+        if call <= self.failures {
+            bail!("attempt {} failed", call);
+        }
+        Ok(())
+    }
+}
+
+/// Delivers to targets "a" and "b", of which "b" fails until it has been
+/// called `failures` times
+struct Targets {
+    failures: u32,
+    calls: Arc<Mutex<Vec<String>>>,
+}
+
+impl Targets {
+    fn send(&self, target: &str) -> Result<()> {
+        let mut calls = self.calls.lock().unwrap();
+        calls.push(target.to_string());
+        let count = calls.iter().filter(|c| *c == target).count() as u32;
+        if target == "b" && count <= self.failures {
+            bail!("b is down");
+        }
+        Ok(())
+    }
+}
+
+impl Notifier for Targets {
+    fn name(&self) -> &str {
+        "targets"
+    }
+
+    fn notify(&self, _reminder: &Reminder, _config: &Config) -> Result<()> {
+        let failed: Vec<(String, String)> = ["a", "b"]
+            .iter()
+            .filter_map(|t| Some((t.to_string(), format!("{:#}", self.send(t).err()?))))
+            .collect();
+        if failed.is_empty() {
+            return Ok(());
+        }
+        Err(FailedTargets {
+            kind: "target",
+            failed,
+            total: 2,
+        }
+        .into())
+    }
+
+    fn notify_target(&self, _reminder: &Reminder, target: &str, _config: &Config) -> Result<()> {
+        self.send(target)
+    }
+}
+
+fn setup(failures: u32) -> (NotifierRegistry, Arc<AtomicU32>, Config, Reminder) {
+    let calls = Arc::new(AtomicU32::new(0));
+    let mut registry = NotifierRegistry::new();
+    registry.register(Flaky {
+        name: "flaky",
+        failures,
+        calls: Arc::clone(&calls),
+    });
+    registry.register(RecordingNotifier::new("ok"));
+
+    let mut config = Config::default();
+    config.retry.retries = 3;
+    config.retry.delay_ms = 20;
+    config.retry.max_delay_ms = 50;
+
+    let mut reminder = Reminder::new_one_time(
+        "Pay rent".to_string(),
+        None,
+        chrono::Local::now(),
+        HashSet::new(),
+    );
+    reminder.channels = vec!["flaky".to_string(), "ok".to_string()];
+
+    (registry, calls, config, reminder)
+}
+
+/// Run the queued retries that are due until none are left
+fn retry_until_settled(registry: &Arc<NotifierRegistry>, config: &Config, queue: &DeadLetterQueue) {
+    while let Some(next) = queue.next_attempt().unwrap() {
+        if let Ok(wait) = (next - chrono::Local::now()).to_std() {
+            thread::sleep(wait);
+        }
+        for handle in retry_due(registry, config, queue).unwrap() {
+            handle.join().unwrap();
+        }
+    }
+}
+
+#[test]
+fn transient_failure_is_retried_with_backoff() {
+    isolate();
+    let dir = tempfile::tempdir().unwrap();
+    let queue = DeadLetterQueue::new(dir.path().join("failed.json"));
+    let (registry, calls, config, reminder) = setup(2);
+
+    let started = Instant::now();
+    deliver_with_retry(&registry, &reminder, &config, &queue);
+    assert_eq!(calls.load(Ordering::SeqCst), 1);
+    let entries = queue.load().unwrap();
+    assert_eq!(entries.len(), 1);
+    assert_eq!(entries[0].attempts, 1);
+    assert!(entries[0].next_attempt.is_some());
+
+    retry_until_settled(&Arc::new(registry), &config, &queue);
+    assert_eq!(calls.load(Ordering::SeqCst), 3);
+    // 20ms, then 40ms before the successful third attempt
+    assert!(started.elapsed().as_millis() >= 60);
+    assert!(queue.load().unwrap().is_empty());
+}
+
+#[test]
+fn exhausted_delivery_lands_in_queue_and_can_be_retried() {
+    isolate();
+    let dir = tempfile::tempdir().unwrap();
+    let queue = DeadLetterQueue::new(dir.path().join("failed.json"));
+    let (registry, calls, config, reminder) = setup(5);
+
+    let registry = Arc::new(registry);
+    deliver_with_retry(&registry, &reminder, &config, &queue);
+    retry_until_settled(&registry, &config, &queue);
+    assert_eq!(calls.load(Ordering::SeqCst), 4);
+
+    let entries = queue.load().unwrap();
+    assert_eq!(entries.len(), 1);
+    assert_eq!(entries[0].channel, "flaky");
+    assert_eq!(entries[0].attempts, 4);
+    assert_eq!(entries[0].error, "attempt 4 failed");
+    assert_eq!(entries[0].reminder.id, reminder.id);
+    assert_eq!(entries[0].next_attempt, None);
+
+    // Fifth call still fails: the entry stays with one more attempt
+    assert!(!retry_failed(&registry, &config, &queue, entries[0].clone()).unwrap());
+    let entry = queue.find(&entries[0].short_id()).unwrap().unwrap();
+    assert_eq!(entry.attempts, 5);
+
+    // Sixth call succeeds and clears it
+    assert!(retry_failed(&registry, &config, &queue, entry).unwrap());
+    assert!(queue.load().unwrap().is_empty());
+}
+
+#[test]
+fn daemon_retries_queued_deliveries() {
+    isolate();
+    let dir = tempfile::tempdir().unwrap();
+    let storage = Storage::with_path(dir.path().join("reminders.json"));
+    let (registry, calls, _, reminder) = setup(2);
+    fs::write(
+        paths::config_file().unwrap(),
+        "[retry]\nretries = 3\ndelay_ms = 20\nmax_delay_ms = 50\n",
+    )
+    .unwrap();
+    storage.add(reminder).unwrap();
+
+    run_daemon_until(
+        &storage,
+        registry,
+        Some(Instant::now() + Duration::from_secs(2)),
+    )
+    .unwrap();
+    assert_eq!(calls.load(Ordering::SeqCst), 3);
+    assert!(DeadLetterQueue::for_storage(&storage)
+        .load()
+        .unwrap()
+        .is_empty());
+}
+
+#[test]
+fn retries_only_the_failed_target() {
+    isolate();
+    let dir = tempfile::tempdir().unwrap();
+    let queue = DeadLetterQueue::new(dir.path().join("failed.json"));
+    let (mut registry, _, config, mut reminder) = setup(0);
+    let calls = Arc::new(Mutex::new(Vec::new()));
+    registry.register(Targets {
+        failures: 2,
+        calls: Arc::clone(&calls),
+    });
+    reminder.channels = vec!["targets".to_string()];
+
+    deliver_with_retry(&registry, &reminder, &config, &queue);
+    let entries = queue.load().unwrap();
+    assert_eq!(entries.len(), 1);
+    assert_eq!(entries[0].target.as_deref(), Some("b"));
+    assert_eq!(entries[0].error, "b is down");
+
+    retry_until_settled(&Arc::new(registry), &config, &queue);
+    assert_eq!(*calls.lock().unwrap(), ["a", "b", "b", "b"]);
+    assert!(queue.load().unwrap().is_empty());
+}
+
+#[test]
+fn channel_covered_by_a_fallback_is_still_retried() {
+    isolate();
+    // Desktop is only used when a session bus is around
+    std::env::set_var("DBUS_SESSION_BUS_ADDRESS", "unix:path=/nonexistent");
+    let dir = tempfile::tempdir().unwrap();
+    let queue = DeadLetterQueue::new(dir.path().join("failed.json"));
+    let (mut registry, _, mut config, mut reminder) = setup(0);
+    let calls = Arc::new(AtomicU32::new(0));
+    registry.register(Flaky {
+        name: DESKTOP_CHANNEL,
+        failures: 1,
+        calls: Arc::clone(&calls),
+    });
+    let log = RecordingNotifier::new(LOG_CHANNEL);
+    registry.register(log.clone());
+    config.delivery.fallback = vec![DESKTOP_CHANNEL.to_string(), LOG_CHANNEL.to_string()];
+    reminder.channels = vec![DESKTOP_CHANNEL.to_string()];
+
+    deliver_with_retry(&registry, &reminder, &config, &queue);
+    assert_eq!(log.delivered().len(), 1);
+    let entries = queue.load().unwrap();
+    assert_eq!(entries.len(), 1);
+    assert_eq!(entries[0].channel, DESKTOP_CHANNEL);
+
+    retry_until_settled(&Arc::new(registry), &config, &queue);
+    assert_eq!(calls.load(Ordering::SeqCst), 2);
+    // The retry goes to the desktop alone, not through the fallback again
+    assert_eq!(log.delivered().len(), 1);
+    assert!(queue.load().unwrap().is_empty());
+}
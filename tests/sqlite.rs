@@ -0,0 +1,121 @@
+//! The SQLite backend behind `Storage`, and migrating a JSON store to it.
+
+mod common;
+
+use common::reminder_in;
+use reminder_cli::config::Config;
+use reminder_cli::daemon::fire_due_reminders;
+use reminder_cli::notifier::{NotifierRegistry, RecordingNotifier};
+use reminder_cli::reminder::Reminder;
+use reminder_cli::storage::{Backend, ReminderStore, Storage};
+use std::sync::Arc;
+
+fn reminder(title: &str, minutes: i64, tags: &[&str]) -> Reminder {
+    let mut reminder = reminder_in(title, chrono::Duration::minutes(minutes));
+    reminder.tags = tags.iter().map(|t| t.to_string()).collect();
+    reminder.channels = vec!["recorder".to_string()];
+    reminder
+}
+
+#[test]
+fn migrates_json_store_to_sqlite() {
+    let dir = tempfile::tempdir().unwrap();
+    let json = Storage::with_path(dir.path().join("reminders.json"));
+    let standup = reminder("Stand-up", 30, &["work"]);
+    json.add(standup.clone()).unwrap();
+    json.add(reminder("Groceries", 60, &["home"])).unwrap();
+
+    let (sqlite, count) = json.migrate(Backend::Sqlite).unwrap();
+    assert_eq!(count, 2);
+    assert_eq!(sqlite.backend(), Backend::Sqlite);
+    assert_eq!(sqlite.path(), dir.path().join("reminders.db"));
+    assert!(dir.path().join("reminders.json.migrated").exists());
+    assert!(!dir.path().join("reminders.json").exists());
+
+    let loaded = sqlite
+        .find_by_short_id(&standup.id.to_string()[..8])
+        .unwrap();
+    assert_eq!(loaded.unwrap().title, "Stand-up");
+    assert_eq!(sqlite.get_all_tags().unwrap(), ["home", "work"]);
+    assert!(json.migrate(Backend::Sqlite).is_err());
+
+    sqlite.update(standup.id, Box::new(|r| r.pause())).unwrap();
+    let (back, count) = sqlite.migrate(Backend::Json).unwrap();
+    assert_eq!(count, 2);
+    assert!(back.get(standup.id).unwrap().unwrap().paused);
+}
+
+#[test]
+fn daemon_fires_and_advances_only_due_reminders() {
+    let dir = tempfile::tempdir().unwrap();
+    std::env::set_var("XDG_DATA_HOME", dir.path());
+    let storage = Storage::with_path(dir.path().join("reminders.db"));
+    for i in 0..200 {
+        storage
+            .add(reminder(&format!("later {}", i), 60, &[]))
+            .unwrap();
+    }
+    let due = reminder("due", -1, &[]);
+    storage.add(due.clone()).unwrap();
+
+    let recorder = RecordingNotifier::new("recorder");
+    let mut registry = NotifierRegistry::new();
+    registry.register(recorder.clone());
+
+    for handle in fire_due_reminders(&storage, &Arc::new(registry), &Config::default()).unwrap() {
+        handle.join().unwrap();
+    }
+
+    let delivered: Vec<_> = recorder.delivered().iter().map(|r| r.id).collect();
+    assert_eq!(delivered, [due.id]);
+    let fired = storage.get(due.id).unwrap().unwrap();
+    assert!(fired.completed);
+    assert_eq!(fired.occurrences, 1);
+    assert_eq!(storage.load().unwrap().len(), 201);
+    assert!(storage.due_reminders().unwrap().is_empty());
+}
+
+#[test]
+fn keeps_names_unique_without_loading_every_row() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("reminders.db");
+
+    // Rows written straight to the database are numbered by the next change
+    let old = reminder("old", 60, &[]);
+    Backend::Sqlite.open(&path).add(old.clone()).unwrap();
+    let storage = Storage::with_path(&path);
+    let mut named = reminder("named", 60, &[]);
+    named.alias = Some("standup".to_string());
+    storage.add(named.clone()).unwrap();
+    assert_eq!(storage.get(old.id).unwrap().unwrap().number, 1);
+    assert_eq!(storage.get(named.id).unwrap().unwrap().number, 2);
+
+    // New numbers follow the highest left, and taken names are refused
+    storage.delete(old.id).unwrap();
+    let mut clash = reminder("clash", 60, &[]);
+    clash.alias = Some("standup".to_string());
+    assert!(storage.add(clash.clone()).is_err());
+    clash.alias = None;
+    storage.add(clash.clone()).unwrap();
+    let clash = storage.get(clash.id).unwrap().unwrap();
+    assert_eq!(clash.number, 3);
+    assert!(storage
+        .update(
+            clash.id,
+            Box::new(|r| r.alias = Some("standup".to_string()))
+        )
+        .is_err());
+
+    // Edits bump the revision of the row they change only
+    let before = storage.get(named.id).unwrap().unwrap().revision;
+    storage.update(clash.id, Box::new(|r| r.pause())).unwrap();
+    assert_eq!(storage.get(named.id).unwrap().unwrap().revision, before);
+    assert_eq!(
+        storage.get(clash.id).unwrap().unwrap().revision,
+        clash.revision + 1
+    );
+    assert_eq!(
+        storage.find_by_short_id("standup").unwrap().unwrap().id,
+        named.id
+    );
+}
@@ -0,0 +1,161 @@
+//! Crash-safe saves of the JSON store, its rotating backups and recovery
+//! from a corrupted file.
+
+mod common;
+
+use common::{cli_in, reminder};
+use reminder_cli::reminder::Reminder;
+use reminder_cli::storage::{JsonStore, ReminderStore, Storage, BACKUP_COUNT};
+use std::fs;
+
+fn titles(reminders: &[Reminder]) -> Vec<&str> {
+    reminders.iter().map(|r| r.title.as_str()).collect()
+}
+
+#[test]
+fn saves_keep_rotating_backups_of_good_versions() {
+    let dir = tempfile::tempdir().unwrap();
+    let store = JsonStore::new(dir.path().join("reminders.json"));
+
+    for i in 0..BACKUP_COUNT + 3 {
+        store.add(reminder(&format!("r{}", i))).unwrap();
+    }
+
+    let backups = store.backup_paths();
+    assert!(backups.iter().all(|b| b.exists()));
+    // The newest backup is the version before the last save
+    let newest: Vec<Reminder> =
+        serde_json::from_str(&fs::read_to_string(&backups[0]).unwrap()).unwrap();
+    assert_eq!(newest.len(), BACKUP_COUNT + 2);
+
+    // No temp files are left behind
+    let leftovers: Vec<_> = fs::read_dir(dir.path())
+        .unwrap()
+        .map(|e| e.unwrap().file_name().into_string().unwrap())
+        .filter(|name| name.contains(".tmp"))
+        .collect();
+    assert!(leftovers.is_empty(), "{:?}", leftovers);
+
+    // A corrupted file is never copied over a good backup
+    fs::write(dir.path().join("reminders.json"), "[{\"trunc").unwrap();
+    store.save(&[reminder("fresh")]).unwrap();
+    let newest: Vec<Reminder> =
+        serde_json::from_str(&fs::read_to_string(&backups[0]).unwrap()).unwrap();
+    assert_eq!(newest.len(), BACKUP_COUNT + 2);
+}
+
+#[test]
+fn recovers_corrupted_file_from_latest_valid_backup() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("reminders.json");
+    let storage = Storage::with_path(&path);
+
+    storage.add(reminder("first")).unwrap();
+    storage.add(reminder("second")).unwrap();
+    storage.add(reminder("third")).unwrap();
+    assert!(storage.recover().is_err(), "valid file needs no recovery");
+
+    // Crash mid-write of the file and of the newest backup
+    fs::write(&path, "[{\"title\": \"thi").unwrap();
+    fs::write(dir.path().join("reminders.json.bak1"), "[{").unwrap();
+
+    let err = storage.load().unwrap_err();
+    assert!(
+        format!("{:#}", err).contains("storage recover"),
+        "{:#}",
+        err
+    );
+
+    let recovery = storage.recover().unwrap();
+    assert_eq!(recovery.backup, dir.path().join("reminders.json.bak2"));
+    assert_eq!(recovery.count, 1);
+    assert_eq!(titles(&storage.load().unwrap()), ["first"]);
+
+    let corrupted = recovery.corrupted.unwrap();
+    assert_eq!(fs::read_to_string(corrupted).unwrap(), "[{\"title\": \"thi");
+}
+
+#[test]
+fn cached_reminders_follow_changes_on_disk() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("reminders.json");
+    let reader = JsonStore::new(&path);
+    let writer = JsonStore::new(&path);
+    writer.add(reminder("first")).unwrap();
+
+    // Unchanged file: the same index is handed out again
+    let index = reader.index().unwrap();
+    assert!(std::sync::Arc::ptr_eq(&index, &reader.index().unwrap()));
+
+    let second = reminder("second");
+    let short = second.id.to_string()[..8].to_string();
+    writer.add(second).unwrap();
+    let found = reader.find_by_short_id(&short).unwrap().unwrap();
+    assert_eq!(found.title, "second");
+    assert_eq!(titles(&reader.load().unwrap()), ["first", "second"]);
+}
+
+/// Each writer increments a counter on the same reminder; a lost update
+/// would leave the total short
+fn hammer(path: std::path::PathBuf) {
+    const WRITERS: usize = 8;
+    const INCREMENTS: u32 = 25;
+
+    let target = reminder("counter");
+    Storage::with_path(&path).add(target.clone()).unwrap();
+
+    std::thread::scope(|scope| {
+        for w in 0..WRITERS {
+            let path = path.clone();
+            scope.spawn(move || {
+                // Separate handles, as separate processes would have
+                let storage = Storage::with_path(path);
+                for i in 0..INCREMENTS {
+                    storage
+                        .update(target.id, Box::new(|r| r.occurrences += 1))
+                        .unwrap();
+                    if i % 5 == 0 {
+                        storage.add(reminder(&format!("w{}-{}", w, i))).unwrap();
+                    }
+                }
+            });
+        }
+    });
+
+    let storage = Storage::with_path(&path);
+    let counter = storage.get(target.id).unwrap().unwrap();
+    assert_eq!(counter.occurrences, WRITERS as u32 * INCREMENTS);
+    assert_eq!(storage.load().unwrap().len(), 1 + WRITERS * 5);
+}
+
+#[test]
+fn concurrent_json_writers_lose_no_updates() {
+    let dir = tempfile::tempdir().unwrap();
+    hammer(dir.path().join("reminders.json"));
+}
+
+#[test]
+fn concurrent_sqlite_writers_lose_no_updates() {
+    let dir = tempfile::tempdir().unwrap();
+    hammer(dir.path().join("reminders.db"));
+}
+
+#[test]
+fn concurrent_cli_processes_lose_no_reminders() {
+    let dir = tempfile::tempdir().unwrap();
+    let children: Vec<_> = (0..8)
+        .map(|i| {
+            cli_in(dir.path())
+                .args(["add", "--title", &format!("process {}", i), "-T", "1h"])
+                .stdout(std::process::Stdio::null())
+                .spawn()
+                .unwrap()
+        })
+        .collect();
+    for mut child in children {
+        assert!(child.wait().unwrap().success());
+    }
+
+    let storage = Storage::with_path(dir.path().join("reminders.json"));
+    assert_eq!(storage.load().unwrap().len(), 8);
+}
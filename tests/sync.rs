@@ -0,0 +1,102 @@
+//! Keeping the data directory in git: a commit per change and `reminder
+//! sync` through a bare repository, merging concurrent edits per field.
+
+mod common;
+
+use common::{cli_in, stdout};
+use reminder_cli::storage::{ReminderStore, Storage};
+use std::path::Path;
+use std::process::Command;
+
+fn reminder(dir: &Path, args: &[&str]) -> String {
+    stdout(cli_in(dir).args(args).output().unwrap())
+}
+
+fn git(dir: &Path, args: &[&str]) -> String {
+    let output = Command::new("git")
+        .arg("-C")
+        .arg(dir)
+        .args(args)
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    String::from_utf8(output.stdout).unwrap()
+}
+
+fn load(dir: &Path) -> Vec<reminder_cli::reminder::Reminder> {
+    Storage::with_path(dir.join("reminders.json"))
+        .load()
+        .unwrap()
+}
+
+#[test]
+fn syncs_and_merges_concurrent_edits() {
+    let tmp = tempfile::tempdir().unwrap();
+    let remote = tmp.path().join("remote.git");
+    let (a, b) = (tmp.path().join("a"), tmp.path().join("b"));
+    git(
+        tmp.path(),
+        &["init", "--quiet", "--bare", remote.to_str().unwrap()],
+    );
+    let remote = remote.to_str().unwrap();
+
+    reminder(&a, &["add", "--title", "Stand-up", "-T", "1h"]);
+    reminder(&a, &["sync", "init", "--remote", remote]);
+    reminder(&a, &["sync"]);
+    reminder(&b, &["add", "--title", "Dentist", "-T", "2h"]);
+    reminder(&b, &["sync", "init", "--remote", remote]);
+    reminder(&b, &["sync"]);
+    reminder(&a, &["sync"]);
+    assert_eq!(load(&a).len(), 2);
+
+    let standup = load(&a)
+        .into_iter()
+        .find(|r| r.title == "Stand-up")
+        .unwrap();
+    let dentist = load(&a).into_iter().find(|r| r.title == "Dentist").unwrap();
+    let (standup_id, dentist_id) = (&standup.id.to_string()[..8], &dentist.id.to_string()[..8]);
+
+    // Different fields of the same reminder on each side, and a delete
+    reminder(
+        &a,
+        &["edit", "--id", standup_id, "--title", "Daily stand-up"],
+    );
+    reminder(&b, &["edit", "--id", standup_id, "-D", "Room 4"]);
+    reminder(&b, &["delete", "--id", dentist_id]);
+    assert!(
+        git(&a, &["log", "-1", "--format=%s"]).starts_with("Update reminder \"Daily stand-up\"")
+    );
+    assert!(git(&b, &["log", "-1", "--format=%s"]).starts_with("Delete reminder \"Dentist\""));
+
+    reminder(&a, &["sync"]);
+    reminder(&b, &["sync"]);
+    reminder(&a, &["sync"]);
+
+    for dir in [&a, &b] {
+        let reminders = load(dir);
+        assert_eq!(reminders.len(), 1);
+        assert_eq!(reminders[0].title, "Daily stand-up");
+        assert_eq!(reminders[0].description.as_deref(), Some("Room 4"));
+        assert!(git(dir, &["status", "--porcelain"]).is_empty());
+    }
+}
+
+#[test]
+fn merge_driver_leaves_data_dir_alone() {
+    let tmp = tempfile::tempdir().unwrap();
+    let data = tmp.path().join("data");
+    reminder(&data, &["add", "--title", "Base", "-T", "1h"]);
+    let base = tmp.path().join("base.json");
+    std::fs::copy(data.join("reminders.json"), &base).unwrap();
+    std::fs::remove_dir_all(&data).unwrap();
+
+    stdout(
+        cli_in(&data)
+            .args(["sync", "merge-driver"])
+            .args([&base, &base, &base])
+            .output()
+            .unwrap(),
+    );
+    assert_eq!(Storage::with_path(&base).load().unwrap().len(), 1);
+    assert!(!data.exists());
+}
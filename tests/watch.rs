@@ -0,0 +1,52 @@
+//! The daemon reacts to changes to the store as soon as they are saved.
+
+mod common;
+
+use common::{isolate, reminder_in};
+use reminder_cli::daemon::run_daemon_until;
+use reminder_cli::notifier::{NotifierRegistry, RecordingNotifier};
+use reminder_cli::reminder::Reminder;
+use reminder_cli::storage::{ReminderStore, Storage};
+use std::thread;
+use std::time::{Duration, Instant};
+
+fn reminder(title: &str, in_ms: i64) -> Reminder {
+    let mut reminder = reminder_in(title, chrono::Duration::milliseconds(in_ms));
+    reminder.channels = vec!["recorder".to_string()];
+    reminder
+}
+
+#[test]
+fn daemon_picks_up_cli_edits_immediately() {
+    isolate();
+    let dir = tempfile::tempdir().unwrap();
+    let storage = Storage::with_path(dir.path().join("reminders.json"));
+    storage.add(reminder("Later", 3_600_000)).unwrap();
+
+    let recorder = RecordingNotifier::new("recorder");
+    let mut registry = NotifierRegistry::new();
+    registry.register(recorder.clone());
+
+    let started = Instant::now();
+    let daemon = {
+        let storage = storage.clone();
+        thread::spawn(move || {
+            run_daemon_until(&storage, registry, Some(started + Duration::from_secs(4))).unwrap()
+        })
+    };
+    thread::sleep(Duration::from_millis(300));
+
+    // Well inside the 10 second poll interval
+    let added = Instant::now();
+    storage.add(reminder("Soon", 500)).unwrap();
+    while recorder.delivered().is_empty() && added.elapsed() < Duration::from_secs(3) {
+        thread::sleep(Duration::from_millis(20));
+    }
+    let delivered: Vec<String> = recorder.delivered().into_iter().map(|r| r.title).collect();
+    assert_eq!(delivered, ["Soon"]);
+    assert!(added.elapsed() < Duration::from_secs(2));
+
+    daemon.join().unwrap();
+    let fired = storage.load().unwrap();
+    assert!(fired.iter().any(|r| r.title == "Soon" && r.completed));
+}
@@ -0,0 +1,159 @@
+//! Webhook delivery against a local HTTP stand-in server.
+
+use reminder_cli::config::Config;
+use reminder_cli::reminder::Reminder;
+use reminder_cli::webhook::{deliver, WebhookConfig};
+use std::collections::HashSet;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::TcpListener;
+use std::sync::mpsc::{channel, Receiver};
+use std::thread;
+use std::time::Duration;
+
+struct Request {
+    method: String,
+    headers: Vec<(String, String)>,
+    body: String,
+}
+
+impl Request {
+    fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(n, _)| n.eq_ignore_ascii_case(name))
+            .map(|(_, v)| v.as_str())
+    }
+}
+
+/// Answer each incoming request with the next status code, reporting what
+/// was received
+fn serve(statuses: Vec<u16>) -> (String, Receiver<Request>) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}/hook", listener.local_addr().unwrap());
+    let (tx, rx) = channel();
+
+    thread::spawn(move || {
+        for status in statuses {
+            let (stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+
+            let mut line = String::new();
+            reader.read_line(&mut line).unwrap();
+            let method = line.split_whitespace().next().unwrap().to_string();
+
+            let mut headers = Vec::new();
+            loop {
+                let mut line = String::new();
+                reader.read_line(&mut line).unwrap();
+                let line = line.trim_end();
+                if line.is_empty() {
+                    break;
+                }
+                let (name, value) = line.split_once(':').unwrap();
+                headers.push((name.trim().to_string(), value.trim().to_string()));
+            }
+
+            let length: usize = headers
+                .iter()
+                .find(|(n, _)| n.eq_ignore_ascii_case("content-length"))
+                .map(|(_, v)| v.parse().unwrap())
+                .unwrap_or(0);
+            let mut body = vec![0; length];
+            reader.read_exact(&mut body).unwrap();
+
+            let mut stream = stream;
+            write!(
+                stream,
+                "HTTP/1.1 {} X\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+                status
+            )
+            .unwrap();
+
+            let _ = tx.send(Request {
+                method,
+                headers,
+                body: String::from_utf8(body).unwrap(),
+            });
+        }
+    });
+
+    (url, rx)
+}
+
+fn reminder() -> Reminder {
+    let tags: HashSet<String> = ["team".to_string()].into_iter().collect();
+    Reminder::new_one_time(
+        "Retro".to_string(),
+        Some("Bring notes".to_string()),
+        chrono::Local::now(),
+        tags,
+    )
+}
+
+#[test]
+fn posts_default_payload() {
+    let (url, rx) = serve(vec![200]);
+    let webhook = WebhookConfig {
+        url,
+        ..Default::default()
+    };
+
+    deliver(&webhook, &reminder(), &Config::default()).unwrap();
+
+    let request = rx.recv().unwrap();
+    assert_eq!(request.method, "POST");
+    assert_eq!(request.header("content-type"), Some("application/json"));
+    let payload: serde_json::Value = serde_json::from_str(&request.body).unwrap();
+    assert_eq!(payload["title"], "Retro");
+    assert_eq!(payload["description"], "Bring notes");
+    assert_eq!(payload["tags"], serde_json::json!(["team"]));
+}
+
+#[test]
+fn sends_template_and_headers() {
+    let (url, rx) = serve(vec![204]);
+    let webhook = WebhookConfig {
+        url,
+        method: "put".to_string(),
+        headers: [("Authorization".to_string(), "Bearer t0ken".to_string())].into(),
+        template: Some(r#"{"text": "{title} ({tags})"}"#.to_string()),
+        ..Default::default()
+    };
+
+    deliver(&webhook, &reminder(), &Config::default()).unwrap();
+
+    let request = rx.recv().unwrap();
+    assert_eq!(request.method, "PUT");
+    assert_eq!(request.header("authorization"), Some("Bearer t0ken"));
+    assert_eq!(request.body, r#"{"text": "Retro (team)"}"#);
+}
+
+#[test]
+fn fails_without_retrying() {
+    // Retries are left to the failed delivery queue
+    let (url, rx) = serve(vec![500, 200]);
+    let webhook = WebhookConfig {
+        url,
+        ..Default::default()
+    };
+
+    let err = deliver(&webhook, &reminder(), &Config::default()).unwrap_err();
+    assert!(format!("{:#}", err).contains("HTTP 500"));
+    rx.recv().unwrap();
+    assert!(rx.recv_timeout(Duration::from_millis(300)).is_err());
+}
+
+#[test]
+fn times_out_on_unresponsive_server() {
+    // Accepts connections but never answers
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let webhook = WebhookConfig {
+        url: format!("http://{}/hook", listener.local_addr().unwrap()),
+        timeout_secs: 1,
+        ..Default::default()
+    };
+
+    let start = std::time::Instant::now();
+    assert!(deliver(&webhook, &reminder(), &Config::default()).is_err());
+    assert!(start.elapsed() < Duration::from_secs(5));
+}